@@ -0,0 +1,125 @@
+//! Reading the input files of ZIP archives, their paths are the ones of the
+//! archives joined with the entries names, like `export.zip/contacts.csv`.
+
+use std::{
+  fs::File,
+  io::{self, Read, Seek, SeekFrom},
+  path::{Path, PathBuf},
+};
+
+use failure::{bail, Error, ResultExt};
+use glob::Pattern;
+use zip::{CompressionMethod, ZipArchive};
+
+/// Whether the `path` is a ZIP archive, by its extension.
+pub fn is_archive(path: &Path) -> bool {
+  path.is_file()
+    && path
+      .extension()
+      .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
+}
+
+/// The paths of the entries of the archive at `path` matching the
+/// `pattern`, in the archive order.
+pub fn entries(path: &Path, pattern: &Pattern) -> Result<Vec<PathBuf>, Error> {
+  let file = File::open(path)
+    .with_context(|_| format!("could not open archive {:?}", path))?;
+  let archive = ZipArchive::new(file)
+    .with_context(|_| format!("invalid archive {:?}", path))?;
+  let entries: Vec<_> = archive
+    .file_names()
+    .filter(|name| !name.ends_with('/') && pattern.matches(name))
+    .map(|name| path.join(name))
+    .collect();
+  if entries.is_empty() {
+    bail!("no entry of {:?} matches `{}`", path, pattern);
+  }
+  Ok(entries)
+}
+
+/// Splits the `path` of an archive entry into the archive and entry paths.
+pub fn split(path: &Path) -> Option<(&Path, &Path)> {
+  path
+    .ancestors()
+    .skip(1)
+    .find(|ancestor| is_archive(ancestor))
+    .and_then(|archive| Some((archive, path.strip_prefix(archive).ok()?)))
+}
+
+/// Opens the `entry` of the `archive`, with its compressed size.
+///
+/// The stored and deflated entries are streamed, the other ones are read
+/// into memory first.
+pub fn open(
+  archive: &Path,
+  entry: &Path,
+) -> Result<(Box<dyn Read>, u64), Error> {
+  let name = entry.to_string_lossy().replace('\\', "/");
+  let mut zip = ZipArchive::new(File::open(archive)?)?;
+  let index = match zip.index_for_name(&name) {
+    Some(index) => index,
+    None => bail!("{:?} has no `{}` entry", archive, name),
+  };
+  let (method, start, len) = {
+    let raw = zip.by_index_raw(index)?;
+    (raw.compression(), raw.data_start(), raw.compressed_size())
+  };
+  let streamed = |start: u64| -> io::Result<io::Take<File>> {
+    let mut file = File::open(archive)?;
+    file.seek(SeekFrom::Start(start))?;
+    Ok(file.take(len))
+  };
+  let reader: Box<dyn Read> = match (method, start) {
+    (CompressionMethod::Stored, Some(start)) => Box::new(streamed(start)?),
+    (CompressionMethod::Deflated, Some(start)) => {
+      Box::new(flate2::read::DeflateDecoder::new(streamed(start)?))
+    },
+    _ => {
+      let mut data = Vec::new();
+      zip.by_index(index)?.read_to_end(&mut data)?;
+      Box::new(io::Cursor::new(data))
+    },
+  };
+  Ok((reader, len))
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{env, fs, io::Write};
+
+  use zip::{write::SimpleFileOptions, ZipWriter};
+
+  use super::*;
+
+  #[test]
+  fn should_read_archive_entries() {
+    let path = env::temp_dir().join("mobcsv-archive.zip");
+    let mut zip = ZipWriter::new(fs::File::create(&path).unwrap());
+    for (name, method) in &[
+      ("a.csv", CompressionMethod::Stored),
+      ("dir/b.csv", CompressionMethod::Deflated),
+      ("c.txt", CompressionMethod::Stored),
+    ] {
+      let options = SimpleFileOptions::default().compression_method(*method);
+      zip.start_file(*name, options).unwrap();
+      zip.write_all(b"ph,name,count\n").unwrap();
+    }
+    zip.finish().unwrap();
+    let pattern = Pattern::new("*.csv").unwrap();
+    let found = entries(&path, &pattern).unwrap();
+    assert_eq!(found, [path.join("a.csv"), path.join("dir/b.csv")]);
+    for entry in &found {
+      let (archive, name) = split(entry).unwrap();
+      assert_eq!(archive, path);
+      let mut data = String::new();
+      open(archive, name)
+        .unwrap()
+        .0
+        .read_to_string(&mut data)
+        .unwrap();
+      assert_eq!(data, "ph,name,count\n");
+    }
+    assert!(split(&env::temp_dir().join("a.csv")).is_none());
+    assert!(entries(&path, &Pattern::new("*.jsonl").unwrap()).is_err());
+  }
+}
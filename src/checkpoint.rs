@@ -0,0 +1,75 @@
+//! The checkpoints of the long runs, for them to be resumed after being
+//! interrupted.
+
+use std::{fs, io, path::Path};
+
+use failure::{Error, ResultExt};
+use serde::{Deserialize, Serialize};
+
+use crate::pipeline::Position;
+
+/// The records handled between two checkpoints.
+pub const INTERVAL: u64 = 10_000;
+
+/// The state of a run at its last checkpoint.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+  pub position: Position,
+  /// The records written to the output.
+  pub written: u64,
+  /// The bytes of the output, and of the rejects file if any, holding the
+  /// rows written up to the position.
+  pub output_len: u64,
+  pub rejects_len: Option<u64>,
+}
+
+impl Checkpoint {
+  /// Loads the checkpoint at `path`, if there is one.
+  pub fn load(path: &Path) -> Result<Option<Self>, Error> {
+    let data = match fs::read(path) {
+      Ok(data) => data,
+      Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+      Err(e) => {
+        return Err(e)
+          .with_context(|_| format!("could not read checkpoint {:?}", path))?
+      },
+    };
+    let checkpoint = serde_json::from_slice(&data)
+      .with_context(|_| format!("invalid checkpoint {:?}", path))?;
+    Ok(Some(checkpoint))
+  }
+
+  /// Saves the checkpoint to `path`, replacing the previous one at once,
+  /// for an interruption not to leave half of it.
+  pub fn save(&self, path: &Path) -> Result<(), Error> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, serde_json::to_vec(self)?)
+      .with_context(|_| format!("could not write checkpoint {:?}", tmp))?;
+    fs::rename(&tmp, path)
+      .with_context(|_| format!("could not write checkpoint {:?}", path))?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn should_save_and_load_checkpoints() {
+    let path = std::env::temp_dir().join("mobcsv-checkpoint.json");
+    let _ = fs::remove_file(&path);
+    assert_eq!(Checkpoint::load(&path).unwrap(), None);
+    let checkpoint = Checkpoint {
+      position: Position {
+        input: 1,
+        records: 20_000,
+      },
+      written: 19_000,
+      output_len: 420_000,
+      rejects_len: None,
+    };
+    checkpoint.save(&path).unwrap();
+    assert_eq!(Checkpoint::load(&path).unwrap(), Some(checkpoint));
+  }
+}
@@ -0,0 +1,262 @@
+//! Cleaning the raw phone numbers before validating them.
+
+use std::{char, sync::OnceLock};
+
+use regex::Regex;
+
+use crate::countries;
+
+/// Cleans the raw phone numbers.
+#[derive(Debug, Clone, Default)]
+pub struct Cleaner {
+  /// Matches the characters to remove, instead of the default ones, see
+  /// [`is_replaced`].
+  pub replacer: Option<Regex>,
+  /// Only remove what the `replacer` matches, and keep any other character
+  /// to fail the validation later.
+  pub strict: bool,
+}
+
+impl Cleaner {
+  /// Cleans a raw phone number, so we can then validate it.
+  pub fn clean(&self, ph: &str) -> String {
+    let ph = ph.trim();
+    let re = match &self.replacer {
+      Some(re) => re,
+      // a single scan of the characters, the hot path of every record.
+      None => {
+        let mut out = String::with_capacity(ph.len());
+        for c in ph.chars().map(|c| ascii_digit(c).unwrap_or(c)) {
+          let keep = if self.strict {
+            !is_replaced(c)
+          } else {
+            c.is_ascii_digit()
+          };
+          if keep {
+            out.push(c);
+          }
+        }
+        return if self.strict { out.trim().into() } else { out };
+      },
+    };
+    let mut ph = transliterate_digits(ph);
+    if !self.strict {
+      ph = strip_non_digits(&ph);
+    }
+    // we need to remove all spacial characters to empty one.
+    re.replace_all(&ph, "").trim().into()
+  }
+
+  /// Deterministically repairs the common typos of a raw phone number, then
+  /// cleans it.
+  ///
+  /// The letter O is replaced with zero, lowercase l and uppercase I with
+  /// one, and a country dialing code written twice, like `2020...`, is
+  /// written only once.
+  pub fn fix_typos(&self, ph: &str) -> String {
+    let ph: String = ph
+      .chars()
+      .map(|c| match c {
+        'O' | 'o' => '0',
+        'l' | 'I' => '1',
+        c => c,
+      })
+      .collect();
+    let mut ph = self.clean(&ph);
+    let start = if ph.starts_with("00") { 2 } else { 0 };
+    let mut codes = countries::all().iter().map(|c| c.dialing_code.as_str());
+    let doubled = codes.find(|code| {
+      ph[start..].starts_with(code)
+        && ph[start + code.len()..].starts_with(code)
+    });
+    if let Some(code) = doubled {
+      ph.replace_range(start..start + code.len(), "");
+    }
+    ph
+  }
+}
+
+/// The phone numbers looking parts of a free `text`, digits with single
+/// separators between them, like `+20 111-661-3061`.
+pub fn candidates(text: &str) -> impl Iterator<Item = &str> {
+  static CANDIDATE: OnceLock<Regex> = OnceLock::new();
+  let re = CANDIDATE.get_or_init(|| {
+    Regex::new(r"\+?\(?\d(?:[\s\-.()]?\d){7,14}").expect("a valid regex")
+  });
+  re.find_iter(text).map(|m| m.as_str())
+}
+
+/// Whether the character is removed by default, one of `!@+#$%-^&*()` or a
+/// space.
+pub fn is_replaced(c: char) -> bool {
+  matches!(
+    c,
+    '!' | '@' | '+' | '#' | '$' | '%' | '-' | '^' | '&' | '*' | '(' | ')' | ' '
+  )
+}
+
+/// Removes every codepoint but the ASCII digits and `+`, that includes the
+/// invisible ones like zero-width spaces, RTL/LTR marks and non-breaking
+/// spaces.
+pub fn strip_non_digits(ph: &str) -> String {
+  ph.chars()
+    .filter(|c| c.is_ascii_digit() || *c == '+')
+    .collect()
+}
+
+/// Whether the raw phone number has characters looking like digits, the
+/// letters O, I and l, their Cyrillic and Greek lookalikes, fullwidth digits,
+/// or digits of several scripts, like Arabic-Indic ones among ASCII ones.
+pub fn has_confusables(ph: &str) -> bool {
+  let mut scripts = None;
+  for c in ph.chars() {
+    if lookalike_digit(c).is_some() || ('\u{FF10}'..='\u{FF19}').contains(&c) {
+      return true;
+    }
+    // the zero of the script of the digit.
+    let script = match ascii_digit(c).or(Some(c).filter(char::is_ascii_digit)) {
+      Some(digit) => c as u32 - digit.to_digit(10).unwrap_or_default(),
+      None => continue,
+    };
+    match scripts {
+      Some(other) if other != script => return true,
+      _ => scripts = Some(script),
+    }
+  }
+  false
+}
+
+/// Converts the letters looking like digits to ASCII digits, like the
+/// letter O to zero.
+pub fn convert_confusables(ph: &str) -> String {
+  ph.chars()
+    .map(|c| lookalike_digit(c).unwrap_or(c))
+    .collect()
+}
+
+fn lookalike_digit(c: char) -> Option<char> {
+  match c {
+    // Latin, Cyrillic and Greek O
+    'O' | 'o' | '\u{041E}' | '\u{043E}' | '\u{039F}' | '\u{03BF}' => Some('0'),
+    // Latin I and l, Cyrillic I
+    'I' | 'l' | '\u{0406}' => Some('1'),
+    _ => None,
+  }
+}
+
+/// Converts the Eastern Arabic, Persian and fullwidth digits to ASCII ones.
+pub fn transliterate_digits(ph: &str) -> String {
+  ph.chars().map(|c| ascii_digit(c).unwrap_or(c)).collect()
+}
+
+fn ascii_digit(c: char) -> Option<char> {
+  let zero = match c {
+    // Arabic-Indic digits
+    '\u{0660}'..='\u{0669}' => 0x0660,
+    // Extended Arabic-Indic (Persian and Urdu) digits
+    '\u{06F0}'..='\u{06F9}' => 0x06F0,
+    // Fullwidth digits
+    '\u{FF10}'..='\u{FF19}' => 0xFF10,
+    _ => return None,
+  };
+  char::from_digit(c as u32 - zero, 10)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn should_find_candidates() {
+    let text = "call 0111-661-3061 or +966 54 002 9129, not 2019";
+    assert_eq!(
+      candidates(text).collect::<Vec<_>>(),
+      ["0111-661-3061", "+966 54 002 9129"]
+    );
+  }
+
+  #[test]
+  fn should_detect_confusables() {
+    assert!(has_confusables("0111O613061"));
+    assert!(has_confusables("٠١١١6613061"));
+    assert!(has_confusables("０１１１６６１３０６１"));
+    assert!(!has_confusables("٠١١١ ٦٦١ ٣٠٦١"));
+    assert!(!has_confusables("+20 111-661-3061"));
+    assert_eq!(convert_confusables("0lll6613O61"), "01116613061");
+  }
+
+  #[test]
+  fn should_transliterate_digits() {
+    assert_eq!(transliterate_digits("٠١٢٣٤٥٦٧٨٩"), "0123456789");
+    assert_eq!(transliterate_digits("۰۱۲۳۴۵۶۷۸۹"), "0123456789");
+    assert_eq!(transliterate_digits("０１２３４５６７８９"), "0123456789");
+    assert_eq!(transliterate_digits("+20 abc"), "+20 abc");
+  }
+
+  fn strict() -> Cleaner {
+    Cleaner {
+      strict: true,
+      ..Cleaner::default()
+    }
+  }
+
+  #[test]
+  fn should_clean_ph() {
+    let cleaner = Cleaner::default();
+    assert_eq!(cleaner.clean(" +2(0111)6613061 "), "201116613061");
+    assert_eq!(cleaner.clean("٠١١١ ٦٦١ ٣٠٦١"), "01116613061");
+    assert_eq!(cleaner.clean("+۹۶۶-۵۴۰۰۲۹۱۲۹"), "966540029129");
+  }
+
+  #[test]
+  fn should_strip_invisible_chars() {
+    let ph = "\u{200F}+20\u{00A0}111\u{200B}661\u{202A}3061\u{FEFF}";
+    assert_eq!(Cleaner::default().clean(ph), "201116613061");
+    assert_ne!(strict().clean(ph), "201116613061");
+    assert_eq!(strict().clean("+20 111-661-3061"), "201116613061");
+  }
+
+  #[test]
+  fn should_match_the_default_replacer_regex() {
+    let replacer = Some(Regex::new(r#"[!@+#$%\-^&*() ]"#).unwrap());
+    for &strict in &[false, true] {
+      let fast = Cleaner {
+        replacer: None,
+        strict,
+      };
+      let slow = Cleaner {
+        replacer: replacer.clone(),
+        strict,
+      };
+      for ph in &[
+        " +2(0111)6613061 ",
+        "+۹۶۶-۵۴۰۰۲۹۱۲۹",
+        "\u{200F}+20\u{00A0}111\u{200B}661\t3061\u{FEFF}",
+        "20#111$661%3061^&*!@ x",
+        "",
+      ] {
+        assert_eq!(fast.clean(ph), slow.clean(ph), "{:?}", ph);
+      }
+    }
+  }
+
+  #[test]
+  fn should_use_custom_replacer() {
+    let cleaner = Cleaner {
+      replacer: Some(Regex::new(r"[./]").unwrap()),
+      strict: true,
+    };
+    assert_eq!(cleaner.clean("2011.1661/3061"), "201116613061");
+    assert_eq!(cleaner.clean("+20 111 661 3061"), "+20 111 661 3061");
+  }
+
+  #[test]
+  fn should_fix_typos() {
+    let cleaner = Cleaner::default();
+    assert_eq!(cleaner.fix_typos("O111661306l"), "01116613061");
+    assert_eq!(cleaner.fix_typos("o1I1 66l 3O6l"), "01116613061");
+    assert_eq!(cleaner.fix_typos("+2020 111 661 3061"), "201116613061");
+    assert_eq!(cleaner.fix_typos("00966966540029129"), "00966540029129");
+    assert_eq!(cleaner.fix_typos("201116613061"), "201116613061");
+  }
+}
@@ -0,0 +1,196 @@
+//! `mobcsv bench`, measuring every stage of the pipeline on synthetic
+//! records.
+
+use std::{
+  alloc::{GlobalAlloc, Layout, System},
+  fmt::Write as _,
+  sync::atomic::{AtomicU64, Ordering},
+  time::{Duration, Instant},
+};
+
+use failure::Error;
+use structopt::StructOpt;
+
+use super::Options;
+use crate::{output, schema::Schema, Accepted, Config, Record};
+
+/// The system allocator, counting the allocations.
+struct Counting;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+  unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+    ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    System.alloc(layout)
+  }
+
+  unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+    System.dealloc(ptr, layout)
+  }
+
+  unsafe fn realloc(
+    &self,
+    ptr: *mut u8,
+    layout: Layout,
+    new_size: usize,
+  ) -> *mut u8 {
+    ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    System.realloc(ptr, layout, new_size)
+  }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+#[derive(Debug, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct Args {
+  /// The synthetic records to generate
+  #[structopt(long, default_value = "1000000")]
+  pub rows: usize,
+  #[structopt(flatten)]
+  pub options: Options,
+}
+
+/// The measures of a stage of the pipeline.
+struct Stage {
+  name: &'static str,
+  elapsed: Duration,
+  /// The bytes read, or written by the `write` stage.
+  bytes: usize,
+  allocations: u64,
+}
+
+/// Runs `f`, measuring it as the `name` stage.
+fn measure<T>(
+  name: &'static str,
+  bytes: usize,
+  f: impl FnOnce() -> Result<T, Error>,
+) -> Result<(T, Stage), Error> {
+  let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+  let started = Instant::now();
+  let value = f()?;
+  let stage = Stage {
+    name,
+    elapsed: started.elapsed(),
+    bytes,
+    allocations: ALLOCATIONS.load(Ordering::Relaxed) - allocations,
+  };
+  Ok((value, stage))
+}
+
+/// A CSV file of `rows` records, numbers written in several ways, some of
+/// them invalid, always the same ones for the same `rows`.
+fn generate(rows: usize) -> Vec<u8> {
+  let mut data = String::with_capacity(rows * 32);
+  data.push_str("ph,name,count\n");
+  // a xorshift generator, good enough for the numbers to vary.
+  let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+  for i in 0..rows {
+    state ^= state << 13;
+    state ^= state >> 7;
+    state ^= state << 17;
+    let operator = [0, 1, 2, 5][(state % 4) as usize];
+    let subscriber = state % 100_000_000;
+    let _ = match i % 5 {
+      0 => write!(data, "+20 1{} {:08}", operator, subscriber),
+      1 => write!(data, "01{}{:08}", operator, subscriber),
+      2 => write!(data, "00201{}-{:08}", operator, subscriber),
+      3 => write!(data, "(01{}) {:08}", operator, subscriber),
+      _ => write!(data, "{}", subscriber % 100_000),
+    };
+    let _ = writeln!(data, ",name {},{}", i, i % 9 + 1);
+  }
+  data.into_bytes()
+}
+
+pub fn run(args: &Args) -> Result<(), Error> {
+  let config = Config::from_options(&args.options)?;
+  let data = generate(args.rows);
+  let len = data.len();
+  println!("Generated {} records, {} bytes", args.rows, len);
+  let (records, parse) = measure("parse", len, || {
+    let mut rdr = csv::Reader::from_reader(&data[..]);
+    let schema = Schema::new(rdr.headers()?, &args.options)?;
+    let mut raw = csv::ByteRecord::new();
+    let mut records = Vec::with_capacity(args.rows);
+    while rdr.read_byte_record(&mut raw)? {
+      records.push(schema.record(&raw)?);
+    }
+    Ok(records)
+  })?;
+  let (cleaned, clean) = measure("clean", len, || {
+    let cleaned: Vec<_> = records
+      .into_iter()
+      .map(|record| {
+        let ph = config.cleaner.clean(&record.ph);
+        (ph, record)
+      })
+      .collect();
+    Ok(cleaned)
+  })?;
+  let (accepted, validate) = measure("validate", len, || {
+    let mut accepted = Vec::with_capacity(cleaned.len());
+    for (ph, record) in cleaned {
+      let normalized = config.validator.normalize(&ph);
+      if let Ok(ph) = normalized.and_then(|ph| {
+        config.check_filters(&ph)?;
+        Ok(ph)
+      }) {
+        accepted.push(Accepted {
+          original_ph: record.ph.clone(),
+          record: Record { ph, ..record },
+          fixed: false,
+          extracted: false,
+        });
+      }
+    }
+    Ok(accepted)
+  })?;
+  let mut out = csv::Writer::from_writer(Vec::with_capacity(len));
+  let (_, mut write) = measure("write", 0, || {
+    for accepted in &accepted {
+      output::write_record(&mut out, &config, accepted)?;
+    }
+    Ok(out.flush()?)
+  })?;
+  write.bytes = out.get_ref().len();
+  println!("Accepted {} records", accepted.len());
+  println!(
+    "{:<10} {:>12} {:>10} {:>14}",
+    "stage", "rows/s", "MB/s", "allocations"
+  );
+  for stage in &[parse, clean, validate, write] {
+    let secs = stage.elapsed.as_secs_f64().max(f64::EPSILON);
+    let rows = if stage.name == "write" {
+      accepted.len()
+    } else {
+      args.rows
+    };
+    println!(
+      "{:<10} {:>12.0} {:>10.1} {:>14}",
+      stage.name,
+      rows as f64 / secs,
+      stage.bytes as f64 / secs / 1e6,
+      stage.allocations
+    );
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn should_generate_records() {
+    let data = generate(10);
+    assert_eq!(data, generate(10));
+    let mut rdr = csv::Reader::from_reader(&data[..]);
+    assert_eq!(rdr.headers().unwrap(), vec!["ph", "name", "count"]);
+    let rows: Vec<_> = rdr.records().map(Result::unwrap).collect();
+    assert_eq!(rows.len(), 10);
+    assert_eq!(&rows[9][1], "name 9");
+  }
+}
@@ -0,0 +1,841 @@
+//! `mobcsv clean`, the records cleaned and validated into a new CSV file.
+
+use std::{
+  cell::Cell,
+  fs, io,
+  path::{Path, PathBuf},
+  slice,
+  time::Instant,
+};
+
+use failure::{bail, Error, ResultExt};
+use indicatif::HumanDuration;
+use log::info;
+use structopt::StructOpt;
+
+use super::{validate, Options, OutputArgs};
+use crate::{
+  checkpoint::{self, Checkpoint},
+  compress::Compression,
+  dedupe::{Dedupe, Keep},
+  encoding::OutputEncoding,
+  format::Format,
+  join::Join,
+  lists,
+  output::{self, RejectsFormat},
+  pipeline::{self, Inputs, Writer},
+  qr,
+  reject::RejectError,
+  review::{self, Review},
+  s3,
+  sample::Sample,
+  sink::{LineEnding, OutputFormat},
+  sort::{Sorter, Top},
+  source::InputFormat,
+  split::{Chunker, Splitter, Target},
+  state::StateDb,
+  stats::Stats,
+  Accepted, Config,
+};
+
+#[derive(Debug, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct Args {
+  #[structopt(flatten)]
+  pub output: OutputArgs,
+  /// Clean every `.csv` file of this directory tree, in place of the input
+  /// paths
+  #[structopt(long, parse(from_os_str), raw(requires = r#""output-dir""#))]
+  pub input_dir: Option<PathBuf>,
+  /// Write the cleaned files of `--input-dir` to this directory, mirroring
+  /// its structure
+  #[structopt(long, parse(from_os_str), raw(requires = r#""input-dir""#))]
+  pub output_dir: Option<PathBuf>,
+  /// Rewrite the single input file with its cleaned records, keeping the
+  /// original one as `<input>.bak`, replaced with `--force` only
+  #[structopt(
+    long,
+    raw(conflicts_with_all = r#"&["output-path", "input-dir", "check"]"#)
+  )]
+  pub in_place: bool,
+  #[structopt(flatten)]
+  pub options: Options,
+  /// The input CSV files paths or glob patterns (e.g. `exports/*.csv`), `-`
+  /// reads the standard input
+  #[structopt(parse(from_os_str), raw(required_unless = r#""input-dir""#))]
+  pub input_paths: Vec<PathBuf>,
+}
+
+/// Cleans the records, without duplicate phone numbers when a `keep`
+/// strategy is given, even without `--dedupe`.
+pub fn run(args: &Args, keep: Option<Keep>) -> Result<(), Error> {
+  match (&args.input_dir, &args.output_dir) {
+    (Some(input_dir), Some(output_dir)) => {
+      write_dir(args, input_dir, output_dir, keep)
+    },
+    _ if args.in_place => in_place(args, keep),
+    _ => write(&args.output, &args.options, &args.input_paths, keep),
+  }
+}
+
+/// Writes the accepted records of all the `inputs` to the output, or only
+/// validates them with `--check`.
+pub fn write(
+  output: &OutputArgs,
+  options: &Options,
+  inputs: &[PathBuf],
+  keep: Option<Keep>,
+) -> Result<(), Error> {
+  let output_path = match &output.output_path {
+    Some(path) if !output.check => path,
+    _ => return validate::check(options, inputs, &output.reports),
+  };
+  let mut config = config(output, options)?;
+  write_to(output, output_path, options, &mut config, inputs, keep)
+}
+
+/// Rewrites the single input file of the `args` with its cleaned records,
+/// backing it up first.
+fn in_place(args: &Args, keep: Option<Keep>) -> Result<(), Error> {
+  let (output, options) = (&args.output, &args.options);
+  let path = match args.input_paths.as_slice() {
+    [path] if path.is_file() => path,
+    _ => bail!("--in-place needs a single input file"),
+  };
+  let mut config = Config {
+    overwrite: true,
+    ..config(output, options)?
+  };
+  if output.dry_run {
+    let inputs = &args.input_paths;
+    return write_to(output, path, options, &mut config, inputs, keep);
+  }
+  // the backup is read, the input may be truncated with `--no-atomic`.
+  let mut backup = path.as_os_str().to_owned();
+  backup.push(".bak");
+  let backup = PathBuf::from(backup);
+  if backup.exists() && !output.force {
+    bail!(
+      "the backup {:?} already exists, use --force to overwrite it",
+      backup
+    );
+  }
+  // read in the format of the input, not by the `.bak` extension.
+  let mut options = options.clone();
+  let format = options
+    .input_format
+    .unwrap_or_else(|| InputFormat::of(path));
+  options.input_format = Some(format);
+  fs::copy(path, &backup).with_context(|_| {
+    format!("could not back up {:?} to {:?}", path, backup)
+  })?;
+  info!("Backed up {:?} to {:?}", path, backup);
+  write_to(
+    output,
+    path,
+    &options,
+    &mut config,
+    slice::from_ref(&backup),
+    keep,
+  )
+}
+
+/// Writes the accepted records of the `inputs` to `output_path`, printing
+/// the summary of the run.
+fn write_to(
+  output: &OutputArgs,
+  output_path: &Path,
+  options: &Options,
+  config: &mut Config,
+  inputs: &[PathBuf],
+  keep: Option<Keep>,
+) -> Result<(), Error> {
+  let started = Instant::now();
+  let mut stats = output.stats(keep);
+  let written = write_records(
+    output,
+    output_path,
+    options,
+    config,
+    inputs,
+    keep,
+    &mut stats,
+  )?;
+  // keep the standard output clean when the records are written to it.
+  let say = |msg: String| {
+    if options.quiet {
+      return;
+    }
+    if pipeline::is_stdio(output_path) {
+      eprintln!("{}", msg);
+    } else {
+      println!("{}", msg);
+    }
+  };
+  options.summarize(&stats, say);
+  if output.dry_run {
+    say(format!(
+      "Dry run, would write {} records to {:?}",
+      written, output_path
+    ));
+  }
+  say(format!(
+    "Done in {} [{}ms]",
+    HumanDuration(started.elapsed()),
+    started.elapsed().as_millis()
+  ));
+  let mut files: Vec<_> = inputs.iter().map(PathBuf::as_path).collect();
+  files.push(output_path);
+  files.extend(output.rejects_path.as_deref());
+  finish(output, options, &stats, &files)
+}
+
+/// Cleans every CSV file of the `input_dir` tree into the same path under
+/// the `output_dir`.
+fn write_dir(
+  args: &Args,
+  input_dir: &Path,
+  output_dir: &Path,
+  keep: Option<Keep>,
+) -> Result<(), Error> {
+  let (output, options) = (&args.output, &args.options);
+  if output.rejects_path.is_some() {
+    bail!("--rejects-path can't be used with --input-dir");
+  }
+  if output.checkpoint.is_some() {
+    bail!("--checkpoint can't be used with --input-dir");
+  }
+  let mut inputs = Vec::new();
+  find_csv_files(input_dir, &mut inputs)?;
+  inputs.sort();
+  if output.check {
+    return validate::check(options, &inputs, &output.reports);
+  }
+  let started = Instant::now();
+  let mut config = config(output, options)?;
+  let mut total = output.stats(keep);
+  let say = |msg: String| {
+    if !options.quiet {
+      println!("{}", msg);
+    }
+  };
+  let mut outputs = Vec::new();
+  for input in &inputs {
+    let output_path = output_dir.join(input.strip_prefix(input_dir)?);
+    if let Some(parent) = output_path.parent() {
+      if !output.dry_run {
+        fs::create_dir_all(parent).with_context(|_| {
+          format!("could not create output directory {:?}", parent)
+        })?;
+      }
+    }
+    let mut stats = output.stats(keep);
+    let written = write_records(
+      output,
+      &output_path,
+      options,
+      &mut config,
+      slice::from_ref(input),
+      keep,
+      &mut stats,
+    )?;
+    say(format!(
+      "{} -> {}: read {}, accepted {}, rejected {}, written {}",
+      input.display(),
+      output_path.display(),
+      stats.total,
+      stats.accepted,
+      stats.rejected(),
+      written
+    ));
+    total.merge(stats);
+    outputs.push(output_path);
+  }
+  say(format!("Cleaned {} files", inputs.len()));
+  options.summarize(&total, say);
+  let files: Vec<_> = inputs
+    .iter()
+    .chain(&outputs)
+    .map(PathBuf::as_path)
+    .collect();
+  finish(output, options, &total, &files)?;
+  say(format!(
+    "Done in {} [{}ms]",
+    HumanDuration(started.elapsed()),
+    started.elapsed().as_millis()
+  ));
+  Ok(())
+}
+
+/// The config of the writing subcommands.
+fn config(output: &OutputArgs, options: &Options) -> Result<Config, Error> {
+  // the contacts numbers are always in E.164.
+  let format = if output.output_format.is_contacts() {
+    Format::E164
+  } else {
+    output.format
+  };
+  let mut config = Config {
+    format,
+    output_delimiter: output.output_delimiter,
+    output_encoding: if output.bom {
+      OutputEncoding::Utf8Bom
+    } else {
+      output.output_encoding
+    },
+    crlf: output.line_ending == LineEnding::Crlf,
+    output_format: output.output_format,
+    table: output.table.clone(),
+    sql_dialect: output.sql_dialect,
+    compression: output.compress,
+    atomic: !output.no_atomic,
+    overwrite: output.force,
+    columns: output.columns(options),
+    ..Config::from_options(options)?
+  };
+  // the joined numbers are normalized the same way as the records.
+  if let Some(path) = &output.join {
+    let join =
+      Join::load(path, &output.join_key, &output.join_columns, &config)?;
+    config.join = Some(join);
+  }
+  Ok(config)
+}
+
+/// Writes the accepted records of the `inputs` to `output_path`, unless it
+/// is a dry run, and returns the count of the written records.
+fn write_records(
+  output: &OutputArgs,
+  output_path: &Path,
+  options: &Options,
+  config: &mut Config,
+  inputs: &[PathBuf],
+  keep: Option<Keep>,
+  stats: &mut Stats,
+) -> Result<u64, Error> {
+  let dry_run = output.dry_run;
+  let stdin = inputs.iter().any(|path| pipeline::is_stdio(path));
+  if output.interactive && stdin {
+    bail!("--interactive can't read the records from the standard input");
+  }
+  let mut inputs = Inputs::open(inputs, options)?;
+  if output.line_ending == LineEnding::Preserve {
+    config.crlf = inputs.crlf();
+  }
+  let config = &*config;
+  let passed = inputs.extra_headers().to_vec();
+  let keep = keep.or_else(|| output.keep());
+  let checkpoint_path = output.checkpoint.as_deref().filter(|_| !dry_run);
+  let resumed = match checkpoint_path {
+    Some(path) => {
+      check_checkpoint(output, output_path, options, config, keep)?;
+      Checkpoint::load(path)?
+    },
+    None => None,
+  };
+  if let Some(state) = &resumed {
+    info!(
+      "Resuming after the {} records written before the checkpoint {:?}, \
+       the summary only counts the records read from now on",
+      state.written,
+      checkpoint_path.unwrap()
+    );
+    inputs.resume(state.position);
+  }
+  if output.interactive
+    || output.patch_file.is_some()
+    || output.apply_suggestions
+  {
+    let patch = output.patch_file.as_deref().filter(|_| !dry_run);
+    let review = Review::open(output.interactive, patch)?;
+    inputs.review(review.apply_suggestions(output.apply_suggestions));
+  }
+  // loaded before the output is created, it may be the same file.
+  let existing = match &output.dedupe_against {
+    Some(path) => Some(lists::load(path, config)?),
+    None => None,
+  };
+  let mut state = match &output.state_db {
+    Some(path) => Some(StateDb::open(path, dry_run)?),
+    None => None,
+  };
+  let mut previously_seen = 0;
+  let headers = output::headers(config, &passed);
+  if output.append && (output.split_by.is_some() || output.chunk_size.is_some())
+  {
+    bail!("--append can't be used with --split-by or --chunk-size");
+  }
+  let mut wrt = match (&resumed, output.split_by) {
+    _ if dry_run => None,
+    (_, Some(by)) => {
+      let splitter = Splitter::new(by, output_path, &headers)?;
+      Some(Target::Split(splitter))
+    },
+    (_, None) if output.chunk_size.is_some() => {
+      let size = output.chunk_size.unwrap();
+      let chunker = Chunker::new(size, output_path, &headers)?;
+      Some(Target::Chunks(chunker))
+    },
+    (Some(state), None) => {
+      info!("Appending to {:?}", output_path);
+      let wrt =
+        pipeline::append(output_path, config, &headers, state.output_len)?;
+      Some(Target::File(wrt))
+    },
+    (None, None) if output.append => {
+      info!("Appending to {:?}", output_path);
+      let wrt = pipeline::extend(output_path, config, &headers)?;
+      Some(Target::File(wrt))
+    },
+    (None, None) => {
+      info!("Trying to write to {:?}", output_path);
+      let wrt = pipeline::create(output_path, config, &headers)?;
+      Some(Target::File(wrt))
+    },
+  };
+  let (rejects_format, reject_headers) = match output.rejects_format {
+    Some(RejectsFormat::Jsonl) => {
+      (OutputFormat::Jsonl, output::REJECT_FIELDS.to_vec())
+    },
+    Some(RejectsFormat::Csv) => {
+      (OutputFormat::Csv, output::reject_headers(&passed))
+    },
+    None => (config.output_format, output::reject_headers(&passed)),
+  };
+  let mut rejects_wrt = match &output.rejects_path {
+    Some(path) if !dry_run => {
+      info!("Writing the rejected records to {:?}", path);
+      let headers = &reject_headers;
+      match resumed.as_ref().and_then(|state| state.rejects_len) {
+        Some(len) => Some(pipeline::append_as(
+          path,
+          config,
+          rejects_format,
+          headers,
+          len,
+        )?),
+        None => {
+          Some(pipeline::create_as(path, config, rejects_format, headers)?)
+        },
+      }
+    },
+    _ => None,
+  };
+  let mut dedupe = match (keep, output.dedupe_bloom) {
+    (None, _) => None,
+    (Some(Keep::First), Some(items)) => Some(Dedupe::with_bloom(items)),
+    (Some(_), Some(_)) => {
+      bail!("--dedupe-bloom only works with --dedupe-keep first")
+    },
+    (Some(keep), None) => Some(Dedupe::new(keep)),
+  };
+  let qr_dir = output.qr_dir.as_deref().filter(|_| !dry_run);
+  if let Some(dir) = qr_dir {
+    info!("Writing the QR codes to {:?}", dir);
+    fs::create_dir_all(dir).with_context(|_| {
+      format!("could not create QR codes directory {:?}", dir)
+    })?;
+  }
+  let written = Cell::new(resumed.map_or(0, |state| state.written));
+  let write = |wrt: &mut Option<Target>,
+               state: &mut Option<StateDb>,
+               accepted: &Accepted| {
+    written.set(written.get() + 1);
+    if let Some(wrt) = wrt {
+      let wrt = wrt.writer(config, &accepted.record.ph)?;
+      output::write_record(wrt.as_mut(), config, accepted)?;
+    }
+    if let Some(state) = state {
+      state.add(&accepted.record.ph);
+    }
+    if let Some(dir) = qr_dir {
+      qr::write(dir, output.qr_uri, &accepted.record.ph)?;
+    }
+    Ok::<_, Error>(())
+  };
+  // the records are held until the end to be ranked or sorted.
+  let mut held = Held {
+    sample: match (output.sample, output.sample_rate) {
+      (Some(size), _) => Some(Sample::size(size, output.seed)),
+      (None, Some(rate)) => Some(Sample::rate(rate, output.seed)),
+      (None, None) => None,
+    },
+    top: output.top.map(|n| Top::new(n, output.by)),
+    sorter: if output.sort.is_empty() {
+      None
+    } else {
+      let limit = output.sort_memory_limit * 1024 * 1024;
+      Some(Sorter::new(output.sort.clone(), limit))
+    },
+  };
+  let position = inputs.position();
+  let mut handled = 0;
+  inputs.process(config, stats, |result| {
+    match result {
+      Ok(accepted) => {
+        let ph = &accepted.record.ph;
+        let seen = match &state {
+          Some(state) => state.contains(ph)?,
+          None => false,
+        };
+        if seen || existing.as_ref().is_some_and(|seen| seen.contains(ph)) {
+          previously_seen += 1;
+        } else {
+          let ready = match &mut dedupe {
+            Some(dedupe) => dedupe.push(accepted),
+            None => Some(accepted),
+          };
+          if let Some(accepted) = ready {
+            if let Some(accepted) = held.push(accepted)? {
+              write(&mut wrt, &mut state, &accepted)?;
+            }
+          }
+        }
+      },
+      Err(rejected) => {
+        if let Some(wrt) = &mut rejects_wrt {
+          let suggestion = if rejected.reason.is_invalid_phone() {
+            review::suggest(config, &rejected, 1).pop()
+          } else {
+            None
+          };
+          let suggestion = suggestion.as_deref();
+          match output.rejects_format {
+            Some(RejectsFormat::Jsonl) => {
+              output::write_rejected_json(wrt.as_mut(), &rejected, suggestion)?
+            },
+            _ => output::write_rejected(wrt.as_mut(), &rejected, suggestion)?,
+          }
+        }
+      },
+    }
+    handled += 1;
+    if let Some(path) = checkpoint_path {
+      if handled % checkpoint::INTERVAL == 0 {
+        let len = |wrt: Option<&mut Writer>, path: &Path| -> Result<_, Error> {
+          match wrt {
+            Some(wrt) => {
+              wrt.flush()?;
+              let path = pipeline::partial_path(path, config);
+              Ok(Some(fs::metadata(path)?.len()))
+            },
+            None => Ok(None),
+          }
+        };
+        let rejects_len = match &output.rejects_path {
+          Some(rejects_path) => len(rejects_wrt.as_mut(), rejects_path)?,
+          None => None,
+        };
+        let wrt = match &mut wrt {
+          Some(Target::File(wrt)) => Some(wrt),
+          _ => None,
+        };
+        let state = Checkpoint {
+          position: position.get(),
+          written: written.get(),
+          output_len: len(wrt, output_path)?.unwrap_or_default(),
+          rejects_len,
+        };
+        state.save(path)?;
+      }
+    }
+    Ok(())
+  })?;
+  stats.previously_seen += previously_seen;
+  for accepted in dedupe.map(Dedupe::finish).unwrap_or_default() {
+    if let Some(accepted) = held.push(accepted)? {
+      write(&mut wrt, &mut state, &accepted)?;
+    }
+  }
+  held.finish(|accepted| write(&mut wrt, &mut state, &accepted))?;
+  if let Some(wrt) = &mut wrt {
+    wrt.finish()?;
+  }
+  if let Some(wrt) = &mut rejects_wrt {
+    wrt.finish()?;
+  }
+  // the numbers are only remembered once they are in the complete outputs.
+  if let Some(state) = state.filter(|_| !dry_run) {
+    let added = state.commit()?;
+    info!("Added {} numbers to the state database", added);
+  }
+  // the run is complete, the next one starts over.
+  if let Some(path) = checkpoint_path {
+    match fs::remove_file(path) {
+      Err(e) if e.kind() != io::ErrorKind::NotFound => {
+        return Err(e).with_context(|_| {
+          format!("could not remove checkpoint {:?}", path)
+        })?;
+      },
+      _ => {},
+    }
+  }
+  Ok(written.get())
+}
+
+/// The final stages of the accepted records, holding them until the end,
+/// applied in order.
+struct Held {
+  sample: Option<Sample>,
+  top: Option<Top>,
+  sorter: Option<Sorter>,
+}
+
+impl Held {
+  /// Returns the record if it is to be written right away.
+  fn push(&mut self, accepted: Accepted) -> Result<Option<Accepted>, Error> {
+    let accepted = match &mut self.sample {
+      Some(sample) => match sample.push(accepted) {
+        Some(accepted) => accepted,
+        None => return Ok(None),
+      },
+      None => accepted,
+    };
+    if let Some(top) = &mut self.top {
+      top.push(accepted);
+      return Ok(None);
+    }
+    if let Some(sorter) = &mut self.sorter {
+      sorter.push(accepted)?;
+      return Ok(None);
+    }
+    Ok(Some(accepted))
+  }
+
+  /// Passes the held records through the next stages, then to `write`.
+  fn finish<F>(mut self, mut write: F) -> Result<(), Error>
+  where
+    F: FnMut(Accepted) -> Result<(), Error>,
+  {
+    if let Some(sample) = self.sample.take() {
+      for accepted in sample.finish() {
+        if let Some(accepted) = self.push(accepted)? {
+          write(accepted)?;
+        }
+      }
+    }
+    if let Some(top) = self.top.take() {
+      for accepted in top.finish() {
+        if let Some(accepted) = self.push(accepted)? {
+          write(accepted)?;
+        }
+      }
+    }
+    match self.sorter {
+      Some(sorter) => sorter.finish(write),
+      None => Ok(()),
+    }
+  }
+}
+
+/// Checks that the run can be resumed from a checkpoint, its output
+/// being a local file, streamed and appended to in the input order.
+fn check_checkpoint(
+  output: &OutputArgs,
+  output_path: &Path,
+  options: &Options,
+  config: &Config,
+  keep: Option<Keep>,
+) -> Result<(), Error> {
+  if pipeline::is_stdio(output_path) || s3::is_s3(output_path) {
+    bail!("--checkpoint needs a local output file");
+  }
+  match config.output_format {
+    OutputFormat::Csv | OutputFormat::Jsonl => {},
+    _ => bail!("--checkpoint only works with the csv and jsonl outputs"),
+  }
+  let paths = Some(output_path).into_iter();
+  for path in paths.chain(output.rejects_path.as_deref()) {
+    let compression =
+      config.compression.unwrap_or_else(|| Compression::of(path));
+    if compression != Compression::None {
+      bail!("--checkpoint can't be used with the compressed outputs");
+    }
+  }
+  if keep.is_some() {
+    bail!("--checkpoint can't be used with --dedupe");
+  }
+  if options.split_cell.is_some() || options.explode {
+    bail!("--checkpoint can't be used with --split-cell or --explode");
+  }
+  if output.split_by.is_some() || output.chunk_size.is_some() {
+    bail!("--checkpoint can't be used with --split-by or --chunk-size");
+  }
+  if !output.sort.is_empty() || output.top.is_some() {
+    bail!("--checkpoint can't be used with --sort or --top");
+  }
+  if output.sample.is_some() || output.sample_rate.is_some() {
+    bail!("--checkpoint can't be used with --sample or --sample-rate");
+  }
+  // the numbers after the checkpoint would be remembered as written.
+  if output.state_db.is_some() {
+    bail!("--checkpoint can't be used with --state-db");
+  }
+  if options.unordered {
+    bail!("--checkpoint can't be used with --unordered");
+  }
+  if options.skip > 0 || options.limit.is_some() {
+    bail!("--checkpoint can't be used with --skip or --limit");
+  }
+  Ok(())
+}
+
+/// Writes the summary file and the reports, if any, with the checksums of the
+/// `files` of the run, and checks the reject rate of the run.
+fn finish(
+  output: &OutputArgs,
+  options: &Options,
+  stats: &Stats,
+  files: &[&Path],
+) -> Result<(), Error> {
+  if !output.dry_run {
+    output.reports.write(stats, files)?;
+  }
+  options
+    .thresholds()
+    .check_rate(stats.rejected(), stats.total)?;
+  if stats.rejected() > 0 {
+    return Err(RejectError::rejected(stats.rejected()));
+  }
+  Ok(())
+}
+
+/// Collects the `.csv` files of the `dir` tree into `files`.
+fn find_csv_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), Error> {
+  let entries = fs::read_dir(dir)
+    .with_context(|_| format!("could not read input directory {:?}", dir))?;
+  for entry in entries {
+    let path = entry?.path();
+    if path.is_dir() {
+      find_csv_files(&path, files)?;
+    } else if path.extension().is_some_and(|ext| ext == "csv") {
+      files.push(path);
+    }
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{commands::Cli, exit_code};
+
+  /// A new directory for the files of the `test`.
+  fn dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mobcsv-clean-{}", test));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+  }
+
+  /// Writes the `content` to the file `name` of the `dir`, returning its
+  /// path.
+  fn write(dir: &Path, name: &str, content: &str) -> String {
+    let path = dir.join(name);
+    fs::write(&path, content).unwrap();
+    path.to_str().unwrap().to_owned()
+  }
+
+  /// Runs `mobcsv clean --quiet` with the `args`.
+  fn clean(args: &[&str]) -> Result<(), Error> {
+    let args = ["mobcsv", "clean", "--quiet"].iter().chain(args);
+    Cli::from_iter_safe(args).unwrap().run()
+  }
+
+  #[test]
+  fn should_fail_fast() {
+    let dir = dir("fail-fast");
+    let input =
+      write(&dir, "in.csv", "ph,name,count\n01116613061,a,1\nbad,b,2\n");
+    let output = dir.join("out.csv");
+    let output = output.to_str().unwrap();
+    let e = clean(&[&input, "-o", output, "--fail-fast"]).unwrap_err();
+    assert_eq!(
+      e.to_string(),
+      format!("{}:3: rejected record `bad,b,2` (NonDigitChars)", input)
+    );
+    assert_eq!(e.downcast_ref::<RejectError>().unwrap().exit_code(), 2);
+    // the blank lines of the JSON Lines files are lines too.
+    let input = write(
+      &dir,
+      "in.jsonl",
+      "{\"ph\":\"01116613061\",\"name\":\"a\",\"count\":1}\n\n\
+       {\"ph\":\"bad\",\"name\":\"b\",\"count\":2}\n",
+    );
+    let e = clean(&[&input, "-o", output, "-f", "--fail-fast"]).unwrap_err();
+    assert_eq!(
+      e.to_string(),
+      format!("{}:3: rejected record `bad,b,2` (NonDigitChars)", input)
+    );
+  }
+
+  #[test]
+  fn should_overwrite_or_append_to_outputs() {
+    let dir = dir("force");
+    let input = write(&dir, "in.csv", "ph,name,count\n01116613061,a,1\n");
+    let output = write(&dir, "out.csv", "existing\n");
+    let e = clean(&[&input, "-o", &output]).unwrap_err();
+    assert!(e.to_string().contains("already exists, use --force"));
+    assert_eq!(fs::read_to_string(&output).unwrap(), "existing\n");
+    clean(&[&input, "-o", &output, "--force"]).unwrap();
+    let headers = "ph,name,count\n";
+    let cleaned = format!("{}201116613061,a,1\n", headers);
+    assert_eq!(fs::read_to_string(&output).unwrap(), cleaned);
+    clean(&[&input, "-o", &output, "--append"]).unwrap();
+    let appended = format!("{}201116613061,a,1\n", cleaned);
+    assert_eq!(fs::read_to_string(&output).unwrap(), appended);
+    let output = write(&dir, "other.csv", "ph,name\n");
+    let e = clean(&[&input, "-o", &output, "--append"]).unwrap_err();
+    assert!(e.to_string().contains("don't match the output headers"));
+    assert_eq!(fs::read_to_string(&output).unwrap(), "ph,name\n");
+  }
+
+  #[test]
+  fn should_clean_in_place() {
+    let dir = dir("in-place");
+    let original = "ph,name,count\n01116613061,a,1\n0111,b,2\n";
+    let input = write(&dir, "in.csv", original);
+    let e = clean(&[&input, "--in-place"]).unwrap_err();
+    assert_eq!(e.downcast_ref::<RejectError>().unwrap().exit_code(), 2);
+    let backup = dir.join("in.csv.bak");
+    assert_eq!(fs::read_to_string(backup).unwrap(), original);
+    assert_eq!(
+      fs::read_to_string(&input).unwrap(),
+      "ph,name,count\n201116613061,a,1\n"
+    );
+    // replaced by the renamed temporary file, only once it is complete.
+    assert!(!dir.join("in.csv.tmp").exists());
+    let input = write(&dir, "in.csv", original);
+    let e = clean(&[&input, "--in-place"]).unwrap_err();
+    assert!(e.to_string().contains("already exists, use --force"));
+    let args = [input.as_str(), "--in-place", "--force", "--fail-fast"];
+    assert!(clean(&args).is_err());
+    assert_eq!(fs::read_to_string(&input).unwrap(), original);
+    let original = "{\"ph\":\"01116613061\",\"name\":\"a\",\"count\":1}\n";
+    let input = write(&dir, "in.jsonl", original);
+    clean(&[&input, "--in-place", "--output-format", "jsonl"]).unwrap();
+    let backup = dir.join("in.jsonl.bak");
+    assert_eq!(fs::read_to_string(backup).unwrap(), original);
+    assert_eq!(
+      fs::read_to_string(&input).unwrap(),
+      "{\"ph\":\"201116613061\",\"name\":\"a\",\"count\":1}\n"
+    );
+  }
+
+  #[test]
+  fn should_exit_with_the_documented_codes() {
+    let dir = dir("exit-codes");
+    let code = |args: &[&str]| clean(args).err().map_or(0, |e| exit_code(&e));
+    let output = dir.join("out.csv");
+    let output = output.to_str().unwrap();
+    let valid = write(&dir, "valid.csv", "ph,name,count\n01116613061,a,1\n");
+    let invalid = write(&dir, "invalid.csv", "ph,name,count\n0111,a,1\n");
+    let missing = dir.join("missing.csv");
+    assert_eq!(code(&[&valid, "-o", output]), 0);
+    assert_eq!(code(&[missing.to_str().unwrap(), "-o", output, "-f"]), 1);
+    assert_eq!(code(&[&invalid, "-o", output, "-f"]), 2);
+    assert_eq!(code(&[&invalid, "-o", output, "-f", "--max-rejects=0"]), 3);
+    let rate = "--max-reject-rate=50%";
+    assert_eq!(code(&[&invalid, "-o", output, "-f", rate]), 3);
+  }
+}
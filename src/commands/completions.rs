@@ -0,0 +1,22 @@
+//! `mobcsv completions`, the completion scripts of the shells.
+
+use std::io;
+
+use failure::Error;
+use structopt::{clap::Shell, StructOpt};
+
+use super::Cli;
+
+#[derive(Debug, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct Args {
+  /// The shell of the script
+  #[structopt(raw(possible_values = "&Shell::variants()"))]
+  pub shell: Shell,
+}
+
+pub fn run(args: &Args) -> Result<(), Error> {
+  let mut app = Cli::clap();
+  app.gen_completions_to("mobcsv", args.shell, &mut io::stdout());
+  Ok(())
+}
@@ -0,0 +1,10 @@
+//! `mobcsv dedupe`, like `clean` but without duplicate phone numbers.
+
+use failure::Error;
+
+use super::clean::{self, Args};
+
+pub fn run(args: &Args) -> Result<(), Error> {
+  let keep = args.output.keep().unwrap_or(args.output.dedupe_keep);
+  clean::run(args, Some(keep))
+}
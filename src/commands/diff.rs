@@ -0,0 +1,188 @@
+//! `mobcsv diff`, the phone numbers added, removed and changed between two
+//! CSV files.
+
+use std::{collections::BTreeMap, path::PathBuf, slice};
+
+use failure::Error;
+use log::info;
+use structopt::StructOpt;
+
+use super::Options;
+use crate::{
+  output,
+  pipeline::{self, Inputs},
+  stats::Stats,
+  Accepted, Config,
+};
+
+#[derive(Debug, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct Args {
+  /// Write the records of the added phone numbers to this CSV file
+  #[structopt(long, parse(from_os_str))]
+  pub added_path: Option<PathBuf>,
+  /// Write the records of the removed phone numbers to this CSV file
+  #[structopt(long, parse(from_os_str))]
+  pub removed_path: Option<PathBuf>,
+  /// Write the phone numbers with a different name or count to this CSV file
+  #[structopt(long, parse(from_os_str))]
+  pub changed_path: Option<PathBuf>,
+  /// Overwrite the output files that already exist
+  #[structopt(short, long)]
+  pub force: bool,
+  #[structopt(flatten)]
+  pub options: Options,
+  /// The old CSV file path
+  #[structopt(parse(from_os_str))]
+  pub old_path: PathBuf,
+  /// The new CSV file path
+  #[structopt(parse(from_os_str))]
+  pub new_path: PathBuf,
+}
+
+/// The accepted records of a file by their phone number, the first record
+/// of a phone number wins.
+type Records = BTreeMap<String, Accepted>;
+
+/// The differences between the old and the new records.
+#[derive(Debug, Default)]
+struct Diff<'a> {
+  added: Vec<&'a Accepted>,
+  removed: Vec<&'a Accepted>,
+  /// The old and new records of the same phone number.
+  changed: Vec<(&'a Accepted, &'a Accepted)>,
+  unchanged: u64,
+}
+
+impl<'a> Diff<'a> {
+  fn new(old: &'a Records, new: &'a Records) -> Self {
+    let mut diff = Diff::default();
+    for (ph, record) in new {
+      match old.get(ph) {
+        None => diff.added.push(record),
+        Some(old) => {
+          let (a, b) = (&old.record, &record.record);
+          if a.name != b.name || a.count != b.count {
+            diff.changed.push((old, record));
+          } else {
+            diff.unchanged += 1;
+          }
+        },
+      }
+    }
+    diff.removed = old
+      .iter()
+      .filter(|(ph, _)| !new.contains_key(*ph))
+      .map(|(_, record)| record)
+      .collect();
+    diff
+  }
+}
+
+pub fn run(args: &Args) -> Result<(), Error> {
+  let config = Config {
+    overwrite: args.force,
+    ..Config::from_options(&args.options)?
+  };
+  let read = |path: &PathBuf| -> Result<(Records, Vec<String>, Stats), Error> {
+    let mut records = Records::new();
+    let mut stats = Stats::default();
+    let inputs = Inputs::open(slice::from_ref(path), &args.options)?;
+    let passed = inputs.extra_headers().to_vec();
+    inputs.process(&config, &mut stats, |result| {
+      if let Ok(accepted) = result {
+        records
+          .entry(accepted.record.ph.clone())
+          .or_insert(accepted);
+      }
+      Ok(())
+    })?;
+    Ok((records, passed, stats))
+  };
+  let (old, old_passed, old_stats) = read(&args.old_path)?;
+  let (new, new_passed, new_stats) = read(&args.new_path)?;
+  let diff = Diff::new(&old, &new);
+  // the records are written with the other columns of their own file.
+  let write =
+    |path: &Option<PathBuf>, passed: &[String], records: &[&Accepted]| {
+      if let Some(path) = path {
+        info!("Writing {} records to {:?}", records.len(), path);
+        let mut wrt =
+          pipeline::create(path, &config, &output::headers(&config, passed))?;
+        for accepted in records {
+          output::write_record(wrt.as_mut(), &config, accepted)?;
+        }
+        wrt.finish()?;
+      }
+      Ok::<_, Error>(())
+    };
+  write(&args.added_path, &new_passed, &diff.added)?;
+  write(&args.removed_path, &old_passed, &diff.removed)?;
+  if let Some(path) = &args.changed_path {
+    info!("Writing {} records to {:?}", diff.changed.len(), path);
+    let mut wrt = pipeline::create(path, &config, output::CHANGED_HEADERS)?;
+    for (old, new) in &diff.changed {
+      output::write_changed(wrt.as_mut(), &config, old, new)?;
+    }
+    wrt.finish()?;
+  }
+  if !args.options.quiet {
+    println!(
+      "Added {}, removed {}, changed {}, unchanged {}",
+      diff.added.len(),
+      diff.removed.len(),
+      diff.changed.len(),
+      diff.unchanged
+    );
+    println!(
+      "Rejected {} old records and {} new records",
+      old_stats.rejected(),
+      new_stats.rejected()
+    );
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::Record;
+
+  fn records(records: &[(&str, &str, u64)]) -> Records {
+    records
+      .iter()
+      .map(|&(ph, name, count)| {
+        let accepted = Accepted {
+          record: Record::new(ph, name, count),
+          original_ph: ph.to_owned(),
+          fixed: false,
+          extracted: false,
+        };
+        (ph.to_owned(), accepted)
+      })
+      .collect()
+  }
+
+  #[test]
+  fn should_diff_records() {
+    let old = records(&[
+      ("201016613061", "a", 1),
+      ("201116613061", "b", 1),
+      ("201216613061", "c", 1),
+    ]);
+    let new = records(&[
+      ("201116613061", "b", 2),
+      ("201216613061", "c", 1),
+      ("966540029129", "d", 1),
+    ]);
+    let diff = Diff::new(&old, &new);
+    let phs = |records: &[&Accepted]| -> Vec<String> {
+      records.iter().map(|r| r.record.ph.clone()).collect()
+    };
+    assert_eq!(phs(&diff.added), ["966540029129"]);
+    assert_eq!(phs(&diff.removed), ["201016613061"]);
+    assert_eq!(diff.changed.len(), 1);
+    assert_eq!(diff.changed[0].1.record.count, 2);
+    assert_eq!(diff.unchanged, 1);
+  }
+}
@@ -0,0 +1,96 @@
+//! `mobcsv man`, the man page of mobcsv and of its subcommands, made of
+//! their help.
+
+use std::io::{self, Write};
+
+use failure::{bail, Error};
+use structopt::{
+  clap::{AppSettings, ErrorKind},
+  StructOpt,
+};
+
+use super::Cli;
+
+/// The subcommands of the man page, in the order of their sections.
+const COMMANDS: &[&str] = &[
+  "clean",
+  "validate",
+  "stats",
+  "dedupe",
+  "merge",
+  "diff",
+  "bench",
+  "completions",
+];
+
+#[derive(Debug, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct Args {}
+
+pub fn run(_: &Args) -> Result<(), Error> {
+  let stdout = io::stdout();
+  let mut out = stdout.lock();
+  write!(out, "{}", page()?)?;
+  Ok(())
+}
+
+/// The man page, in the roff format.
+fn page() -> Result<String, Error> {
+  let mut page = String::new();
+  page.push_str(concat!(
+    ".TH MOBCSV 1 \"\" \"mobcsv ",
+    env!("CARGO_PKG_VERSION"),
+    "\"\n"
+  ));
+  page.push_str(".SH NAME\n");
+  page.push_str("mobcsv \\- validate and format mobile numbers\n");
+  page.push_str(".SH SYNOPSIS\n");
+  page.push_str(".nf\n");
+  page.push_str(&escape(&help(&[])?));
+  page.push_str(".fi\n");
+  for command in COMMANDS {
+    page.push_str(&format!(".SH \"MOBCSV {}\"\n", command.to_uppercase()));
+    page.push_str(".nf\n");
+    page.push_str(&escape(&help(&[command])?));
+    page.push_str(".fi\n");
+  }
+  Ok(page)
+}
+
+/// The long help of the subcommand of the `args`.
+fn help(args: &[&str]) -> Result<String, Error> {
+  let app = Cli::clap().global_setting(AppSettings::ColorNever);
+  let all = ["mobcsv"].iter().chain(args).chain(&["--help"]);
+  match app.get_matches_from_safe(all) {
+    Err(e) if e.kind == ErrorKind::HelpDisplayed => Ok(e.message),
+    _ => bail!("could not get the help of `mobcsv {}`", args.join(" ")),
+  }
+}
+
+/// Escapes the `text` for it to be written as is by roff.
+fn escape(text: &str) -> String {
+  let mut escaped = String::new();
+  for line in text.lines() {
+    let line = line.replace('\\', "\\e").replace('-', "\\-");
+    if line.starts_with('.') || line.starts_with('\'') {
+      escaped.push_str("\\&");
+    }
+    escaped.push_str(&line);
+    escaped.push('\n');
+  }
+  escaped
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn should_write_the_help_of_every_subcommand() {
+    let page = page().unwrap();
+    assert!(page.starts_with(".TH MOBCSV 1"));
+    assert!(page.contains(".SH \"MOBCSV CLEAN\"\n"));
+    assert!(page.contains("\\-\\-fail\\-fast"));
+    assert_eq!(escape(".a\\b"), "\\&.a\\eb\n");
+  }
+}
@@ -0,0 +1,37 @@
+//! `mobcsv merge`, several CSV files cleaned into one.
+
+use std::path::PathBuf;
+
+use failure::Error;
+use structopt::StructOpt;
+
+use super::{clean, Options, OutputArgs};
+use crate::dedupe::Keep;
+
+#[derive(Debug, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct Args {
+  /// Which record of a phone number found in several files is kept, `sum`
+  /// merges them summing their counts [default: first]
+  #[structopt(long, raw(possible_values = "Keep::VARIANTS"))]
+  pub strategy: Option<Keep>,
+  #[structopt(flatten)]
+  pub output: OutputArgs,
+  #[structopt(flatten)]
+  pub options: Options,
+  /// The input CSV files paths or glob patterns (e.g. `exports/*.csv`), `-`
+  /// reads the standard input
+  #[structopt(parse(from_os_str), raw(required = "true"))]
+  pub input_paths: Vec<PathBuf>,
+}
+
+pub fn run(args: &Args) -> Result<(), Error> {
+  // the phone numbers are deduplicated across all the files.
+  let keep = args.strategy.or_else(|| args.output.keep());
+  clean::write(
+    &args.output,
+    &args.options,
+    &args.input_paths,
+    Some(keep.unwrap_or_default()),
+  )
+}
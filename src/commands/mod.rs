@@ -0,0 +1,741 @@
+//! The subcommands of mobcsv, each one in its own module, and the options
+//! they share.
+
+use std::{
+  env,
+  path::{Path, PathBuf},
+};
+
+use clap_verbosity_flag::Verbosity;
+use console::Term;
+use failure::Error;
+use glob::Pattern;
+use log::info;
+use regex::Regex;
+use structopt::StructOpt;
+
+use crate::{
+  compress::Compression,
+  dedupe::Keep,
+  encoding::{Encoding, OutputEncoding},
+  format::Format,
+  http,
+  logging::{self, LogFormat},
+  names::{ArabicNames, NameRule},
+  output::{Column, RejectsFormat},
+  pipeline,
+  qr::QrUri,
+  reject::{self, Thresholds},
+  report, sample,
+  schema::{Field, Mapping},
+  sink::{LineEnding, OutputFormat, SqlDialect},
+  sort::{SortField, SortKey},
+  source::InputFormat,
+  split::SplitBy,
+  stats::Stats,
+  validator::Engine,
+};
+
+mod bench;
+mod clean;
+mod completions;
+mod dedupe;
+mod diff;
+mod man;
+mod merge;
+mod stats;
+mod validate;
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+  name = "mobcsv",
+  about = "Validate and format mobile number in one standard way.",
+  version = "0.1.0",
+  author = "Shady Khalifa <shekohex@gmail>",
+  rename_all = "kebab-case",
+  after_help = "EXIT CODES:
+    0    Every record is accepted
+    1    An I/O or parse error
+    2    Some records are rejected
+    3    The rejects exceed --max-rejects or --max-reject-rate"
+)]
+pub enum Cli {
+  /// Clean, validate and format the phone numbers of a CSV file
+  #[structopt(name = "clean")]
+  Clean(clean::Args),
+  /// Only check the phone numbers of a CSV file, failing if any of them is
+  /// rejected
+  #[structopt(name = "validate")]
+  Validate(validate::Args),
+  /// Analyze the phone numbers of a CSV file, without writing any output
+  #[structopt(name = "stats")]
+  Stats(stats::Args),
+  /// Like `clean --dedupe`, only keep one record of every phone number
+  #[structopt(name = "dedupe")]
+  Dedupe(clean::Args),
+  /// Clean several CSV files into one, without duplicate phone numbers
+  #[structopt(name = "merge")]
+  Merge(merge::Args),
+  /// Compare the phone numbers of two CSV files
+  #[structopt(name = "diff")]
+  Diff(diff::Args),
+  /// Measure every stage of the pipeline on synthetic records
+  #[structopt(name = "bench")]
+  Bench(bench::Args),
+  /// Write the completion script of a shell to the standard output
+  #[structopt(name = "completions")]
+  Completions(completions::Args),
+  /// Write the man page of mobcsv to the standard output
+  #[structopt(name = "man")]
+  Man(man::Args),
+}
+
+impl Cli {
+  /// The options of the subcommands reading records.
+  pub fn options(&self) -> Option<&Options> {
+    match self {
+      Cli::Clean(args) | Cli::Dedupe(args) => Some(&args.options),
+      Cli::Validate(args) => Some(&args.options),
+      Cli::Stats(args) => Some(&args.options),
+      Cli::Merge(args) => Some(&args.options),
+      Cli::Diff(args) => Some(&args.options),
+      Cli::Bench(args) => Some(&args.options),
+      Cli::Completions(_) | Cli::Man(_) => None,
+    }
+  }
+
+  pub fn run(&self) -> Result<(), Error> {
+    match self {
+      Cli::Clean(args) => clean::run(args, None),
+      Cli::Validate(args) => validate::run(args),
+      Cli::Stats(args) => stats::run(args),
+      Cli::Dedupe(args) => dedupe::run(args),
+      Cli::Merge(args) => merge::run(args),
+      Cli::Diff(args) => diff::run(args),
+      Cli::Bench(args) => bench::run(args),
+      Cli::Completions(args) => completions::run(args),
+      Cli::Man(args) => man::run(args),
+    }
+  }
+}
+
+/// The options of reading and validating the records, shared by all the
+/// subcommands.
+#[derive(Debug, Clone, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct Options {
+  /// The config file of the default flags, `mobcsv.toml` of the current
+  /// directory or of `~/.config/mobcsv/` by default
+  #[structopt(long, parse(from_os_str))]
+  pub config: Option<PathBuf>,
+  /// The profile of the config file to use, its `[profile.<name>]` table
+  #[structopt(long)]
+  pub profile: Option<String>,
+  /// Stop at the first rejected record or malformed CSV row
+  #[structopt(long)]
+  pub fail_fast: bool,
+  /// Skip this number of records first, after the header
+  #[structopt(long, default_value = "0")]
+  pub skip: u64,
+  /// Process this number of records at most, after the skipped ones
+  #[structopt(long)]
+  pub limit: Option<u64>,
+  /// Fail the run when more than this number of records are rejected
+  #[structopt(long)]
+  pub max_rejects: Option<u64>,
+  /// Fail the run when more than this percentage of the records are
+  /// rejected (e.g. 5%)
+  #[structopt(long, parse(try_from_str = "reject::parse_rate"))]
+  pub max_reject_rate: Option<f64>,
+  /// The country of national numbers written with a leading trunk prefix,
+  /// like `01116613061` (e.g. EG, SA)
+  #[structopt(long)]
+  pub default_country: Option<String>,
+  /// The validation engine
+  #[structopt(
+    long,
+    default_value = "regex",
+    raw(possible_values = "Engine::VARIANTS")
+  )]
+  pub engine: Engine,
+  /// A TOML (or JSON) file of country and prefix rules to use instead of the
+  /// embedded ones
+  #[structopt(long, raw(alias = r#""rules-file""#), parse(from_os_str))]
+  pub rules: Option<PathBuf>,
+  /// Only accept numbers of these dialing codes (e.g. 20,966)
+  #[structopt(long, raw(use_delimiter = "true", number_of_values = "1"))]
+  pub countries: Vec<String>,
+  /// Reject numbers of these dialing codes (e.g. 971)
+  #[structopt(long, raw(use_delimiter = "true", number_of_values = "1"))]
+  pub exclude_countries: Vec<String>,
+  /// A regex the normalized numbers must match, in place of the country and
+  /// prefix rules of the regex engine
+  #[structopt(long)]
+  pub mob_regex: Option<Regex>,
+  /// A regex of the characters removed from the numbers before validating
+  #[structopt(long)]
+  pub replacer_regex: Option<Regex>,
+  /// Only remove the well known separators from the phone numbers, instead
+  /// of every character that is not a digit
+  #[structopt(long)]
+  pub strict_chars: bool,
+  /// Try to repair the common typos (like the letter O instead of zero) of
+  /// the rejected phone numbers, and add a `fixed` column to the output
+  #[structopt(long)]
+  pub fix_typos: bool,
+  /// Convert the characters looking like digits of the rejected phone
+  /// numbers, like the letter O or its Cyrillic lookalike, to digits, and
+  /// add a `fixed` column to the output
+  #[structopt(long)]
+  pub convert_confusables: bool,
+  /// Look for a phone number in the text of this input column when the
+  /// phone one is empty or invalid (e.g. `notes`), and add an `extracted`
+  /// column to the output
+  #[structopt(long)]
+  pub extract_from: Option<String>,
+  /// Clean the names of the accepted records with these steps, along with
+  /// their Unicode NFC normalization (e.g. `trim,collapse-spaces,titlecase`)
+  #[structopt(
+    long,
+    raw(
+      use_delimiter = "true",
+      number_of_values = "1",
+      possible_values = "NameRule::VARIANTS"
+    )
+  )]
+  pub normalize_names: Vec<NameRule>,
+  /// Normalize the Arabic names, removing their tatweel and diacritics and
+  /// writing the alef and hamza forms as their base letter
+  #[structopt(long, raw(possible_values = "ArabicNames::VARIANTS"))]
+  pub arabic_names: Option<ArabicNames>,
+  /// Only accept the numbers known to be mobile ones
+  #[structopt(long)]
+  pub only_mobile: bool,
+  /// Reject the records with a lower `count`
+  #[structopt(long)]
+  pub min_count: Option<u64>,
+  /// Reject the records with a higher `count`
+  #[structopt(long)]
+  pub max_count: Option<u64>,
+  /// Reject the numbers of this do-not-contact list, a number per line or a
+  /// CSV file with a `ph` column
+  #[structopt(long, parse(from_os_str))]
+  pub blacklist: Option<PathBuf>,
+  /// Only accept the numbers of this approved contacts list, in the same
+  /// format as the `--blacklist`
+  #[structopt(long, parse(from_os_str))]
+  pub allowlist: Option<PathBuf>,
+  /// The input column of the phone numbers
+  #[structopt(long, default_value = "ph")]
+  pub ph_column: String,
+  /// The input columns of several phone numbers (e.g.
+  /// `mobile,mobile2,whatsapp`), the first one replacing the `--ph-column`,
+  /// the others normalized in place, empty when invalid, and the first valid
+  /// one accepted when it isn't
+  #[structopt(long, raw(use_delimiter = "true", number_of_values = "1"))]
+  pub ph_columns: Vec<String>,
+  /// Add a record by phone number of the `--ph-columns` instead, with the
+  /// same name and count
+  #[structopt(long, raw(requires = r#""ph-columns""#))]
+  pub explode: bool,
+  /// The input column of the names
+  #[structopt(long, default_value = "name")]
+  pub name_column: String,
+  /// The input column of the counts
+  #[structopt(long, default_value = "count")]
+  pub count_column: String,
+  /// Only fail on these missing input columns, the missing names being
+  /// empty and counts zero (e.g. `ph`), all of them by default
+  #[structopt(
+    long,
+    raw(
+      use_delimiter = "true",
+      number_of_values = "1",
+      possible_values = "Field::VARIANTS"
+    )
+  )]
+  pub require_columns: Vec<Field>,
+  /// Split the phone number cells on these characters (e.g. `/;,`), a
+  /// record by phone number with the same name and count
+  #[structopt(long)]
+  pub split_cell: Option<String>,
+  /// Read the empty counts as zero, and reject the records with invalid
+  /// counts instead of failing the run
+  #[structopt(long)]
+  pub lenient_count: bool,
+  /// The input columns of the fields, matched regardless of the case (e.g.
+  /// `ph=Mobile,name=Customer Name,count=Sends`)
+  #[structopt(long, raw(use_delimiter = "true", number_of_values = "1"))]
+  pub map: Vec<Mapping>,
+  /// The input files have no headers row, their columns are found by their
+  /// `--*-index` positions
+  #[structopt(long)]
+  pub no_headers: bool,
+  /// The position of the phone numbers column with `--no-headers`, 0 by
+  /// default
+  #[structopt(long, raw(requires = r#""no-headers""#))]
+  pub ph_index: Option<usize>,
+  /// The position of the names column with `--no-headers`, 1 by default
+  #[structopt(long, raw(requires = r#""no-headers""#))]
+  pub name_index: Option<usize>,
+  /// The position of the counts column with `--no-headers`, 2 by default
+  #[structopt(long, raw(requires = r#""no-headers""#))]
+  pub count_index: Option<usize>,
+  /// The file format of the inputs, by their extension by default
+  #[structopt(long, raw(possible_values = "InputFormat::VARIANTS"))]
+  pub input_format: Option<InputFormat>,
+  /// A `Name: value` header of the requests of the `http(s)://` inputs, like
+  /// `Authorization: Bearer <token>`
+  #[structopt(
+    long,
+    number_of_values = 1,
+    parse(try_from_str = "http::parse_header")
+  )]
+  pub header: Vec<(String, String)>,
+  /// The entries of the `.zip` inputs to read, as if they were concatenated
+  #[structopt(long, default_value = "*.csv")]
+  pub zip_entry: Pattern,
+  /// The sheet of the `.xlsx` (or `.xls`, `.ods`) inputs, the first one by
+  /// default
+  #[structopt(long)]
+  pub sheet: Option<String>,
+  /// The delimiter of the input fields, `\t` for the tabs
+  #[structopt(long, parse(try_from_str = "pipeline::parse_delimiter"))]
+  pub delimiter: Option<u8>,
+  /// Detect the delimiter of every input, one of `,`, `;`, tab or `|`, from
+  /// its first line
+  #[structopt(long, raw(conflicts_with = r#""delimiter""#))]
+  pub detect_delimiter: bool,
+  /// The text encoding of the inputs, transcoded to UTF-8, `auto` to detect
+  /// it from their byte order mark or first bytes
+  #[structopt(
+    long,
+    default_value = "utf-8",
+    raw(possible_values = "Encoding::VARIANTS")
+  )]
+  pub encoding: Encoding,
+  /// The threads cleaning and validating the records, in chunks, 0 for one
+  /// per CPU, the records are still written in the input order
+  #[structopt(short = "j", long, default_value = "1")]
+  pub jobs: usize,
+  /// Handle the chunks of `--jobs` as soon as they are cleaned, reading the
+  /// next ones meanwhile, the records are written in no particular order
+  #[structopt(long)]
+  pub unordered: bool,
+  /// Memory map the input files, the CSV ones with no quoted fields are
+  /// then also parsed by the threads of `--jobs`, split at the line ends
+  #[structopt(long)]
+  pub mmap: bool,
+  /// Show the progress even when the standard error isn't a terminal, as
+  /// plain lines, every second
+  #[structopt(long)]
+  pub progress: bool,
+  #[structopt(flatten)]
+  pub verbosity: Verbosity,
+  /// Only write the errors, no summary, progress or logs
+  #[structopt(short = "q", long)]
+  pub quiet: bool,
+  /// Don't color the summary table, like with the `NO_COLOR` environment
+  /// variable
+  #[structopt(long)]
+  pub no_color: bool,
+  /// The format of the logs written to the standard error
+  #[structopt(
+    long,
+    default_value = "text",
+    raw(possible_values = "LogFormat::VARIANTS")
+  )]
+  pub log_format: LogFormat,
+  /// Also write the logs to this file, appending to it
+  #[structopt(long, parse(from_os_str))]
+  pub log_file: Option<PathBuf>,
+  /// Rotate the `--log-file` when it reaches this size (e.g. 10MB), keeping
+  /// the last 5 ones as `<log-file>.1` to `.5`
+  #[structopt(
+    long,
+    raw(requires = r#""log-file""#),
+    parse(try_from_str = "logging::parse_size")
+  )]
+  pub log_rotate_size: Option<u64>,
+}
+
+impl Options {
+  pub fn thresholds(&self) -> Thresholds {
+    Thresholds {
+      max_rejects: self.max_rejects,
+      max_reject_rate: self.max_reject_rate,
+    }
+  }
+
+  /// Writes the summary of the `stats`, as a table to the standard error
+  /// when it is a terminal, otherwise as lines with `say`.
+  pub fn summarize<F: Fn(String)>(&self, stats: &Stats, say: F) {
+    if self.quiet {
+      return;
+    }
+    if Term::stderr().is_term() {
+      let color = !self.no_color && env::var_os("NO_COLOR").is_none();
+      eprint!("{}", stats.table(color));
+    } else {
+      say(stats.to_string());
+    }
+  }
+}
+
+/// The summaries and reports of a run, written once it is done.
+#[derive(Debug, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct ReportArgs {
+  /// Also write the summary of the run to this JSON file
+  #[structopt(long, parse(from_os_str))]
+  pub summary_json: Option<PathBuf>,
+  /// Also write an HTML report of the run, with the charts of the countries,
+  /// operators and reject reasons, its duplicates and a sample of the
+  /// rejected records
+  #[structopt(long, parse(from_os_str))]
+  pub report: Option<PathBuf>,
+  /// Also write the summary of the run as Markdown tables, with the
+  /// checksums of its files
+  #[structopt(long, parse(from_os_str))]
+  pub report_md: Option<PathBuf>,
+  /// Also write every phone number found more than once to this CSV file,
+  /// with all of its input rows
+  #[structopt(long, parse(from_os_str))]
+  pub dup_report: Option<PathBuf>,
+  /// Also write every phone number found with different names, ignoring
+  /// their case and spaces, to this CSV file, with all of its input rows
+  #[structopt(long, parse(from_os_str))]
+  pub name_conflicts: Option<PathBuf>,
+}
+
+impl ReportArgs {
+  /// No statistics yet, keeping the records needed by the reports, and
+  /// counting the duplicates if they are reported.
+  pub fn stats(&self) -> Stats {
+    let dups = self.dup_report.is_some() || self.name_conflicts.is_some();
+    let stats = Stats::new(self.report.is_some(), dups);
+    let reported = self.summary_json.is_some() || self.report_md.is_some();
+    if reported {
+      stats.count_duplicates()
+    } else {
+      stats
+    }
+  }
+
+  /// Writes the reports of the `stats` of the run, with the checksums of
+  /// its `files`.
+  pub fn write(&self, stats: &Stats, files: &[&Path]) -> Result<(), Error> {
+    if let Some(path) = &self.summary_json {
+      info!("Writing the summary to {:?}", path);
+      stats.write_json(path)?;
+    }
+    if let Some(path) = &self.report {
+      info!("Writing the report to {:?}", path);
+      report::write(path, stats)?;
+    }
+    if let Some(path) = &self.report_md {
+      info!("Writing the report to {:?}", path);
+      report::write_markdown(path, stats, files)?;
+    }
+    if let (Some(path), Some(dups)) = (&self.dup_report, &stats.dups) {
+      info!("Writing the duplicates to {:?}", path);
+      dups.write(path)?;
+    }
+    if let (Some(path), Some(dups)) = (&self.name_conflicts, &stats.dups) {
+      info!("Writing the name conflicts to {:?}", path);
+      dups.write_conflicts(path)?;
+    }
+    Ok(())
+  }
+}
+
+/// The options of the subcommands writing the accepted records.
+#[derive(Debug, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct OutputArgs {
+  /// The CSV output file path, an `s3://bucket/key` URL, or `-` for the
+  /// standard output
+  #[structopt(
+    short = "o",
+    parse(from_os_str),
+    raw(required_unless_one = r#"&["check", "output-dir", "in-place"]"#)
+  )]
+  pub output_path: Option<PathBuf>,
+  /// Only validate the records and print the summary, without writing any
+  /// output, failing if any record is rejected
+  #[structopt(long)]
+  pub check: bool,
+  /// Process all the records and print the summary, without creating or
+  /// touching any output file
+  #[structopt(long)]
+  pub dry_run: bool,
+  /// Write the output files in place, not to a `.tmp` file renamed once
+  /// complete, for the filesystems where renaming isn't atomic
+  #[structopt(long)]
+  pub no_atomic: bool,
+  /// Overwrite the output files that already exist
+  #[structopt(short, long)]
+  pub force: bool,
+  /// Add the records to the output file if it already exists, without
+  /// writing the headers again
+  #[structopt(long, raw(conflicts_with = r#""force""#))]
+  pub append: bool,
+  /// Write the rejected records to this CSV file, with a `reject_reason`
+  /// column
+  #[structopt(long, parse(from_os_str))]
+  pub rejects_path: Option<PathBuf>,
+  /// The format of the rejects file, `jsonl` for a JSON object by record
+  /// with its `original`, `normalized_attempt`, `reason` and `line`, the
+  /// output format by default
+  #[structopt(
+    long,
+    raw(
+      possible_values = "RejectsFormat::VARIANTS",
+      requires = r#""rejects-path""#
+    )
+  )]
+  pub rejects_format: Option<RejectsFormat>,
+  /// Accept the rejected records with the corrected phone number suggested
+  /// for them, a single edit away, marking them as `fixed`
+  #[structopt(long)]
+  pub apply_suggestions: bool,
+  #[structopt(flatten)]
+  pub reports: ReportArgs,
+  /// Ask what to do with the rejected records a single edit away from a
+  /// valid phone number, accepting one of the suggested fixes, editing it,
+  /// or skipping it
+  #[structopt(long)]
+  pub interactive: bool,
+  /// Replay the decisions of this `ph,fix` CSV file, recording the new ones
+  /// of `--interactive` to it
+  #[structopt(long, parse(from_os_str))]
+  pub patch_file: Option<PathBuf>,
+  /// Record the progress of the run to this JSON file, from time to time,
+  /// resuming after it when run again, appending to the output
+  #[structopt(long, parse(from_os_str))]
+  pub checkpoint: Option<PathBuf>,
+  /// Only keep one record of every phone number
+  #[structopt(long)]
+  pub dedupe: bool,
+  /// Which record of a duplicate phone number is kept
+  #[structopt(
+    long,
+    default_value = "first",
+    raw(possible_values = "Keep::VARIANTS")
+  )]
+  pub dedupe_keep: Keep,
+  /// Merge the records of the same phone number into one, summing their
+  /// counts, same as `--dedupe --dedupe-keep sum`
+  #[structopt(long)]
+  pub aggregate: bool,
+  /// Find the duplicates with a bloom filter sized for this many numbers,
+  /// using much less memory but dropping about 1% of the unique numbers
+  #[structopt(long)]
+  pub dedupe_bloom: Option<usize>,
+  /// Skip the phone numbers already in this file, like the output with
+  /// `--append`, a number per line or a CSV file with a `ph` column
+  #[structopt(long, parse(from_os_str))]
+  pub dedupe_against: Option<PathBuf>,
+  /// Skip the phone numbers written by the previous runs, remembered in
+  /// this database with the ones of this run
+  #[structopt(long, parse(from_os_str))]
+  pub state_db: Option<PathBuf>,
+  /// Split the output into a file per value, the output path being a
+  /// template with the value placeholder, like `clean-{country}.csv` or
+  /// `{operator}.csv`
+  #[structopt(long, raw(possible_values = "SplitBy::VARIANTS"))]
+  pub split_by: Option<SplitBy>,
+  /// Start a new output file every this many records, the output path
+  /// being a template with the part number, like `batch-{n}.csv`
+  #[structopt(long, raw(conflicts_with = r#""split-by""#))]
+  pub chunk_size: Option<u64>,
+  /// Sort the output records by these fields, `ph`, `name` or `count`,
+  /// each one followed by `:asc` or `:desc`, like `count:desc,name`
+  #[structopt(long, raw(use_delimiter = "true", number_of_values = "1"))]
+  pub sort: Vec<SortKey>,
+  /// The megabytes of records sorted in memory, the larger outputs are
+  /// sorted in runs spilled to temporary files
+  #[structopt(long, default_value = "512")]
+  pub sort_memory_limit: usize,
+  /// Only write this many records, the ones with the highest `--by` values
+  #[structopt(long)]
+  pub top: Option<usize>,
+  /// The field of the `--top` records
+  #[structopt(
+    long,
+    default_value = "count",
+    raw(possible_values = "SortField::VARIANTS")
+  )]
+  pub by: SortField,
+  /// Only write a random sample of this many records, in their order
+  #[structopt(long)]
+  pub sample: Option<usize>,
+  /// Only write a random sample of about this rate of the records, between
+  /// 0 and 1
+  #[structopt(
+    long,
+    parse(try_from_str = "sample::parse_rate"),
+    raw(conflicts_with = r#""sample""#)
+  )]
+  pub sample_rate: Option<f64>,
+  /// The seed of the random samples, the same seed always picking the same
+  /// records
+  #[structopt(long)]
+  pub seed: Option<u64>,
+  /// The file format of the outputs
+  #[structopt(
+    long,
+    default_value = "csv",
+    raw(possible_values = "OutputFormat::VARIANTS")
+  )]
+  pub output_format: OutputFormat,
+  /// Compress the outputs, by their `.gz` or `.zst` extension by default,
+  /// the inputs decompression is always detected
+  #[structopt(long, raw(possible_values = "Compression::VARIANTS"))]
+  pub compress: Option<Compression>,
+  /// The table of the `sqlite` output format, created if needed, unique by
+  /// phone number, or the one the `sql` output format statements load into
+  #[structopt(long, default_value = "contacts")]
+  pub table: String,
+  /// The SQL dialect of the `sql` output format, `postgres` for a `COPY`
+  /// statement instead of the `INSERT` ones
+  #[structopt(
+    long,
+    default_value = "ansi",
+    raw(possible_values = "SqlDialect::VARIANTS")
+  )]
+  pub sql_dialect: SqlDialect,
+  /// The delimiter of the output fields, `\t` for the tabs
+  #[structopt(
+    long,
+    default_value = ",",
+    parse(try_from_str = "pipeline::parse_delimiter")
+  )]
+  pub output_delimiter: u8,
+  /// The text encoding of the outputs, `utf-8-bom` or `cp1256` for the
+  /// Arabic-locale Excel to show them right
+  #[structopt(
+    long,
+    default_value = "utf-8",
+    raw(possible_values = "OutputEncoding::VARIANTS")
+  )]
+  pub output_encoding: OutputEncoding,
+  /// Start the outputs with the UTF-8 byte order mark, like
+  /// `--output-encoding utf-8-bom`
+  #[structopt(long, raw(conflicts_with = r#""output-encoding""#))]
+  pub bom: bool,
+  /// The line ending of the CSV outputs, `crlf` for the Windows SMS tools,
+  /// or `preserve` to keep the one of the first input
+  #[structopt(
+    long,
+    default_value = "lf",
+    raw(possible_values = "LineEnding::VARIANTS")
+  )]
+  pub line_ending: LineEnding,
+  /// How the phone numbers are written to the output, always `e164` for
+  /// the contacts output formats, `vcf`, `google-contacts` and `outlook`
+  #[structopt(
+    long,
+    default_value = "digits",
+    raw(possible_values = "Format::VARIANTS")
+  )]
+  pub format: Format,
+  /// Add an `original_ph` column with the phone number before cleaning
+  #[structopt(long)]
+  pub keep_original: bool,
+  /// Add an `operator` column with the detected mobile operator
+  #[structopt(long)]
+  pub with_operator: bool,
+  /// Add `country_code` and `country_name` columns
+  #[structopt(long)]
+  pub with_country: bool,
+  /// Add a `line_type` column, one of mobile, landline or unknown
+  #[structopt(long)]
+  pub with_line_type: bool,
+  /// Also write a PNG QR code per accepted record to this directory, named
+  /// by the normalized number
+  #[structopt(long, parse(from_os_str))]
+  pub qr_dir: Option<PathBuf>,
+  /// The URI of the QR codes, `tel` to call the number or `wa` to chat with
+  /// it on WhatsApp
+  #[structopt(
+    long,
+    default_value = "tel",
+    raw(possible_values = "QrUri::VARIANTS")
+  )]
+  pub qr_uri: QrUri,
+  /// Add a `wa_link` column, the `https://wa.me/` WhatsApp click-to-chat
+  /// link of the number
+  #[structopt(long)]
+  pub with_wa_link: bool,
+  /// Add the columns of this CSV file to the records of the same phone
+  /// number, empty for the numbers not found in it
+  #[structopt(long, parse(from_os_str))]
+  pub join: Option<PathBuf>,
+  /// The phone number column of the `--join` file
+  #[structopt(long, default_value = "ph")]
+  pub join_key: String,
+  /// The comma separated columns of the `--join` file to add, all of them
+  /// but the key by default
+  #[structopt(long, raw(use_delimiter = "true", number_of_values = "1"))]
+  pub join_columns: Vec<String>,
+}
+
+impl OutputArgs {
+  /// The dedupe strategy of the flags, if any.
+  /// No statistics yet, counting the duplicates if they are reported, or
+  /// removed with `keep` or the flags, with the bloom filter of
+  /// `--dedupe-bloom` if any.
+  pub fn stats(&self, keep: Option<Keep>) -> Stats {
+    let mut stats = self.reports.stats();
+    if keep.or_else(|| self.keep()).is_some() {
+      stats = stats.count_duplicates();
+    }
+    match self.dedupe_bloom {
+      Some(items) if stats.counts_duplicates() => {
+        stats.count_duplicates_with_bloom(items)
+      },
+      _ => stats,
+    }
+  }
+
+  pub fn keep(&self) -> Option<Keep> {
+    if self.aggregate {
+      Some(Keep::Sum)
+    } else if self.dedupe {
+      Some(self.dedupe_keep)
+    } else {
+      None
+    }
+  }
+
+  /// The extra output columns, in order.
+  pub fn columns(&self, options: &Options) -> Vec<Column> {
+    let mut columns = Vec::new();
+    if self.keep_original {
+      columns.push(Column::OriginalPh);
+    }
+    if self.with_operator {
+      columns.push(Column::Operator);
+    }
+    if self.with_country {
+      columns.extend(&[Column::CountryCode, Column::CountryName]);
+    }
+    if self.with_line_type {
+      columns.push(Column::LineType);
+    }
+    // the fixes of the reviews and the suggestions are marked too.
+    let review = self.interactive || self.patch_file.is_some();
+    let fixes = options.fix_typos || options.convert_confusables;
+    if fixes || self.apply_suggestions || review {
+      columns.push(Column::Fixed);
+    }
+    if options.extract_from.is_some() {
+      columns.push(Column::Extracted);
+    }
+    if self.with_wa_link {
+      columns.push(Column::WaLink);
+    }
+    columns
+  }
+}
@@ -0,0 +1,44 @@
+//! `mobcsv stats`, a report on the records without writing any output.
+
+use std::path::PathBuf;
+
+use failure::Error;
+use structopt::StructOpt;
+
+use super::Options;
+use crate::{pipeline, stats::Stats, Config};
+
+#[derive(Debug, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct Args {
+  /// Print the report as JSON
+  #[structopt(long)]
+  pub json: bool,
+  #[structopt(flatten)]
+  pub options: Options,
+  /// The input CSV files paths or glob patterns (e.g. `exports/*.csv`), `-`
+  /// reads the standard input
+  #[structopt(parse(from_os_str), raw(required = "true"))]
+  pub input_paths: Vec<PathBuf>,
+}
+
+pub fn run(args: &Args) -> Result<(), Error> {
+  let config = Config::from_options(&args.options)?;
+  let mut stats = Stats::default().count_duplicates();
+  pipeline::process(
+    &args.input_paths,
+    &args.options,
+    &config,
+    &mut stats,
+    |_| Ok(()),
+  )?;
+  if args.json {
+    println!("{}", serde_json::to_string_pretty(&stats)?);
+  } else {
+    print!("{}", stats.report());
+  }
+  args
+    .options
+    .thresholds()
+    .check_rate(stats.rejected(), stats.total)
+}
@@ -0,0 +1,44 @@
+//! `mobcsv validate`, checks that every record is acceptable.
+
+use std::path::PathBuf;
+
+use failure::Error;
+use structopt::StructOpt;
+
+use super::{Options, ReportArgs};
+use crate::{pipeline, reject::RejectError, Config};
+
+#[derive(Debug, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct Args {
+  #[structopt(flatten)]
+  pub reports: ReportArgs,
+  #[structopt(flatten)]
+  pub options: Options,
+  /// The input CSV files paths or glob patterns (e.g. `exports/*.csv`), `-`
+  /// reads the standard input
+  #[structopt(parse(from_os_str), raw(required = "true"))]
+  pub input_paths: Vec<PathBuf>,
+}
+
+pub fn run(args: &Args) -> Result<(), Error> {
+  check(&args.options, &args.input_paths, &args.reports)
+}
+
+/// Validates all the records of the `inputs`, failing if any is rejected.
+pub fn check(
+  options: &Options,
+  inputs: &[PathBuf],
+  reports: &ReportArgs,
+) -> Result<(), Error> {
+  let config = Config::from_options(options)?;
+  let mut stats = reports.stats();
+  pipeline::process(inputs, options, &config, &mut stats, |_| Ok(()))?;
+  options.summarize(&stats, |msg| println!("{}", msg));
+  let files: Vec<_> = inputs.iter().map(PathBuf::as_path).collect();
+  reports.write(&stats, &files)?;
+  if stats.rejected() > 0 {
+    return Err(RejectError::rejected(stats.rejected()));
+  }
+  Ok(())
+}
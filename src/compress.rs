@@ -0,0 +1,129 @@
+//! The compression of the input and output streams.
+
+use std::{
+  io::{self, BufRead, Read, Write},
+  path::Path,
+  str::FromStr,
+};
+
+use failure::Error;
+
+/// The compression of a stream.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Compression {
+  #[default]
+  None,
+  Gzip,
+  Zstd,
+}
+
+impl Compression {
+  pub const VARIANTS: &'static [&'static str] = &["none", "gzip", "zstd"];
+
+  /// The compression of the file at `path` by its extension.
+  pub fn of(path: &Path) -> Self {
+    match path.extension().and_then(|ext| ext.to_str()) {
+      Some("gz") => Compression::Gzip,
+      Some("zst") => Compression::Zstd,
+      _ => Compression::None,
+    }
+  }
+
+  /// The compression of a stream by its first bytes, `head`.
+  pub fn sniff(head: &[u8]) -> Self {
+    if head.starts_with(&[0x1f, 0x8b]) {
+      Compression::Gzip
+    } else if head.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+      Compression::Zstd
+    } else {
+      Compression::None
+    }
+  }
+
+  /// Decompresses the `reader`, detecting its compression by its first
+  /// bytes.
+  pub fn decoder<'a, R: BufRead + 'a>(
+    mut reader: R,
+  ) -> Result<Box<dyn Read + 'a>, Error> {
+    Ok(match Self::sniff(reader.fill_buf()?) {
+      Compression::None => Box::new(reader),
+      Compression::Gzip => {
+        Box::new(flate2::bufread::MultiGzDecoder::new(reader))
+      },
+      Compression::Zstd => Box::new(zstd::Decoder::with_buffer(reader)?),
+    })
+  }
+
+  /// Compresses the `writer`, the compressed stream ends when it is dropped.
+  pub fn encoder<'a, W: Write + Send + 'a>(
+    self,
+    writer: W,
+  ) -> io::Result<Box<dyn Write + Send + 'a>> {
+    Ok(match self {
+      Compression::None => Box::new(writer),
+      Compression::Gzip => Box::new(flate2::write::GzEncoder::new(
+        writer,
+        flate2::Compression::default(),
+      )),
+      Compression::Zstd => {
+        Box::new(zstd::Encoder::new(writer, 0)?.auto_finish())
+      },
+    })
+  }
+}
+
+impl FromStr for Compression {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "none" => Ok(Compression::None),
+      "gzip" => Ok(Compression::Gzip),
+      "zstd" => Ok(Compression::Zstd),
+      _ => Err(format!("unknown compression `{}`", s)),
+    }
+  }
+}
+
+/// The `path` without its compression extension, like `dump.csv` for
+/// `dump.csv.gz`.
+pub fn strip(path: &Path) -> &Path {
+  match Compression::of(path) {
+    Compression::None => path,
+    _ => Path::new(path.file_stem().unwrap_or_default()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn should_round_trip_streams() {
+    for &compression in
+      &[Compression::None, Compression::Gzip, Compression::Zstd]
+    {
+      let mut out = Vec::new();
+      {
+        let mut encoder = compression.encoder(&mut out).unwrap();
+        encoder.write_all(b"ph,name\n").unwrap();
+      }
+      assert_eq!(Compression::sniff(&out), compression);
+      let mut decoded = String::new();
+      Compression::decoder(&out[..])
+        .unwrap()
+        .read_to_string(&mut decoded)
+        .unwrap();
+      assert_eq!(decoded, "ph,name\n");
+    }
+  }
+
+  #[test]
+  fn should_detect_extensions() {
+    let path = Path::new("exports/dump.csv.gz");
+    assert_eq!(Compression::of(path), Compression::Gzip);
+    assert_eq!(Compression::of(Path::new("a.zst")), Compression::Zstd);
+    assert_eq!(strip(path), Path::new("dump.csv"));
+    assert_eq!(strip(Path::new("a.csv")), Path::new("a.csv"));
+  }
+}
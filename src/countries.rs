@@ -0,0 +1,168 @@
+//! The dialing rules of every country we know how to normalize and
+//! validate mobile numbers for.
+
+use serde::Deserialize;
+
+use crate::{reject::RejectReason, rules};
+
+/// A single row of the country rule table, see the `[[country]]` sections of
+/// the rules file.
+#[derive(Debug, Deserialize)]
+pub struct Country {
+  /// The ISO 3166-1 alpha-2 code of the country.
+  pub iso: String,
+  /// The English short name of the country.
+  pub name: String,
+  /// The international dialing code, without the leading `+` or `00`.
+  pub dialing_code: String,
+  /// The trunk prefix dialed before national numbers inside the country, if
+  /// the country uses one.
+  pub trunk_prefix: Option<String>,
+  /// Leading digits that identify a bare national number (no dialing code,
+  /// no trunk prefix) as belonging to this country.
+  #[serde(default)]
+  pub local_prefixes: Vec<String>,
+  /// Allowed lengths of the national significant number, that is the number
+  /// without the dialing code.
+  pub lengths: Vec<usize>,
+  /// How the digits of the national significant number are grouped when
+  /// written for humans.
+  #[serde(default)]
+  pub groups: Vec<usize>,
+}
+
+impl Country {
+  /// Does `national` look like a national number of this country?
+  pub fn is_local(&self, national: &str) -> bool {
+    self.has_valid_length(national)
+      && self.local_prefixes.iter().any(|p| national.starts_with(p))
+  }
+
+  /// Checks the length of the international number `ph` of this country.
+  pub fn check_length(&self, ph: &str) -> Result<(), RejectReason> {
+    let national = &ph[self.dialing_code.len()..];
+    RejectReason::check_length(national.len(), &self.lengths)
+  }
+
+  fn has_valid_length(&self, national: &str) -> bool {
+    self.lengths.contains(&national.len())
+  }
+}
+
+/// All the countries we support, the order matters when trying to guess the
+/// country of a bare national number.
+pub fn all() -> &'static [Country] {
+  &rules::get().countries
+}
+
+/// Finds a country by its ISO 3166-1 alpha-2 code, ignoring case.
+pub fn by_iso<'a>(
+  countries: &'a [Country],
+  iso: &str,
+) -> Result<&'a Country, String> {
+  countries
+    .iter()
+    .find(|c| c.iso.eq_ignore_ascii_case(iso))
+    .ok_or_else(|| {
+      let known: Vec<_> = countries.iter().map(|c| c.iso.as_str()).collect();
+      format!(
+        "unknown country `{}`, expected one of {}",
+        iso,
+        known.join(", ")
+      )
+    })
+}
+
+/// Finds a country by its international dialing code, with or without the
+/// leading `+`.
+pub fn by_dialing_code<'a>(
+  countries: &'a [Country],
+  code: &str,
+) -> Result<&'a Country, String> {
+  let code = code.trim_start_matches('+');
+  countries
+    .iter()
+    .find(|c| c.dialing_code == code)
+    .ok_or_else(|| {
+      let known: Vec<_> =
+        countries.iter().map(|c| c.dialing_code.as_str()).collect();
+      format!(
+        "unknown dialing code `{}`, expected one of {}",
+        code,
+        known.join(", ")
+      )
+    })
+}
+
+/// Finds the country of a bare national number.
+pub fn find_local(national: &str) -> Option<&'static Country> {
+  all().iter().find(|c| c.is_local(national))
+}
+
+/// Finds the country of an international number by its dialing code only,
+/// without checking the number length.
+pub fn of(ph: &str) -> Option<&'static Country> {
+  all().iter().find(|c| ph.starts_with(&c.dialing_code))
+}
+
+/// Finds the country of a fully qualified international number, checking
+/// that it is digits only and has a valid length.
+pub fn check(ph: &str) -> Result<&'static Country, RejectReason> {
+  if ph.is_empty() {
+    return Err(RejectReason::EmptyPhone);
+  }
+  if !ph.bytes().all(|b| b.is_ascii_digit()) {
+    return Err(RejectReason::NonDigitChars);
+  }
+  let country = of(ph).ok_or(RejectReason::UnknownCountry)?;
+  country.check_length(ph)?;
+  Ok(country)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn should_find_gcc_numbers() {
+    assert_eq!(check("971501234567").unwrap().dialing_code, "971");
+    assert_eq!(check("96550123456").unwrap().dialing_code, "965");
+    assert_eq!(check("97433123456").unwrap().dialing_code, "974");
+    assert_eq!(check("97336123456").unwrap().dialing_code, "973");
+    assert_eq!(check("96891234567").unwrap().dialing_code, "968");
+    assert_eq!(check("962791234567").unwrap().dialing_code, "962");
+  }
+
+  #[test]
+  fn should_check_lengths_per_country() {
+    assert!(check("97150123456").is_err());
+    assert!(check("9655012345678").is_err());
+    assert!(check("2011166130611").is_err());
+    assert!(check("9665116613").is_err());
+    assert_eq!(
+      check("96250abc1234").unwrap_err(),
+      RejectReason::NonDigitChars
+    );
+  }
+
+  #[test]
+  fn should_find_by_iso() {
+    assert_eq!(by_iso(all(), "eg").unwrap().dialing_code, "20");
+    assert_eq!(by_iso(all(), "SA").unwrap().dialing_code, "966");
+    assert!(by_iso(all(), "XX").is_err());
+  }
+
+  #[test]
+  fn should_find_by_dialing_code() {
+    assert_eq!(by_dialing_code(all(), "20").unwrap().iso, "EG");
+    assert_eq!(by_dialing_code(all(), "+971").unwrap().iso, "AE");
+    assert!(by_dialing_code(all(), "1").is_err());
+  }
+
+  #[test]
+  fn should_find_local_numbers() {
+    assert_eq!(find_local("1116613061").unwrap().dialing_code, "20");
+    assert_eq!(find_local("540029129").unwrap().dialing_code, "966");
+    assert!(find_local("1232131").is_none());
+  }
+}
@@ -0,0 +1,227 @@
+//! Removing the records of already seen phone numbers.
+
+use std::{
+  collections::{hash_map::DefaultHasher, HashMap, HashSet},
+  hash::{Hash, Hasher},
+  str::FromStr,
+};
+
+use crate::Accepted;
+
+/// Which record of a duplicate phone number is kept.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Keep {
+  #[default]
+  First,
+  Last,
+  /// The record with the highest `count`, the first one of them on ties.
+  MaxCount,
+  /// The first record, with the sum of all the counts, and the first
+  /// non-empty name.
+  Sum,
+}
+
+impl Keep {
+  pub const VARIANTS: &'static [&'static str] =
+    &["first", "last", "max-count", "sum"];
+}
+
+impl FromStr for Keep {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "first" => Ok(Keep::First),
+      "last" => Ok(Keep::Last),
+      "max-count" => Ok(Keep::MaxCount),
+      "sum" => Ok(Keep::Sum),
+      _ => Err(format!("unknown dedupe strategy `{}`", s)),
+    }
+  }
+}
+
+/// The phone numbers seen so far.
+#[derive(Debug)]
+pub enum Seen {
+  Exact(HashSet<String>),
+  Bloom(Bloom),
+}
+
+impl Seen {
+  /// Inserts the phone number `ph`, returning whether it is new, probably
+  /// with the bloom filter.
+  pub fn insert(&mut self, ph: &str) -> bool {
+    match self {
+      Seen::Exact(seen) => seen.insert(ph.to_owned()),
+      Seen::Bloom(bloom) => !bloom.insert(ph),
+    }
+  }
+}
+
+/// Keeps one record of every phone number.
+///
+/// With [`Keep::First`] the records are passed through as they come, the
+/// other strategies hold all of them in memory until [`Dedupe::finish`].
+#[derive(Debug)]
+pub struct Dedupe {
+  keep: Keep,
+  seen: Seen,
+  /// The records held until the end, with the index of every phone number.
+  kept: Vec<Accepted>,
+  index: HashMap<String, usize>,
+}
+
+impl Dedupe {
+  pub fn new(keep: Keep) -> Self {
+    Dedupe {
+      keep,
+      seen: Seen::Exact(HashSet::new()),
+      kept: Vec::new(),
+      index: HashMap::new(),
+    }
+  }
+
+  /// Keeps the first records using a bloom filter sized for `items` phone
+  /// numbers, using much less memory, but dropping about 1% of the unique
+  /// numbers as false duplicates.
+  pub fn with_bloom(items: usize) -> Self {
+    Dedupe {
+      seen: Seen::Bloom(Bloom::new(items)),
+      ..Dedupe::new(Keep::First)
+    }
+  }
+
+  /// Returns the record if it is to be written right away.
+  pub fn push(&mut self, accepted: Accepted) -> Option<Accepted> {
+    let ph = &accepted.record.ph;
+    if self.keep == Keep::First {
+      let is_new = self.seen.insert(ph);
+      return if is_new { Some(accepted) } else { None };
+    }
+    match self.index.get(ph) {
+      Some(&i) => {
+        let kept = &mut self.kept[i];
+        match self.keep {
+          Keep::Sum => {
+            let record = &mut kept.record;
+            // the counts can't go beyond the largest `count` column value.
+            record.count = record.count.saturating_add(accepted.record.count);
+            if record.name.is_empty() {
+              record.name = accepted.record.name;
+            }
+          },
+          Keep::MaxCount if accepted.record.count <= kept.record.count => {},
+          _ => *kept = accepted,
+        }
+      },
+      None => {
+        self.index.insert(ph.clone(), self.kept.len());
+        self.kept.push(accepted);
+      },
+    }
+    None
+  }
+
+  /// The records held until the end, in the order of the first record of
+  /// their phone numbers.
+  pub fn finish(self) -> Vec<Accepted> {
+    self.kept
+  }
+}
+
+/// A probabilistic set of strings, with false positives but no false
+/// negatives.
+#[derive(Debug)]
+pub struct Bloom {
+  bits: Vec<u64>,
+}
+
+impl Bloom {
+  /// The hashes of every item, optimal for a 1% false positive rate.
+  const HASHES: u64 = 7;
+
+  /// A filter of `items` strings with a false positive rate of about 1%.
+  pub fn new(items: usize) -> Self {
+    // about 9.6 bits per item for a 1% false positive rate.
+    let len = (items.max(1) * 10).div_ceil(64);
+    Bloom { bits: vec![0; len] }
+  }
+
+  /// Inserts the `item`, returning whether it was probably there already.
+  pub fn insert(&mut self, item: &str) -> bool {
+    let total = self.bits.len() as u64 * 64;
+    let (h1, h2) = (hash(item, 0), hash(item, 1));
+    let mut found = true;
+    for i in 0..Self::HASHES {
+      let bit = h1.wrapping_add(i.wrapping_mul(h2)) % total;
+      let (word, mask) = ((bit / 64) as usize, 1 << (bit % 64));
+      found &= self.bits[word] & mask != 0;
+      self.bits[word] |= mask;
+    }
+    found
+  }
+}
+
+fn hash(item: &str, seed: u8) -> u64 {
+  let mut hasher = DefaultHasher::new();
+  seed.hash(&mut hasher);
+  item.hash(&mut hasher);
+  hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::Record;
+
+  fn accepted(ph: &str, name: &str, count: u64) -> Accepted {
+    Accepted {
+      record: Record::new(ph, name, count),
+      original_ph: ph.to_owned(),
+      fixed: false,
+      extracted: false,
+    }
+  }
+
+  fn dedupe(mut dedupe: Dedupe) -> Vec<String> {
+    let records = vec![
+      accepted("201116613061", "a", 1),
+      accepted("966540029129", "b", 5),
+      accepted("201116613061", "c", 3),
+      accepted("201116613061", "d", 2),
+    ];
+    let mut out: Vec<_> =
+      records.into_iter().filter_map(|r| dedupe.push(r)).collect();
+    out.extend(dedupe.finish());
+    out.into_iter().map(|r| r.record.name).collect()
+  }
+
+  #[test]
+  fn should_keep_records() {
+    assert_eq!(dedupe(Dedupe::new(Keep::First)), ["a", "b"]);
+    assert_eq!(dedupe(Dedupe::new(Keep::Last)), ["d", "b"]);
+    assert_eq!(dedupe(Dedupe::new(Keep::MaxCount)), ["c", "b"]);
+    assert_eq!(dedupe(Dedupe::with_bloom(10)), ["a", "b"]);
+  }
+
+  #[test]
+  fn should_sum_counts() {
+    let mut dedupe = Dedupe::new(Keep::Sum);
+    dedupe.push(accepted("201116613061", "", 1));
+    dedupe.push(accepted("201116613061", "a", 3));
+    dedupe.push(accepted("201116613061", "b", u64::MAX));
+    let kept = dedupe.finish();
+    assert_eq!(kept.len(), 1);
+    assert_eq!(kept[0].record.name, "a");
+    assert_eq!(kept[0].record.count, u64::MAX);
+  }
+
+  #[test]
+  fn should_find_bloom_items() {
+    let mut bloom = Bloom::new(1000);
+    let items: Vec<_> = (0..1000).map(|i| format!("2011{:08}", i)).collect();
+    let found = items.iter().filter(|item| bloom.insert(item)).count();
+    assert!(found < 50, "{} false positives", found);
+    assert!(items.iter().all(|item| bloom.insert(item)));
+  }
+}
@@ -0,0 +1,156 @@
+//! The duplicated phone numbers of a run, with all the rows they were found
+//! in, for the data owners to pick the right one before deduping, and the
+//! ones found with different names, often recycled numbers or typos.
+
+use std::{
+  cmp::Reverse,
+  collections::{HashMap, HashSet},
+  path::Path,
+};
+
+use failure::{Error, ResultExt};
+
+use crate::Accepted;
+
+/// The columns of the reports.
+const HEADERS: &[&str] = &["ph", "records", "path", "line", "name", "count"];
+
+/// An input row of an accepted phone number.
+#[derive(Debug)]
+struct Source {
+  path: String,
+  line: u64,
+  name: String,
+  count: u64,
+}
+
+/// The rows of every accepted phone number, held until the end of the run.
+#[derive(Debug, Default)]
+pub struct Dups {
+  rows: HashMap<String, Vec<Source>>,
+}
+
+impl Dups {
+  /// Adds the row of the `accepted` record, at the `line` of its input.
+  pub fn push(&mut self, accepted: &Accepted, path: &Path, line: u64) {
+    let record = &accepted.record;
+    let rows = self.rows.entry(record.ph.clone()).or_default();
+    rows.push(Source {
+      path: path.display().to_string(),
+      line,
+      name: record.name.clone(),
+      count: record.count,
+    });
+  }
+
+  pub fn merge(&mut self, other: Dups) {
+    for (ph, rows) in other.rows {
+      self.rows.entry(ph).or_default().extend(rows);
+    }
+  }
+
+  /// Writes the phone numbers found more than once, the most repeated ones
+  /// first, with a row by source row to the CSV file at `path`.
+  pub fn write(&self, path: &Path) -> Result<(), Error> {
+    write(path, self.rows.iter().filter(|(_, rows)| rows.len() > 1))
+  }
+
+  /// Writes the phone numbers found with different names, ignoring their
+  /// case and spaces and the empty ones, like `write`.
+  pub fn write_conflicts(&self, path: &Path) -> Result<(), Error> {
+    let conflicts = self.rows.iter().filter(|(_, rows)| {
+      let names: HashSet<_> = rows
+        .iter()
+        .map(|row| normalize(&row.name))
+        .filter(|name| !name.is_empty())
+        .collect();
+      names.len() > 1
+    });
+    write(path, conflicts)
+  }
+}
+
+/// The `name` compared to the others, in lowercase with single spaces.
+fn normalize(name: &str) -> String {
+  let words: Vec<_> = name.split_whitespace().collect();
+  words.join(" ").to_lowercase()
+}
+
+/// Writes the rows of the phone numbers to the CSV file at `path`.
+fn write<'a, I>(path: &Path, rows: I) -> Result<(), Error>
+where
+  I: Iterator<Item = (&'a String, &'a Vec<Source>)>,
+{
+  let mut wrt = csv::Writer::from_path(path)
+    .with_context(|_| format!("could not create report {:?}", path))?;
+  wrt.write_record(HEADERS)?;
+  let mut dups: Vec<_> = rows.collect();
+  dups.sort_by_key(|&(ph, rows)| (Reverse(rows.len()), ph));
+  for (ph, rows) in dups {
+    let records = rows.len().to_string();
+    for row in rows {
+      wrt.write_record([
+        ph,
+        &records,
+        &row.path,
+        &row.line.to_string(),
+        &row.name,
+        &row.count.to_string(),
+      ])?;
+    }
+  }
+  wrt.flush()?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::Record;
+
+  #[test]
+  fn should_write_the_duplicates() {
+    let mut dups = Dups::default();
+    let rows = [("201116613061", "a", 2), ("966540029129", "b", 3)];
+    for (i, &(ph, name, line)) in rows.iter().enumerate() {
+      let accepted = Accepted {
+        record: Record::new(ph, name, i as u64),
+        original_ph: ph.to_owned(),
+        fixed: false,
+        extracted: false,
+      };
+      dups.push(&accepted, Path::new("a.csv"), line);
+    }
+    let mut other = Dups::default();
+    let accepted = Accepted {
+      record: Record::new("201116613061", "c", 5),
+      original_ph: String::new(),
+      fixed: false,
+      extracted: false,
+    };
+    other.push(&accepted, Path::new("b.csv"), 7);
+    dups.merge(other);
+    let path = std::env::temp_dir().join("mobcsv-dups.csv");
+    dups.write(&path).unwrap();
+    assert_eq!(
+      std::fs::read_to_string(&path).unwrap(),
+      "ph,records,path,line,name,count\n\
+       201116613061,2,a.csv,2,a,0\n\
+       201116613061,2,b.csv,7,c,5\n"
+    );
+    let accepted = Accepted {
+      record: Record::new("966540029129", " B ", 1),
+      original_ph: String::new(),
+      fixed: false,
+      extracted: false,
+    };
+    dups.push(&accepted, Path::new("b.csv"), 8);
+    dups.write_conflicts(&path).unwrap();
+    assert_eq!(
+      std::fs::read_to_string(&path).unwrap(),
+      "ph,records,path,line,name,count\n\
+       201116613061,2,a.csv,2,a,0\n\
+       201116613061,2,b.csv,7,c,5\n"
+    );
+  }
+}
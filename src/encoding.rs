@@ -0,0 +1,278 @@
+//! The text encodings of the inputs, transcoded to UTF-8 before they are
+//! parsed, and of the text outputs.
+
+use std::{
+  cmp,
+  io::{self, BufRead, BufReader, Read, Write},
+  str::FromStr,
+};
+
+use encoding_rs::{
+  Decoder, Encoder, EncoderResult, UTF_16LE, UTF_8, WINDOWS_1256,
+};
+use failure::Error;
+use log::info;
+
+/// The UTF-8 byte order mark, left by some spreadsheet exports.
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// The encoding of an input.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Encoding {
+  /// Read as it is, without its byte order mark.
+  #[default]
+  Utf8,
+  /// The Windows-1256 Arabic code page of the legacy systems.
+  Cp1256,
+  /// UTF-16 little endian, with or without its byte order mark.
+  Utf16Le,
+  /// Detected from the byte order mark, or UTF-8 when the first bytes are
+  /// valid UTF-8, Windows-1256 otherwise.
+  Auto,
+}
+
+impl Encoding {
+  pub const VARIANTS: &'static [&'static str] =
+    &["utf-8", "cp1256", "utf-16le", "auto"];
+}
+
+impl FromStr for Encoding {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "utf-8" => Ok(Encoding::Utf8),
+      "cp1256" => Ok(Encoding::Cp1256),
+      "utf-16le" => Ok(Encoding::Utf16Le),
+      "auto" => Ok(Encoding::Auto),
+      _ => Err(format!("unknown encoding `{}`", s)),
+    }
+  }
+}
+
+/// The encoding of the text outputs.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OutputEncoding {
+  #[default]
+  Utf8,
+  /// UTF-8 starting with its byte order mark, for Excel to know it is.
+  Utf8Bom,
+  /// The Windows-1256 Arabic code page, the characters it doesn't have
+  /// written as `?`.
+  Cp1256,
+}
+
+impl OutputEncoding {
+  pub const VARIANTS: &'static [&'static str] =
+    &["utf-8", "utf-8-bom", "cp1256"];
+
+  /// The encoding of the rows appended to an output, after its byte order
+  /// mark.
+  pub fn appended(self) -> Self {
+    match self {
+      OutputEncoding::Utf8Bom => OutputEncoding::Utf8,
+      encoding => encoding,
+    }
+  }
+}
+
+impl FromStr for OutputEncoding {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "utf-8" => Ok(OutputEncoding::Utf8),
+      "utf-8-bom" => Ok(OutputEncoding::Utf8Bom),
+      "cp1256" => Ok(OutputEncoding::Cp1256),
+      _ => Err(format!("unknown output encoding `{}`", s)),
+    }
+  }
+}
+
+/// The UTF-8 text of the `reader` in the `encoding`, read as it is when it
+/// already is UTF-8.
+pub fn decode<'a, R: BufRead + 'a>(
+  mut reader: R,
+  encoding: Encoding,
+) -> Result<Box<dyn BufRead + 'a>, Error> {
+  let decoder = match encoding {
+    Encoding::Utf8 => None,
+    Encoding::Cp1256 => Some(WINDOWS_1256.new_decoder_with_bom_removal()),
+    Encoding::Utf16Le => Some(UTF_16LE.new_decoder_with_bom_removal()),
+    Encoding::Auto => {
+      let start = reader.fill_buf()?;
+      let bom = encoding_rs::Encoding::for_bom(start);
+      match std::str::from_utf8(start) {
+        _ if bom.is_some() => Some(UTF_8.new_decoder()),
+        // the last character may be cut by the end of the buffer.
+        Err(e) if e.error_len().is_some() => {
+          info!("Detected the Windows-1256 encoding");
+          Some(WINDOWS_1256.new_decoder())
+        },
+        _ => None,
+      }
+    },
+  };
+  let decoder = match decoder {
+    Some(decoder) => decoder,
+    None => {
+      if reader.fill_buf()?.starts_with(UTF8_BOM) {
+        reader.consume(UTF8_BOM.len());
+      }
+      return Ok(Box::new(reader));
+    },
+  };
+  let transcode = Transcode {
+    inner: reader,
+    decoder,
+    out: Vec::new(),
+    pos: 0,
+    done: false,
+  };
+  Ok(Box::new(BufReader::new(transcode)))
+}
+
+/// A reader of the UTF-8 text of another one.
+struct Transcode<R> {
+  inner: R,
+  decoder: Decoder,
+  /// The text decoded and not read yet, from `pos`.
+  out: Vec<u8>,
+  pos: usize,
+  done: bool,
+}
+
+impl<R: BufRead> Read for Transcode<R> {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    while self.pos == self.out.len() && !self.done {
+      let input = self.inner.fill_buf()?;
+      let last = input.is_empty();
+      let len = self
+        .decoder
+        .max_utf8_buffer_length(input.len())
+        .unwrap_or(input.len() * 3 + 16);
+      self.out.resize(len, 0);
+      // the invalid bytes are replaced with U+FFFD.
+      let (_, read, written, _) =
+        self.decoder.decode_to_utf8(input, &mut self.out, last);
+      self.out.truncate(written);
+      self.pos = 0;
+      self.inner.consume(read);
+      self.done = last;
+    }
+    let n = cmp::min(buf.len(), self.out.len() - self.pos);
+    buf[..n].copy_from_slice(&self.out[self.pos..self.pos + n]);
+    self.pos += n;
+    Ok(n)
+  }
+}
+
+/// Writes the UTF-8 text to the `writer` in the `encoding`, starting with
+/// the byte order mark of `utf-8-bom`.
+pub fn encode<'a, W: Write + Send + 'a>(
+  mut writer: W,
+  encoding: OutputEncoding,
+) -> io::Result<Box<dyn Write + Send + 'a>> {
+  Ok(match encoding {
+    OutputEncoding::Utf8 => Box::new(writer),
+    OutputEncoding::Utf8Bom => {
+      writer.write_all(UTF8_BOM)?;
+      Box::new(writer)
+    },
+    OutputEncoding::Cp1256 => Box::new(Encode {
+      inner: writer,
+      encoder: WINDOWS_1256.new_encoder(),
+      pending: Vec::new(),
+      out: Vec::new(),
+    }),
+  })
+}
+
+/// A writer of the UTF-8 text to another one, in another encoding.
+struct Encode<W> {
+  inner: W,
+  encoder: Encoder,
+  /// The start of a character cut by the end of the last write.
+  pending: Vec<u8>,
+  out: Vec<u8>,
+}
+
+impl<W: Write> Write for Encode<W> {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    self.pending.extend_from_slice(buf);
+    let valid = match std::str::from_utf8(&self.pending) {
+      Ok(text) => text.len(),
+      Err(e) if e.error_len().is_none() => e.valid_up_to(),
+      Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+    };
+    let bytes = std::mem::take(&mut self.pending);
+    // checked above.
+    let mut text = std::str::from_utf8(&bytes[..valid]).unwrap_or_default();
+    let len = self
+      .encoder
+      .max_buffer_length_from_utf8_without_replacement(text.len())
+      .unwrap_or(text.len() + 16);
+    self.out.resize(len.max(16), 0);
+    loop {
+      let (result, read, written) = self
+        .encoder
+        .encode_from_utf8_without_replacement(text, &mut self.out, false);
+      self.inner.write_all(&self.out[..written])?;
+      text = &text[read..];
+      match result {
+        EncoderResult::InputEmpty => break,
+        EncoderResult::OutputFull => {},
+        EncoderResult::Unmappable(_) => self.inner.write_all(b"?")?,
+      }
+    }
+    self.pending.extend_from_slice(&bytes[valid..]);
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.inner.flush()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn read(bytes: &[u8], encoding: Encoding) -> String {
+    let mut text = String::new();
+    decode(bytes, encoding)
+      .unwrap()
+      .read_to_string(&mut text)
+      .unwrap();
+    text
+  }
+
+  #[test]
+  fn should_decode_the_inputs() {
+    assert_eq!(read(b"\xEF\xBB\xBFph\n", Encoding::Utf8), "ph\n");
+    assert_eq!(read(b"\xE3\xCD\xE3\xCF\n", Encoding::Cp1256), "محمد\n");
+    assert_eq!(read(b"\xFF\xFEp\0h\0", Encoding::Utf16Le), "ph");
+    assert_eq!(read(b"\xFF\xFEp\0h\0", Encoding::Auto), "ph");
+    assert_eq!(read(b"\xE3\xCD\xE3\xCF\n", Encoding::Auto), "محمد\n");
+    assert_eq!(read("محمد\n".as_bytes(), Encoding::Auto), "محمد\n");
+    assert_eq!("utf-16le".parse(), Ok(Encoding::Utf16Le));
+  }
+
+  #[test]
+  fn should_encode_the_outputs() {
+    let mut out = Vec::new();
+    let mut wrt = encode(&mut out, OutputEncoding::Cp1256).unwrap();
+    let text = "محمد✓\n".as_bytes();
+    // a character cut between two writes.
+    wrt.write_all(&text[..3]).unwrap();
+    wrt.write_all(&text[3..]).unwrap();
+    drop(wrt);
+    assert_eq!(out, b"\xE3\xCD\xE3\xCF?\n");
+    let mut out = Vec::new();
+    encode(&mut out, OutputEncoding::Utf8Bom)
+      .unwrap()
+      .write_all(b"ph")
+      .unwrap();
+    assert_eq!(out, b"\xEF\xBB\xBFph");
+  }
+}
@@ -0,0 +1,99 @@
+//! How a normalized phone number is written to the output.
+
+use std::str::FromStr;
+
+use crate::countries;
+
+/// The output formats of the phone number.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Format {
+  /// `+201116613061`
+  E164,
+  /// `+20 111 661 3061`
+  International,
+  /// `01116613061`
+  National,
+  /// `201116613061`
+  #[default]
+  Digits,
+}
+
+impl Format {
+  pub const VARIANTS: &'static [&'static str] =
+    &["e164", "international", "national", "digits"];
+
+  /// Formats a normalized number, that is digits only with the country
+  /// dialing code.
+  ///
+  /// Numbers of countries we have no rules for are written in E.164 for all
+  /// the formats but `digits`.
+  pub fn apply(self, ph: &str) -> String {
+    let country = match (self, countries::of(ph)) {
+      (Format::Digits, _) => return ph.to_owned(),
+      (Format::E164, _) | (_, None) => return format!("+{}", ph),
+      (_, Some(country)) => country,
+    };
+    let national = &ph[country.dialing_code.len()..];
+    match self {
+      Format::National => {
+        country.trunk_prefix.clone().unwrap_or_default() + national
+      },
+      _ => {
+        let mut out = format!("+{}", country.dialing_code);
+        let grouped = country.groups.iter().sum::<usize>() == national.len();
+        if grouped {
+          let mut rest = national;
+          for &size in &country.groups {
+            let (group, tail) = rest.split_at(size);
+            out.push(' ');
+            out.push_str(group);
+            rest = tail;
+          }
+        } else {
+          out.push(' ');
+          out.push_str(national);
+        }
+        out
+      },
+    }
+  }
+}
+
+impl FromStr for Format {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "e164" => Ok(Format::E164),
+      "international" => Ok(Format::International),
+      "national" => Ok(Format::National),
+      "digits" => Ok(Format::Digits),
+      _ => Err(format!("unknown format `{}`", s)),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn should_format_egyptian_numbers() {
+    let ph = "201116613061";
+    assert_eq!(Format::E164.apply(ph), "+201116613061");
+    assert_eq!(Format::International.apply(ph), "+20 111 661 3061");
+    assert_eq!(Format::National.apply(ph), "01116613061");
+    assert_eq!(Format::Digits.apply(ph), "201116613061");
+  }
+
+  #[test]
+  fn should_format_per_country() {
+    assert_eq!(
+      Format::International.apply("966540029129"),
+      "+966 54 002 9129"
+    );
+    assert_eq!(Format::International.apply("96550123456"), "+965 5012 3456");
+    assert_eq!(Format::National.apply("96550123456"), "50123456");
+    assert_eq!(Format::International.apply("14155552671"), "+14155552671");
+  }
+}
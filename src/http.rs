@@ -0,0 +1,64 @@
+//! Reading the input files from HTTP(S) URLs, streamed as they download.
+
+use std::{io::Read, path::Path};
+
+use failure::{bail, Error, ResultExt};
+use log::info;
+
+/// Whether the input `path` is an `http://` or `https://` URL.
+pub fn is_url(path: &Path) -> bool {
+  let s = path.to_string_lossy();
+  s.starts_with("http://") || s.starts_with("https://")
+}
+
+/// Parses a `Name: value` request header of `--header`.
+pub fn parse_header(s: &str) -> Result<(String, String), String> {
+  match s.split_once(':') {
+    Some((name, value)) if !name.trim().is_empty() => {
+      Ok((name.trim().to_owned(), value.trim().to_owned()))
+    },
+    _ => Err(format!("expected `Name: value`, got `{}`", s)),
+  }
+}
+
+/// Starts downloading the `url` with the request `headers`, returning the
+/// body with its length, if known.
+pub fn open(
+  url: &Path,
+  headers: &[(String, String)],
+) -> Result<(Box<dyn Read>, Option<u64>), Error> {
+  let url = url.to_string_lossy();
+  info!("Downloading {}", url);
+  let mut request = ureq::get(url.as_ref());
+  for (name, value) in headers {
+    request = request.header(name, value);
+  }
+  let response = match request.call() {
+    Ok(response) => response,
+    Err(ureq::Error::StatusCode(status)) => {
+      bail!("could not download {}: HTTP status {}", url, status)
+    },
+    Err(e) => {
+      return Err(e).with_context(|_| format!("could not download {}", url))?
+    },
+  };
+  let body = response.into_body();
+  let len = body.content_length();
+  Ok((Box::new(body.into_reader()), len))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn should_parse_urls_and_headers() {
+    assert!(is_url(Path::new("https://example.com/export.csv")));
+    assert!(!is_url(Path::new("exports/https.csv")));
+    assert_eq!(
+      parse_header("Authorization: Bearer abc"),
+      Ok(("Authorization".to_owned(), "Bearer abc".to_owned()))
+    );
+    assert!(parse_header("nope").is_err());
+  }
+}
@@ -0,0 +1,102 @@
+//! Enriching the records with the columns of another CSV file.
+
+use std::{collections::HashMap, fs::File, io::BufReader, path::Path};
+
+use failure::{bail, Error, ResultExt};
+use log::info;
+
+use crate::{lists, Config};
+
+/// The columns of a secondary CSV file, by its normalized phone numbers.
+#[derive(Debug, Default)]
+pub struct Join {
+  /// The names of the joined columns, in order.
+  pub columns: Vec<String>,
+  rows: HashMap<String, Vec<String>>,
+}
+
+impl Join {
+  /// Reads the `columns` of the CSV file at `path`, all of them but the
+  /// `key` one when empty, by its `key` column phone numbers normalized with
+  /// the `config`.
+  ///
+  /// The first row of a phone number wins, and the rows with an invalid
+  /// phone number are skipped.
+  pub fn load(
+    path: &Path,
+    key: &str,
+    columns: &[String],
+    config: &Config,
+  ) -> Result<Self, Error> {
+    info!("Loading the joined file {:?}", path);
+    let file = File::open(path)
+      .with_context(|_| format!("could not open joined file {:?}", path))?;
+    let mut rdr = csv::Reader::from_reader(BufReader::new(file));
+    let headers = rdr.headers()?.clone();
+    let find = |name: &str| headers.iter().position(|h| h == name);
+    let key_index = match find(key) {
+      Some(i) => i,
+      None => bail!("{:?} has no `{}` join key column", path, key),
+    };
+    let columns: Vec<String> = if columns.is_empty() {
+      headers
+        .iter()
+        .filter(|&h| h != key)
+        .map(String::from)
+        .collect()
+    } else {
+      columns.to_vec()
+    };
+    let mut indexes = Vec::with_capacity(columns.len());
+    for column in &columns {
+      match find(column) {
+        Some(i) => indexes.push(i),
+        None => bail!("{:?} has no `{}` column to join", path, column),
+      }
+    }
+    let mut rows = HashMap::new();
+    for row in rdr.records() {
+      let row =
+        row.with_context(|_| format!("invalid joined file {:?}", path))?;
+      let ph = match lists::normalize(&row[key_index], config) {
+        Some(ph) => ph,
+        None => continue,
+      };
+      rows.entry(ph).or_insert_with(|| {
+        indexes.iter().map(|&i| row[i].to_owned()).collect()
+      });
+    }
+    Ok(Join { columns, rows })
+  }
+
+  /// The joined values of the normalized number `ph`, empty ones when it is
+  /// not in the joined file.
+  pub fn values(&self, ph: &str) -> Vec<&str> {
+    match self.rows.get(ph) {
+      Some(values) => values.iter().map(String::as_str).collect(),
+      None => vec![""; self.columns.len()],
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{env, fs};
+
+  use super::*;
+
+  #[test]
+  fn should_join_columns() {
+    let config = Config::default();
+    let path = env::temp_dir().join("mobcsv-join.csv");
+    fs::write(&path, "mobile,segment,city\n01116613061,vip,Cairo\n").unwrap();
+    let join = Join::load(&path, "mobile", &[], &config).unwrap();
+    assert_eq!(join.columns, ["segment", "city"]);
+    assert_eq!(join.values("201116613061"), ["vip", "Cairo"]);
+    assert_eq!(join.values("966540029129"), ["", ""]);
+    let join = Join::load(&path, "mobile", &["city".into()], &config).unwrap();
+    assert_eq!(join.values("201116613061"), ["Cairo"]);
+    assert!(Join::load(&path, "ph", &[], &config).is_err());
+    assert!(Join::load(&path, "mobile", &["nope".into()], &config).is_err());
+  }
+}
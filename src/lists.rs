@@ -0,0 +1,72 @@
+//! The reference lists of phone numbers, like the do-not-contact ones.
+
+use std::{collections::HashSet, fs::File, io::BufReader, path::Path};
+
+use failure::{Error, ResultExt};
+use log::{debug, info};
+
+use crate::Config;
+
+/// Reads the phone numbers of the list at `path`, normalized the same way as
+/// the records by the `config`.
+///
+/// The list is either a number per line, or a CSV file with a `ph` column.
+/// The invalid numbers of the list are skipped.
+pub fn load(path: &Path, config: &Config) -> Result<HashSet<String>, Error> {
+  info!("Loading the phone numbers list {:?}", path);
+  let file = File::open(path)
+    .with_context(|_| format!("could not open list file {:?}", path))?;
+  let mut rdr = csv::ReaderBuilder::new()
+    .has_headers(false)
+    .flexible(true)
+    .from_reader(BufReader::new(file));
+  let mut numbers = HashSet::new();
+  let mut column = None;
+  let mut skipped = 0;
+  for (i, row) in rdr.records().enumerate() {
+    let row = row.with_context(|_| format!("invalid list file {:?}", path))?;
+    if i == 0 {
+      column = row.iter().position(|f| f.trim().eq_ignore_ascii_case("ph"));
+      if column.is_some() {
+        continue;
+      }
+    }
+    match normalize(row.get(column.unwrap_or(0)).unwrap_or(""), config) {
+      Some(ph) => {
+        numbers.insert(ph);
+      },
+      None => skipped += 1,
+    }
+  }
+  debug!("Skipped {} invalid numbers of {:?}", skipped, path);
+  Ok(numbers)
+}
+
+/// Cleans and normalizes the reference number `ph` like the records ones,
+/// without the filters.
+pub fn normalize(ph: &str, config: &Config) -> Option<String> {
+  config.validator.normalize(&config.cleaner.clean(ph)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{env, fs};
+
+  use super::*;
+
+  #[test]
+  fn should_load_lists() {
+    let config = Config::default();
+    let dir = env::temp_dir();
+    let lines = dir.join("mobcsv-list-lines.txt");
+    fs::write(&lines, "+20 111 661 3061\n0540029129\nnope\n").unwrap();
+    let with_headers = dir.join("mobcsv-list-headers.csv");
+    fs::write(&with_headers, "name,ph\na,201116613061\n").unwrap();
+    let numbers = load(&lines, &config).unwrap();
+    assert_eq!(numbers.len(), 2);
+    assert!(numbers.contains("201116613061"));
+    let numbers = load(&with_headers, &config).unwrap();
+    assert!(numbers.contains("201116613061"));
+    assert_eq!(numbers.len(), 1);
+  }
+}
@@ -0,0 +1,295 @@
+//! The logs of the runs, as text for the people, or as JSON objects for
+//! the orchestrators running them.
+
+use std::{
+  fs::{self, File, OpenOptions},
+  io::{self, Write},
+  path::{Path, PathBuf},
+  str::FromStr,
+  sync::Mutex,
+  time::SystemTime,
+};
+
+use failure::{Error, ResultExt};
+use log::{
+  kv::{self, Key, Value, VisitSource},
+  Level, LevelFilter, Log, Metadata, Record,
+};
+use serde_json::{Map, Value as Json};
+
+use crate::commands::Options;
+
+/// The format of the log events.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+  /// A line of text by event.
+  Text,
+  /// A JSON object by line, with the `level`, `timestamp` and `message` of
+  /// the event, and its fields like the `line` and `reason` of a reject.
+  Json,
+}
+
+impl LogFormat {
+  pub const VARIANTS: &'static [&'static str] = &["text", "json"];
+}
+
+impl FromStr for LogFormat {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "text" => Ok(LogFormat::Text),
+      "json" => Ok(LogFormat::Json),
+      _ => Err(format!("unknown log format `{}`", s)),
+    }
+  }
+}
+
+/// The rotated log files kept, `mobcsv.log.1` being the latest one.
+const ROTATED: u32 = 5;
+
+/// Logs the events of mobcsv up to the `-v` level, and the warnings of its
+/// dependencies, to the standard error and to the `--log-file`, if any.
+pub fn setup(options: &Options) -> Result<(), Error> {
+  let file = match &options.log_file {
+    Some(path) => Some(LogFile::open(path, options.log_rotate_size)?),
+    None => None,
+  };
+  let level = if options.quiet {
+    LevelFilter::Error
+  } else {
+    options.verbosity.log_level().to_level_filter()
+  };
+  let logger = Logger {
+    level,
+    format: options.log_format,
+    file: file.map(Mutex::new),
+  };
+  log::set_boxed_logger(Box::new(logger))?;
+  log::set_max_level(level.max(dependencies(level)));
+  Ok(())
+}
+
+/// The level of the events of the dependencies, their warnings unless the
+/// `level` is quieter.
+fn dependencies(level: LevelFilter) -> LevelFilter {
+  level.min(LevelFilter::Warn).max(LevelFilter::Error)
+}
+
+struct Logger {
+  level: LevelFilter,
+  format: LogFormat,
+  file: Option<Mutex<LogFile>>,
+}
+
+impl Log for Logger {
+  fn enabled(&self, metadata: &Metadata) -> bool {
+    let own = metadata.target().starts_with(env!("CARGO_PKG_NAME"));
+    metadata.level()
+      <= if own {
+        self.level
+      } else {
+        dependencies(self.level)
+      }
+  }
+
+  fn log(&self, record: &Record) {
+    if !self.enabled(record.metadata()) {
+      return;
+    }
+    let line = match self.format {
+      LogFormat::Text => text(record),
+      LogFormat::Json => {
+        serde_json::to_string(&event(record)).unwrap_or_default()
+      },
+    };
+    let line = format!("{}\n", line);
+    // a log event is not worth failing the run.
+    let _ = io::stderr().write_all(line.as_bytes());
+    if let Some(file) = &self.file {
+      if let Ok(mut file) = file.lock() {
+        let _ = file.write(line.as_bytes());
+      }
+    }
+  }
+
+  fn flush(&self) {
+    let _ = io::stderr().flush();
+    if let Some(file) = &self.file {
+      if let Ok(mut file) = file.lock() {
+        let _ = file.file.flush();
+      }
+    }
+  }
+}
+
+/// The log file, rotated when it reaches its maximum size.
+struct LogFile {
+  path: PathBuf,
+  file: File,
+  len: u64,
+  max_size: Option<u64>,
+}
+
+impl LogFile {
+  /// Opens the log file at `path`, appending to it.
+  fn open(path: &Path, max_size: Option<u64>) -> Result<Self, Error> {
+    let file = OpenOptions::new()
+      .create(true)
+      .append(true)
+      .open(path)
+      .with_context(|_| format!("could not open log file {:?}", path))?;
+    Ok(LogFile {
+      path: path.to_owned(),
+      len: file.metadata()?.len(),
+      file,
+      max_size,
+    })
+  }
+
+  fn write(&mut self, line: &[u8]) -> io::Result<()> {
+    let full = self
+      .max_size
+      .is_some_and(|max| self.len > 0 && self.len + line.len() as u64 > max);
+    if full {
+      self.rotate()?;
+    }
+    self.file.write_all(line)?;
+    self.len += line.len() as u64;
+    Ok(())
+  }
+
+  /// Renames the log file to `<path>.1`, after renaming the previous ones to
+  /// the next number, and starts a new one.
+  fn rotate(&mut self) -> io::Result<()> {
+    let rotated = |n: u32| {
+      let mut path = self.path.clone().into_os_string();
+      path.push(format!(".{}", n));
+      PathBuf::from(path)
+    };
+    for n in (1..ROTATED).rev() {
+      if rotated(n).exists() {
+        fs::rename(rotated(n), rotated(n + 1))?;
+      }
+    }
+    fs::rename(&self.path, rotated(1))?;
+    self.file = OpenOptions::new()
+      .create(true)
+      .append(true)
+      .open(&self.path)?;
+    self.len = 0;
+    Ok(())
+  }
+}
+
+/// The line of the event of the `record`, like
+/// ` INFO 2019-04-01T12:00:00Z: mobcsv: Read 2 records`.
+fn text(record: &Record) -> String {
+  let now = humantime::format_rfc3339_seconds(SystemTime::now());
+  format!(
+    "{:>5} {}: {}: {}",
+    record.level(),
+    now,
+    record.module_path().unwrap_or_else(|| record.target()),
+    record.args()
+  )
+}
+
+/// The JSON object of the event of the `record`.
+fn event(record: &Record) -> Map<String, Json> {
+  let mut event = Map::new();
+  let level = match record.level() {
+    Level::Error => "error",
+    Level::Warn => "warn",
+    Level::Info => "info",
+    Level::Debug => "debug",
+    Level::Trace => "trace",
+  };
+  event.insert("level".into(), level.into());
+  let now = humantime::format_rfc3339_seconds(SystemTime::now());
+  event.insert("timestamp".into(), now.to_string().into());
+  event.insert("message".into(), record.args().to_string().into());
+  let _ = record.key_values().visit(&mut Fields(&mut event));
+  event
+}
+
+/// Adds the fields of an event to its JSON object.
+struct Fields<'a>(&'a mut Map<String, Json>);
+
+impl<'kvs> VisitSource<'kvs> for Fields<'_> {
+  fn visit_pair(
+    &mut self,
+    key: Key<'kvs>,
+    value: Value<'kvs>,
+  ) -> Result<(), kv::Error> {
+    let value = match value.to_u64() {
+      Some(n) => n.into(),
+      None => value.to_string().into(),
+    };
+    self.0.insert(key.as_str().to_owned(), value);
+    Ok(())
+  }
+}
+
+/// Parses a size of bytes, like `512KB` or `10MB`.
+pub fn parse_size(s: &str) -> Result<u64, String> {
+  let s = s.trim();
+  let digits = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+  let unit = match s[digits..].trim().to_uppercase().as_str() {
+    "" | "B" => 1,
+    "K" | "KB" => 1 << 10,
+    "M" | "MB" => 1 << 20,
+    "G" | "GB" => 1 << 30,
+    _ => return Err(format!("invalid size `{}`, expected like 10MB", s)),
+  };
+  match s[..digits]
+    .parse::<u64>()
+    .ok()
+    .and_then(|n| n.checked_mul(unit))
+  {
+    Some(n) if n > 0 => Ok(n),
+    _ => Err(format!("invalid size `{}`, expected like 10MB", s)),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn should_format_events_as_json() {
+    let fields: &[(&str, Value)] = &[
+      ("line", Value::from(2u64)),
+      ("reason", Value::from("Empty")),
+    ];
+    let record = Record::builder()
+      .level(Level::Debug)
+      .args(format_args!("rejected"))
+      .key_values(&fields)
+      .build();
+    let event = event(&record);
+    assert_eq!(event["level"], "debug");
+    assert_eq!(event["message"], "rejected");
+    assert_eq!(event["line"], 2);
+    assert_eq!(event["reason"], "Empty");
+    assert!(event["timestamp"].as_str().unwrap().ends_with('Z'));
+    assert_eq!("json".parse(), Ok(LogFormat::Json));
+  }
+
+  #[test]
+  fn should_rotate_the_log_file() {
+    let path = std::env::temp_dir().join("mobcsv-rotate.log");
+    let rotated = path.with_extension("log.1");
+    let _ = fs::remove_file(&path);
+    let _ = fs::remove_file(&rotated);
+    let mut file = LogFile::open(&path, Some(8)).unwrap();
+    file.write(b"first\n").unwrap();
+    file.write(b"second\n").unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "second\n");
+    assert_eq!(fs::read_to_string(&rotated).unwrap(), "first\n");
+    assert_eq!(parse_size("10MB"), Ok(10 << 20));
+    assert_eq!(parse_size("512 kb"), Ok(512 << 10));
+    assert!(parse_size("10XB").is_err());
+    assert!(parse_size("99999999999GB").is_err());
+  }
+}
@@ -1,134 +1,460 @@
-use std::{
-  fs::File,
-  io::{BufReader, BufWriter},
-  path::PathBuf,
-  time::Instant,
-};
-
-use clap_verbosity_flag::Verbosity;
-use indicatif::{HumanBytes, HumanDuration, ProgressBar, ProgressStyle};
-use lazy_static::lazy_static;
-use log::{debug, info};
+use std::{collections::HashSet, process};
+
+mod archive;
+mod checkpoint;
+mod clean;
+mod commands;
+mod compress;
+mod countries;
+mod dedupe;
+mod dups;
+mod encoding;
+mod format;
+mod http;
+mod join;
+mod lists;
+mod logging;
+mod mmap;
+mod names;
+mod operators;
+mod output;
+mod pipeline;
+mod progress;
+mod qr;
+mod reject;
+mod report;
+mod review;
+mod rules;
+mod s3;
+mod sample;
+mod schema;
+mod settings;
+mod sink;
+mod sort;
+mod source;
+mod split;
+mod state;
+mod stats;
+mod validator;
+
+use clean::Cleaner;
+use commands::{Cli, Options};
+use compress::Compression;
+use countries::Country;
+use encoding::OutputEncoding;
+use exitfailure::ExitFailure;
+use failure::{format_err, Error};
+use format::Format;
+use join::Join;
+use log::info;
+use names::{ArabicNames, NameRule};
+use output::Column;
 use regex::Regex;
-use serde::{Deserialize, Serialize};
-use structopt::StructOpt;
+use reject::{RejectError, RejectReason};
+use rules::{LineType, Rules};
+use sink::{OutputFormat, SqlDialect};
+use validator::{Engine, PhoneValidator};
+
+/// Everything that controls how a record is cleaned and validated.
+#[derive(Debug)]
+struct Config {
+  validator: Box<dyn PhoneValidator>,
+  cleaner: Cleaner,
+  fix_typos: bool,
+  /// Whether the letters looking like digits are converted to digits.
+  convert_confusables: bool,
+  /// When not empty, only numbers of these countries are accepted.
+  countries: Vec<&'static Country>,
+  excluded_countries: Vec<&'static Country>,
+  only_mobile: bool,
+  /// The range of the accepted counts, inclusive.
+  min_count: Option<u64>,
+  max_count: Option<u64>,
+  /// The normalized numbers that are never accepted.
+  blacklist: HashSet<String>,
+  /// When set, only these normalized numbers are accepted.
+  allowlist: Option<HashSet<String>>,
+  format: Format,
+  /// The delimiter of the output fields.
+  output_delimiter: u8,
+  output_encoding: OutputEncoding,
+  /// Whether the CSV rows end with CRLF instead of LF.
+  crlf: bool,
+  output_format: OutputFormat,
+  /// The table of the SQLite and SQL outputs.
+  table: String,
+  sql_dialect: SqlDialect,
+  /// The compression of the outputs, by their extension when not set.
+  compression: Option<Compression>,
+  /// Whether the output files are written to temporary files first,
+  /// renamed once complete.
+  atomic: bool,
+  /// Whether the existing output files are overwritten, the SQLite
+  /// databases are always updated.
+  overwrite: bool,
+  /// The extra output columns, in order.
+  columns: Vec<Column>,
+  /// The columns joined from another file, after the extra ones.
+  join: Option<Join>,
+  /// The cleaning steps of the names of the accepted records, in order.
+  names: Vec<NameRule>,
+}
 
-type CliResult = Result<(), exitfailure::ExitFailure>;
+impl Config {
+  /// Loads the rules file, if any, and builds the config of the `options`.
+  fn from_options(options: &Options) -> Result<Self, Error> {
+    if let Some(path) = &options.rules {
+      info!("Loading rules from {:?}", path);
+      rules::load(path)?;
+    }
+    Config::with_rules(options, rules::get())
+  }
 
-const BUFFER_SIZE: usize = 64 * 1024;
-const MOB_REGEX_STR: &str = "^((20)|(966))([0-9]{9,11})$";
+  /// Builds the config of the `options`, their countries being the ones of
+  /// the `rules`.
+  fn with_rules(
+    options: &Options,
+    rules: &'static Rules,
+  ) -> Result<Self, Error> {
+    let by_code = |flag: &str, codes: &[String]| {
+      codes
+        .iter()
+        .map(|code| countries::by_dialing_code(&rules.countries, code))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format_err!("invalid --{}: {}", flag, e))
+    };
+    let default_country = match &options.default_country {
+      Some(iso) => Some(
+        countries::by_iso(&rules.countries, iso)
+          .map_err(|e| format_err!("invalid --default-country: {}", e))?,
+      ),
+      None => None,
+    };
+    // the command line flags take precedence over the rules file.
+    let compile =
+      |flag: &Option<Regex>, rule: &Option<String>| match (flag, rule) {
+        (Some(re), _) => Ok(Some(re.clone())),
+        (None, Some(rule)) => Regex::new(rule).map(Some),
+        (None, None) => Ok(None),
+      };
+    let mob_regex = compile(&options.mob_regex, &rules.mob_regex)?;
+    let replacer = compile(&options.replacer_regex, &rules.replacer_regex)?;
+    let cleaner = Cleaner {
+      replacer,
+      strict: options.strict_chars,
+    };
+    let mut config = Config {
+      validator: options.engine.validator(default_country, mob_regex),
+      cleaner,
+      fix_typos: options.fix_typos,
+      convert_confusables: options.convert_confusables,
+      countries: by_code("countries", &options.countries)?,
+      excluded_countries: by_code(
+        "exclude-countries",
+        &options.exclude_countries,
+      )?,
+      only_mobile: options.only_mobile,
+      min_count: options.min_count,
+      max_count: options.max_count,
+      ..Config::default()
+    };
+    config.names = options.normalize_names.clone();
+    if let Some(ArabicNames::Normalize) = options.arabic_names {
+      config.names.push(NameRule::Arabic);
+    }
+    if !config.names.is_empty() {
+      config.names.push(NameRule::Nfc);
+      config.names.sort();
+      config.names.dedup();
+    }
+    // the lists are normalized with the same cleaner and validator.
+    if let Some(path) = &options.blacklist {
+      config.blacklist = lists::load(path, &config)?;
+    }
+    if let Some(path) = &options.allowlist {
+      config.allowlist = Some(lists::load(path, &config)?);
+    }
+    Ok(config)
+  }
 
-lazy_static! {
-  static ref MOB_RE: Regex = Regex::new(MOB_REGEX_STR).unwrap();
-  static ref REPLACER_RE: Regex =
-    Regex::new(r#"^(0)|^(00)|[!@+#$%\-^&*() ]"#).unwrap();
+  /// Checks the `count` of a record against `--min-count` and
+  /// `--max-count`.
+  fn check_count(&self, count: u64) -> Result<(), RejectReason> {
+    let low = self.min_count.is_some_and(|min| count < min);
+    let high = self.max_count.is_some_and(|max| count > max);
+    if low || high {
+      return Err(RejectReason::CountOutOfRange);
+    }
+    Ok(())
+  }
+
+  /// Checks the normalized number `ph` against the country, line and
+  /// reference lists filters.
+  fn check_filters(&self, ph: &str) -> Result<(), RejectReason> {
+    if self.blacklist.contains(ph) {
+      return Err(RejectReason::Blacklisted);
+    }
+    if self
+      .allowlist
+      .as_ref()
+      .is_some_and(|list| !list.contains(ph))
+    {
+      return Err(RejectReason::NotAllowlisted);
+    }
+    if self.only_mobile && rules::get().line_type(ph) != LineType::Mobile {
+      return Err(RejectReason::NotMobile);
+    }
+    let country = countries::of(ph);
+    let same = |c: &&Country| {
+      country.is_some_and(|country| c.dialing_code == country.dialing_code)
+    };
+    if (self.countries.is_empty() || self.countries.iter().any(same))
+      && !self.excluded_countries.iter().any(same)
+    {
+      Ok(())
+    } else {
+      Err(RejectReason::CountryNotAllowed)
+    }
+  }
 }
 
-#[derive(Debug, StructOpt)]
-#[structopt(
-  name = "mobcsv",
-  about = "Validate and format mobile number in one standard way.",
-  version = "0.1.0",
-  author = "Shady Khalifa <shekohex@gmail>",
-  rename_all = "kebab-case"
-)]
-struct Cli {
-  /// The CSV output file path
-  #[structopt(short = "o")]
-  output_path: PathBuf,
-  #[structopt(flatten)]
-  verbosity: Verbosity,
-  /// The input CSV file path
-  input_path: PathBuf,
+impl Default for Config {
+  fn default() -> Self {
+    Config {
+      validator: Engine::Regex.validator(None, None),
+      cleaner: Cleaner::default(),
+      fix_typos: false,
+      convert_confusables: false,
+      countries: Vec::new(),
+      excluded_countries: Vec::new(),
+      only_mobile: false,
+      min_count: None,
+      max_count: None,
+      blacklist: HashSet::new(),
+      allowlist: None,
+      format: Format::default(),
+      output_delimiter: b',',
+      output_encoding: OutputEncoding::default(),
+      crlf: false,
+      output_format: OutputFormat::default(),
+      table: "contacts".to_owned(),
+      sql_dialect: SqlDialect::default(),
+      compression: None,
+      atomic: true,
+      overwrite: false,
+      columns: Vec::new(),
+      join: None,
+      names: Vec::new(),
+    }
+  }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone)]
 struct Record {
   /// The mobile phone number
   ph: String,
   name: String,
-  count: u16,
+  count: u64,
+  /// The values of the other input columns, in order.
+  extra: Vec<String>,
 }
 
-fn main() -> CliResult {
-  let args: Cli = Cli::from_args();
-  args.verbosity.setup_env_logger(&env!("CARGO_PKG_NAME"))?;
-  info!("Starting Application...");
-  info!("I/O Buffer Size: {} byte", BUFFER_SIZE);
-  info!("Reading from {:?}", args.input_path);
-  let c = File::open(args.input_path)?;
-  let metadata = c.metadata()?;
-  let pb = ProgressBar::new(metadata.len());
-  pb.set_prefix("Working");
-  pb.set_style(
-    ProgressStyle::default_bar()
-      .template(
-        "{prefix:.bold.dim} {spinner:.green} [{eta_precise}] [{bar:40.cyan/blue}] \
-        {percent}% \
-         ({eta})",
-      )
-      .tick_chars("∙∙∙●∙∙∙●∙∙∙●")
-      .progress_chars("=> "),
-  );
-  let buffer = BufReader::with_capacity(BUFFER_SIZE, c);
-  let mut rdr = csv::Reader::from_reader(pb.wrap_read(buffer));
-  pb.println(format!(
-    "The input CSV File is {} large",
-    HumanBytes(metadata.len())
-  ));
-  info!("Trying to write to {:?}", args.output_path);
-  let out = File::create(args.output_path)?;
-  let buffer = BufWriter::with_capacity(BUFFER_SIZE, out);
-  let mut wrt = csv::Writer::from_writer(buffer);
-  let started = Instant::now();
-  for r in rdr.deserialize() {
-    if let Some(record) = is_good_ph(r?) {
-      wrt.serialize(record)?;
-    }
-  }
-  wrt.flush()?;
-  pb.finish_and_clear();
-  println!(
-    "Done in {} [{}ms]",
-    HumanDuration(started.elapsed()),
-    started.elapsed().as_millis()
-  );
+/// A record that passed the validation.
+#[derive(Debug)]
+struct Accepted {
+  record: Record,
+  /// The phone number as found in the input, before any cleaning.
+  original_ph: String,
+  /// Whether the phone number typos were repaired to pass the validation.
+  fixed: bool,
+  /// Whether the phone number was found in the `--extract-from` column.
+  extracted: bool,
+}
+
+/// A record that failed the validation.
+#[derive(Debug, Clone)]
+struct Rejected {
+  /// The record as found in the input.
+  record: Record,
+  /// The phone number as cleaned for its validation.
+  attempt: String,
+  reason: RejectReason,
+  /// The line of the record in its input, once handled.
+  line: u64,
+}
+
+fn main() {
+  if let Err(e) = run() {
+    let code = exit_code(&e);
+    eprintln!("Error: {:?}", ExitFailure::from(e));
+    process::exit(code);
+  }
+}
+
+/// The exit code of a failed run, see the `EXIT CODES` of the help.
+fn exit_code(e: &Error) -> i32 {
+  e.downcast_ref::<RejectError>()
+    .map_or(1, RejectError::exit_code)
+}
+
+fn run() -> Result<(), Error> {
+  let args: Cli = settings::parse(std::env::args_os().collect())?;
+  if let Some(options) = args.options() {
+    logging::setup(options)?;
+    match (&options.config, &options.profile) {
+      (Some(path), Some(profile)) => {
+        info!("Using the `{}` profile of {:?}", profile, path)
+      },
+      (Some(path), None) => info!("Using the default flags of {:?}", path),
+      _ => {},
+    }
+  }
+  args.run()?;
   Ok(())
 }
 
 #[inline]
-fn remove_bad_chars(mut record: Record) -> Record {
-  // we need to remove all spacial characters to empty one, so we can then
-  // validate the mobile number.
-  record.ph = REPLACER_RE.replace_all(&record.ph.trim(), "").trim().into();
+fn remove_bad_chars(config: &Config, mut record: Record) -> Record {
+  record.ph = config.cleaner.clean(&record.ph);
   record
 }
 
-fn standardize_ph(mut record: Record) -> Record {
-  match record.ph.chars().next() {
-    Some('1') => {
-      // Egypt, so we need to add 20
-      record.ph = "20".to_owned() + &record.ph;
-      record
+/// The normalized phone number of the cleaned `ph`, if it passes the
+/// filters.
+fn normalize_ph(config: &Config, ph: &str) -> Result<String, RejectReason> {
+  if ph.is_empty() {
+    return Err(RejectReason::EmptyPhone);
+  }
+  let ph = config.validator.normalize(ph)?;
+  config.check_filters(&ph)?;
+  Ok(ph)
+}
+
+fn is_good_ph(config: &Config, record: Record) -> Result<Accepted, Rejected> {
+  let original_ph = record.ph.clone();
+  let mut r = remove_bad_chars(config, record);
+  let normalize = |ph: &str| normalize_ph(config, ph);
+  let mut normalized = normalize(&r.ph);
+  let mut fixed = false;
+  if normalized.is_err() && config.fix_typos {
+    let repaired = normalize(&config.cleaner.fix_typos(&original_ph));
+    if repaired.is_ok() {
+      normalized = repaired;
+      fixed = true;
+    }
+  }
+  if normalized.is_err() && config.convert_confusables {
+    let converted = clean::convert_confusables(&original_ph);
+    let converted = normalize(&config.cleaner.clean(&converted));
+    if converted.is_ok() {
+      normalized = converted;
+      fixed = true;
+    }
+  }
+  // the lookalikes are the likely cause of the invalid numbers having them,
+  // and the phone numbers of letters only are empty once cleaned.
+  let normalized = normalized.map_err(|reason| {
+    if reason.is_invalid_phone() && clean::has_confusables(&original_ph) {
+      RejectReason::ConfusableCharacters
+    } else if reason == RejectReason::EmptyPhone
+      && !original_ph.trim().is_empty()
+    {
+      RejectReason::NonDigitChars
+    } else {
+      reason
+    }
+  });
+  let count = r.count;
+  let normalized = normalized.and_then(|ph| {
+    config.check_count(count)?;
+    Ok(ph)
+  });
+  match normalized {
+    Ok(ph) => {
+      r.ph = ph;
+      if !config.names.is_empty() {
+        r.name = names::normalize(&r.name, &config.names);
+      }
+      Ok(Accepted {
+        record: r,
+        original_ph,
+        fixed,
+        extracted: false,
+      })
     },
-    Some('5') => {
-      // Saudi Arabia, add 966
-      record.ph = "966".to_owned() + &record.ph;
-      record
+    Err(reason) => {
+      let attempt = std::mem::replace(&mut r.ph, original_ph);
+      Err(Rejected {
+        record: r,
+        attempt,
+        reason,
+        line: 0,
+      })
     },
-    _ => record,
   }
 }
 
-fn is_good_ph(record: Record) -> Option<Record> {
-  let r = remove_bad_chars(record);
-  let r = standardize_ph(r);
-  if MOB_RE.is_match(&r.ph) {
-    Some(r)
-  } else {
-    debug!("Not Acceptable: {:?}", r);
-    None
+/// Looks for an acceptable phone number in the text of the `column` of the
+/// other columns of the record rejected for its phone number.
+fn extract_ph(
+  config: &Config,
+  rejected: Rejected,
+  column: usize,
+) -> Result<Accepted, Rejected> {
+  if !rejected.reason.is_invalid_phone() {
+    return Err(rejected);
   }
+  let text = rejected.record.extra.get(column).map_or("", String::as_str);
+  for candidate in clean::candidates(text) {
+    let record = Record {
+      ph: candidate.to_owned(),
+      ..rejected.record.clone()
+    };
+    if let Ok(accepted) = is_good_ph(config, record) {
+      return Ok(Accepted {
+        extracted: true,
+        ..accepted
+      });
+    }
+  }
+  Err(rejected)
+}
+
+/// Normalizes the phone numbers of the other `columns` of the `--ph-columns`
+/// of the accepted record, emptying the invalid ones, or accepts the rejected
+/// one with the first valid of them.
+fn other_phs(
+  config: &Config,
+  result: Result<Accepted, Rejected>,
+  columns: &[usize],
+) -> Result<Accepted, Rejected> {
+  let mut accepted = match result {
+    Ok(accepted) => accepted,
+    Err(rejected) if !rejected.reason.is_invalid_phone() => {
+      return Err(rejected)
+    },
+    Err(rejected) => {
+      let found = columns.iter().find_map(|&i| {
+        let record = Record {
+          ph: rejected.record.extra[i].clone(),
+          ..rejected.record.clone()
+        };
+        is_good_ph(config, record).ok()
+      });
+      match found {
+        Some(accepted) => accepted,
+        None => return Err(rejected),
+      }
+    },
+  };
+  for &i in columns {
+    let ph = &mut accepted.record.extra[i];
+    *ph = match normalize_ph(config, &config.cleaner.clean(ph)) {
+      Ok(normalized) => config.format.apply(&normalized),
+      Err(_) => String::new(),
+    };
+  }
+  Ok(accepted)
 }
 
 #[cfg(test)]
@@ -136,31 +462,34 @@ mod tests {
   use super::*;
 
   impl Record {
-    pub fn new(ph: &str, name: &str, count: u16) -> Self {
+    pub fn new(ph: &str, name: &str, count: u64) -> Self {
       Record {
         ph: ph.to_owned(),
         name: name.to_owned(),
         count,
+        extra: Vec::new(),
       }
     }
   }
 
   #[test]
   fn should_detect_bad_numbers() {
+    let config = Config::default();
     let bad_record = Record::new("20111bad", "test1", 0);
     let bad_record2 = Record::new("hah2011166130", "test2", 0);
     let bad_record3 = Record::new("1232131", "test3", 0);
     let bad_record4 = Record::new("00", "test4", 0);
     let bad_record5 = Record::new("2011166130", "test5", 0);
-    assert!(is_good_ph(bad_record).is_none());
-    assert!(is_good_ph(bad_record2).is_none());
-    assert!(is_good_ph(bad_record3).is_none());
-    assert!(is_good_ph(bad_record4).is_none());
-    assert!(is_good_ph(bad_record5).is_none());
+    assert!(is_good_ph(&config, bad_record).is_err());
+    assert!(is_good_ph(&config, bad_record2).is_err());
+    assert!(is_good_ph(&config, bad_record3).is_err());
+    assert!(is_good_ph(&config, bad_record4).is_err());
+    assert!(is_good_ph(&config, bad_record5).is_err());
   }
 
   #[test]
   fn should_pass_good_numbers() {
+    let config = Config::default();
     let good_record = Record::new("201116613061", "test1", 0);
     let good_record2 = Record::new("00201116613061", "test2", 0);
     let good_record3 = Record::new("+2(0111)6613061", "test3", 0);
@@ -169,21 +498,227 @@ mod tests {
     let good_record6 = Record::new("1116613061", "test6", 0);
     let good_record7 = Record::new("540029129", "test7", 0);
     let good_record8 = Record::new("5400 291 29", "test8", 0);
-    assert!(is_good_ph(good_record).is_some());
-    assert!(is_good_ph(good_record2).is_some());
-    assert!(is_good_ph(good_record3).is_some());
-    assert!(is_good_ph(good_record4).is_some());
-    assert!(is_good_ph(good_record5).is_some());
-    assert!(is_good_ph(good_record6).is_some());
-    assert!(is_good_ph(good_record7).is_some());
-    assert!(is_good_ph(good_record8).is_some());
+    let good_record9 = Record::new("+971 50 123 4567", "test9", 0);
+    let good_record10 = Record::new("٠١١١٦٦١٣٠٦١", "test10", 0);
+    assert!(is_good_ph(&config, good_record).is_ok());
+    assert!(is_good_ph(&config, good_record2).is_ok());
+    assert!(is_good_ph(&config, good_record3).is_ok());
+    assert!(is_good_ph(&config, good_record4).is_ok());
+    assert!(is_good_ph(&config, good_record5).is_ok());
+    assert!(is_good_ph(&config, good_record6).is_ok());
+    assert!(is_good_ph(&config, good_record7).is_ok());
+    assert!(is_good_ph(&config, good_record8).is_ok());
+    assert!(is_good_ph(&config, good_record9).is_ok());
+    assert!(is_good_ph(&config, good_record10).is_ok());
+  }
+
+  #[test]
+  fn should_use_default_country_for_trunk_prefix() {
+    let config = Config {
+      validator: Engine::Regex
+        .validator(countries::by_iso(countries::all(), "SA").ok(), None),
+      ..Config::default()
+    };
+    let record = Record::new("0540029129", "test1", 0);
+    let record2 = Record::new("01116613061", "test2", 0);
+    let record3 = Record::new("00201116613061", "test3", 0);
+    assert_eq!(
+      is_good_ph(&config, record).unwrap().record.ph,
+      "966540029129"
+    );
+    assert!(is_good_ph(&config, record2).is_err());
+    assert_eq!(
+      is_good_ph(&config, record3).unwrap().record.ph,
+      "201116613061"
+    );
+  }
+
+  #[test]
+  fn should_validate_with_libphonenumber_engine() {
+    let config = Config {
+      validator: Engine::LibPhoneNumber.validator(None, None),
+      ..Config::default()
+    };
+    let record = Record::new("+20 111 661 3061", "test1", 0);
+    let record2 = Record::new("+20 111 661 306", "test2", 0);
+    assert_eq!(
+      is_good_ph(&config, record).unwrap().record.ph,
+      "201116613061"
+    );
+    assert!(is_good_ph(&config, record2).is_err());
+  }
+
+  #[test]
+  fn should_filter_countries() {
+    let config = Config {
+      countries: vec![
+        countries::by_dialing_code(countries::all(), "20").unwrap()
+      ],
+      ..Config::default()
+    };
+    let config2 = Config {
+      excluded_countries: vec![countries::by_dialing_code(
+        countries::all(),
+        "971",
+      )
+      .unwrap()],
+      ..Config::default()
+    };
+    let eg = || Record::new("201116613061", "test1", 0);
+    let ae = || Record::new("971501234567", "test2", 0);
+    assert!(is_good_ph(&config, eg()).is_ok());
+    assert!(is_good_ph(&config, ae()).is_err());
+    assert!(is_good_ph(&config2, eg()).is_ok());
+    assert!(is_good_ph(&config2, ae()).is_err());
+  }
+
+  #[test]
+  fn should_extract_phone_numbers() {
+    let config = Config::default();
+    let mut record = Record::new("", "test1", 0);
+    record.extra.push("new number: 0111 661 3061".into());
+    let rejected = is_good_ph(&config, record).unwrap_err();
+    let accepted = extract_ph(&config, rejected.clone(), 0).unwrap();
+    assert_eq!(accepted.record.ph, "201116613061");
+    assert!(accepted.extracted);
+    assert!(extract_ph(&config, rejected, 1).is_err());
+  }
+
+  #[test]
+  fn should_detect_confusables() {
+    let record = Record::new("0111O613061", "test1", 0);
+    let rejected = is_good_ph(&Config::default(), record).unwrap_err();
+    assert_eq!(rejected.reason, RejectReason::ConfusableCharacters);
+    let config = Config {
+      convert_confusables: true,
+      ..Config::default()
+    };
+    let converted = is_good_ph(&config, rejected.record).unwrap();
+    assert_eq!(converted.record.ph, "201110613061");
+    assert!(converted.fixed);
+    let record = Record::new("٠١١١ ٦٦١ ٣٠٦١", "test1", 0);
+    assert!(is_good_ph(&Config::default(), record).is_ok());
+  }
+
+  #[test]
+  fn should_fix_typos() {
+    let config = Config {
+      fix_typos: true,
+      ..Config::default()
+    };
+    let record = Record::new("O1116613O61", "test1", 0);
+    let record2 = Record::new("201116613061", "test2", 0);
+    let fixed = is_good_ph(&config, record).unwrap();
+    assert_eq!(fixed.record.ph, "201116613061");
+    assert!(fixed.fixed);
+    assert!(!is_good_ph(&config, record2).unwrap().fixed);
+    let record = Record::new("O1116613O61", "test1", 0);
+    assert!(is_good_ph(&Config::default(), record).is_err());
+  }
+
+  #[test]
+  fn should_keep_only_mobiles() {
+    let config = Config {
+      only_mobile: true,
+      ..Config::default()
+    };
+    let landline = Record::new("+20 2 2345 6789", "test1", 0);
+    let mobile = Record::new("01116613061", "test2", 0);
+    assert!(
+      is_good_ph(&Config::default(), Record::new("20223456789", "", 0)).is_ok()
+    );
+    assert!(is_good_ph(&config, landline).is_err());
+    assert!(is_good_ph(&config, mobile).is_ok());
+  }
+
+  #[test]
+  fn should_reject_counts_out_of_range() {
+    let config = Config {
+      min_count: Some(3),
+      max_count: Some(100),
+      ..Config::default()
+    };
+    let rejected = is_good_ph(&config, Record::new("01116613061", "", 2));
+    assert_eq!(rejected.unwrap_err().reason, RejectReason::CountOutOfRange);
+    assert!(is_good_ph(&config, Record::new("01116613061", "", 101)).is_err());
+    assert!(is_good_ph(&config, Record::new("01116613061", "", 3)).is_ok());
+  }
+
+  #[test]
+  fn should_reject_blacklisted_numbers() {
+    let config = Config {
+      blacklist: vec!["201116613061".to_owned()].into_iter().collect(),
+      ..Config::default()
+    };
+    let rejected = is_good_ph(&config, Record::new("01116613061", "", 0));
+    assert_eq!(rejected.unwrap_err().reason, RejectReason::Blacklisted);
+    assert!(is_good_ph(&config, Record::new("01016613061", "", 0)).is_ok());
+  }
+
+  #[test]
+  fn should_only_accept_allowlisted_numbers() {
+    let config = Config {
+      allowlist: Some(vec!["201116613061".to_owned()].into_iter().collect()),
+      ..Config::default()
+    };
+    let rejected = is_good_ph(&config, Record::new("01016613061", "", 0));
+    assert_eq!(rejected.unwrap_err().reason, RejectReason::NotAllowlisted);
+    assert!(is_good_ph(&config, Record::new("01116613061", "", 0)).is_ok());
+  }
+
+  #[test]
+  fn should_reject_with_reasons() {
+    let config = Config {
+      countries: vec![
+        countries::by_dialing_code(countries::all(), "20").unwrap()
+      ],
+      ..Config::default()
+    };
+    let reason = |ph| is_good_ph(&config, Record::new(ph, "", 0)).unwrap_err();
+    assert_eq!(reason("").reason, RejectReason::EmptyPhone);
+    assert_eq!(reason(" ").reason, RejectReason::EmptyPhone);
+    assert_eq!(reason("bad").reason, RejectReason::NonDigitChars);
+    assert_eq!(reason("+20 111 661 306").reason, RejectReason::TooShort);
+    assert_eq!(reason("540029129").reason, RejectReason::CountryNotAllowed);
+    assert_eq!(reason("+20 111 661 306").record.ph, "+20 111 661 306");
   }
 
   #[test]
-  fn should_standardize_ph() {
-    let good_record = Record::new("1116613061", "test1", 0);
-    let good_record2 = Record::new("511661306", "test2", 0);
-    assert_eq!(standardize_ph(good_record).ph, "201116613061");
-    assert_eq!(standardize_ph(good_record2).ph, "966511661306");
+  fn should_choose_the_countries_of_the_rules_file() {
+    use structopt::StructOpt;
+    let path = std::env::temp_dir().join("mobcsv-custom-rules.toml");
+    std::fs::write(
+      &path,
+      "[[country]]\niso = \"ZZ\"\nname = \"Zedland\"\n\
+       dialing_code = \"999\"\ntrunk_prefix = \"0\"\nlengths = [9]\n",
+    )
+    .unwrap();
+    let path = path.to_str().unwrap();
+    let cli = Cli::from_iter(&[
+      "mobcsv",
+      "validate",
+      "in.csv",
+      "--rules",
+      path,
+      "--countries",
+      "999",
+      "--exclude-countries=+999",
+      "--default-country",
+      "zz",
+    ]);
+    let options = cli.options().unwrap();
+    // the parsed flags don't load the embedded rules.
+    let rules =
+      Box::leak(Box::new(rules::read(std::path::Path::new(path)).unwrap()));
+    let config = Config::with_rules(options, rules).unwrap();
+    assert_eq!(config.countries.len(), 1);
+    assert_eq!(config.countries[0].name, "Zedland");
+    assert_eq!(config.excluded_countries[0].iso, "ZZ");
+    let cli =
+      Cli::from_iter(&["mobcsv", "validate", "in.csv", "--countries=20"]);
+    let e = Config::with_rules(cli.options().unwrap(), rules).unwrap_err();
+    assert!(e
+      .to_string()
+      .starts_with("invalid --countries: unknown dialing"));
   }
 }
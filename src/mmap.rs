@@ -0,0 +1,91 @@
+//! Reading the input files through memory mappings, split at the line ends
+//! for the records to be parsed on several threads.
+
+use std::{fs::File, ops::Range, path::Path, sync::Arc};
+
+use failure::{Error, ResultExt};
+use memmap2::Mmap;
+
+/// A memory mapped file, shared by its readers.
+#[derive(Clone)]
+pub struct Mapping(Arc<Mmap>);
+
+impl Mapping {
+  /// Maps the file at `path`, returning it with its size.
+  pub fn open(path: &Path) -> Result<(Self, u64), Error> {
+    let file = File::open(path)
+      .with_context(|_| format!("could not open input file {:?}", path))?;
+    // the file must not be truncated while it is mapped, like any input.
+    let mmap = unsafe { Mmap::map(&file) }
+      .with_context(|_| format!("could not map input file {:?}", path))?;
+    let len = mmap.len() as u64;
+    Ok((Mapping(Arc::new(mmap)), len))
+  }
+}
+
+impl AsRef<[u8]> for Mapping {
+  fn as_ref(&self) -> &[u8] {
+    &self.0
+  }
+}
+
+/// The offset of the 1-based `line` of the `data`.
+pub fn offset_of_line(data: &[u8], line: u64) -> usize {
+  let mut offset = 0;
+  for _ in 1..line {
+    match data[offset..].iter().position(|&b| b == b'\n') {
+      Some(end) => offset += end + 1,
+      None => return data.len(),
+    }
+  }
+  offset
+}
+
+/// Splits the `data` from `start` into ranges of about `size` bytes, ending
+/// at the line ends, each one with the count of its lines.
+///
+/// The quoted fields may span several lines, so the data must have no
+/// quotes to be split this way.
+pub fn segments(
+  data: &[u8],
+  start: usize,
+  size: usize,
+) -> Vec<(Range<usize>, u64)> {
+  let mut segments = Vec::new();
+  let mut begin = start;
+  while begin < data.len() {
+    let cut = (begin + size).min(data.len());
+    let end = match data[cut..].iter().position(|&b| b == b'\n') {
+      Some(i) => cut + i + 1,
+      None => data.len(),
+    };
+    let lines = data[begin..end].iter().filter(|&&b| b == b'\n').count();
+    segments.push((begin..end, lines as u64));
+    begin = end;
+  }
+  segments
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn should_split_at_line_ends() {
+    let data = b"ph,name\n0111,a\n0112,b\n0113,c";
+    let start = offset_of_line(data, 2);
+    assert_eq!(start, 8);
+    let segments = segments(data, start, 4);
+    assert_eq!(segments, [(8..15, 1), (15..22, 1), (22..28, 0)]);
+    assert_eq!(offset_of_line(data, 9), data.len());
+  }
+
+  #[test]
+  fn should_map_files() {
+    let path = std::env::temp_dir().join("mobcsv-mmap.csv");
+    std::fs::write(&path, "ph,name\n").unwrap();
+    let (mapping, len) = Mapping::open(&path).unwrap();
+    assert_eq!(len, 8);
+    assert_eq!(mapping.as_ref(), b"ph,name\n");
+  }
+}
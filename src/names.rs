@@ -0,0 +1,179 @@
+//! Cleaning the names of the records, along with their phone numbers.
+
+use std::str::FromStr;
+
+use unicode_normalization::UnicodeNormalization;
+
+/// A step of the cleaning of the names, applied in this order whatever the
+/// order they are given in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum NameRule {
+  /// Removes the emojis and the control characters.
+  StripEmoji,
+  /// The Unicode NFC normalization, the same name always being the same
+  /// characters.
+  Nfc,
+  /// Removes the tatweel and the diacritics of the Arabic letters, and
+  /// writes the alef and hamza forms as their base letter, with
+  /// `--arabic-names normalize`.
+  Arabic,
+  /// Replaces the runs of whitespace with a single space.
+  CollapseSpaces,
+  /// Removes the leading and trailing whitespace.
+  Trim,
+  /// Uppercases the first letter of every word, and lowercases the others.
+  Titlecase,
+}
+
+impl NameRule {
+  pub const VARIANTS: &'static [&'static str] =
+    &["trim", "collapse-spaces", "nfc", "titlecase", "strip-emoji"];
+}
+
+impl FromStr for NameRule {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "trim" => Ok(NameRule::Trim),
+      "collapse-spaces" => Ok(NameRule::CollapseSpaces),
+      "nfc" => Ok(NameRule::Nfc),
+      "titlecase" => Ok(NameRule::Titlecase),
+      "strip-emoji" => Ok(NameRule::StripEmoji),
+      _ => Err(format!("unknown name normalization `{}`", s)),
+    }
+  }
+}
+
+/// How the Arabic names are cleaned.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArabicNames {
+  /// The `Arabic` rule, for the same names to be written the same way.
+  Normalize,
+}
+
+impl ArabicNames {
+  pub const VARIANTS: &'static [&'static str] = &["normalize"];
+}
+
+impl FromStr for ArabicNames {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "normalize" => Ok(ArabicNames::Normalize),
+      _ => Err(format!("unknown Arabic names handling `{}`", s)),
+    }
+  }
+}
+
+/// Cleans the `name` with the sorted `rules`.
+pub fn normalize(name: &str, rules: &[NameRule]) -> String {
+  let mut name = name.to_owned();
+  for rule in rules {
+    name = match rule {
+      NameRule::StripEmoji => name
+        .chars()
+        .filter(|&c| !is_emoji(c) && (!c.is_control() || c.is_whitespace()))
+        .collect(),
+      NameRule::Nfc => name.nfc().collect(),
+      NameRule::Arabic => name.chars().filter_map(arabic).collect(),
+      NameRule::CollapseSpaces => collapse_spaces(&name),
+      NameRule::Trim => name.trim().to_owned(),
+      NameRule::Titlecase => titlecase(&name),
+    };
+  }
+  name
+}
+
+/// Whether the character is an emoji, a part of one like the skin tones
+/// and joiners, or a pictograph.
+fn is_emoji(c: char) -> bool {
+  matches!(
+    c as u32,
+    0x1F000..=0x1FAFF
+      | 0x2600..=0x27BF
+      | 0x2B00..=0x2BFF
+      | 0x200D
+      | 0x20E3
+      | 0xFE00..=0xFE0F
+      | 0xE0020..=0xE007F
+  )
+}
+
+/// The normalized Arabic letter `c`, if any.
+fn arabic(c: char) -> Option<char> {
+  match c {
+    // the tatweel, the harakat and the Quranic marks.
+    '\u{640}' | '\u{64B}'..='\u{65F}' | '\u{670}' | '\u{6D6}'..='\u{6ED}' => {
+      None
+    },
+    'أ' | 'إ' | 'آ' | 'ٱ' => Some('ا'),
+    'ؤ' => Some('و'),
+    'ئ' | 'ى' => Some('ي'),
+    c => Some(c),
+  }
+}
+
+/// Keeps the leading and trailing whitespace, if any, as a single space.
+fn collapse_spaces(name: &str) -> String {
+  let mut out = String::with_capacity(name.len());
+  let mut space = false;
+  for c in name.chars() {
+    if c.is_whitespace() {
+      space = true;
+      continue;
+    }
+    if space {
+      out.push(' ');
+      space = false;
+    }
+    out.push(c);
+  }
+  if space {
+    out.push(' ');
+  }
+  out
+}
+
+/// The first letter of every word, after any non letter like a space or a
+/// hyphen, in uppercase.
+fn titlecase(name: &str) -> String {
+  let mut out = String::with_capacity(name.len());
+  let mut start = true;
+  for c in name.chars() {
+    if start {
+      out.extend(c.to_uppercase());
+    } else {
+      out.extend(c.to_lowercase());
+    }
+    start = !c.is_alphabetic();
+  }
+  out
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn should_normalize_names() {
+    let mut rules: Vec<NameRule> = "trim,collapse-spaces,titlecase,strip-emoji"
+      .split(',')
+      .map(|rule| rule.parse().unwrap())
+      .collect();
+    rules.sort();
+    assert_eq!(
+      normalize("  mARY-jane \t o'brien 👍🏽\u{7} ", &rules),
+      "Mary-Jane O'Brien"
+    );
+    assert_eq!(normalize("  a  b ", &[NameRule::CollapseSpaces]), " a b ");
+    assert_eq!(normalize("Jose\u{301}", &[NameRule::Nfc]), "José");
+    assert_eq!(normalize("محمد  علي", &rules), "محمد علي");
+    let rules = [NameRule::Nfc, NameRule::Arabic];
+    assert_eq!(normalize("مُحَمّـــد", &rules), "محمد");
+    assert_eq!(normalize("أحمد إسماعيل", &rules), "احمد اسماعيل");
+    assert_eq!(normalize("مؤمن مصطفى", &rules), "مومن مصطفي");
+    assert_eq!(normalize("\u{627}\u{654}حمد", &rules), "احمد");
+  }
+}
@@ -0,0 +1,77 @@
+//! Mobile operators (carriers) lookup by the leading digits of a normalized
+//! number.
+
+/// A single row of the operator prefix table.
+#[derive(Debug)]
+pub struct Operator {
+  /// Leading digits of a normalized number, dialing code included.
+  pub prefix: &'static str,
+  pub name: &'static str,
+}
+
+pub static OPERATORS: &[Operator] = &[
+  // Egypt
+  Operator {
+    prefix: "2010",
+    name: "Vodafone",
+  },
+  Operator {
+    prefix: "2011",
+    name: "Etisalat",
+  },
+  Operator {
+    prefix: "2012",
+    name: "Orange",
+  },
+  Operator {
+    prefix: "2015",
+    name: "WE",
+  },
+  // Saudi Arabia
+  Operator {
+    prefix: "96650",
+    name: "STC",
+  },
+  Operator {
+    prefix: "96655",
+    name: "STC",
+  },
+  Operator {
+    prefix: "96654",
+    name: "Mobily",
+  },
+  Operator {
+    prefix: "96656",
+    name: "Mobily",
+  },
+  Operator {
+    prefix: "96658",
+    name: "Zain",
+  },
+  Operator {
+    prefix: "96659",
+    name: "Zain",
+  },
+];
+
+/// Finds the operator of a normalized number.
+pub fn find(ph: &str) -> Option<&'static Operator> {
+  OPERATORS.iter().find(|o| ph.starts_with(o.prefix))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn should_find_operators() {
+    assert_eq!(find("201016613061").unwrap().name, "Vodafone");
+    assert_eq!(find("201116613061").unwrap().name, "Etisalat");
+    assert_eq!(find("201216613061").unwrap().name, "Orange");
+    assert_eq!(find("201516613061").unwrap().name, "WE");
+    assert_eq!(find("966551166130").unwrap().name, "STC");
+    assert_eq!(find("966561166130").unwrap().name, "Mobily");
+    assert_eq!(find("966591166130").unwrap().name, "Zain");
+    assert!(find("971501234567").is_none());
+  }
+}
@@ -0,0 +1,356 @@
+//! Writing the accepted records, along with the optional extra columns.
+
+use std::{borrow::Cow, str::FromStr};
+
+use failure::Error;
+use serde_json::{Map, Value};
+
+use crate::{
+  countries, operators, rules, sink::Sink, Accepted, Config, Rejected,
+};
+
+/// The columns of every output, in order.
+pub const BASE_HEADERS: &[&str] = &["ph", "name", "count"];
+
+/// An optional column appended to the output after the base columns.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Column {
+  /// The phone number as found in the input, before any cleaning.
+  OriginalPh,
+  Operator,
+  CountryCode,
+  CountryName,
+  /// Whether the phone number typos were repaired.
+  Fixed,
+  /// Whether the phone number was extracted from a text column.
+  Extracted,
+  /// Whether it is a mobile or a landline number.
+  LineType,
+  /// The WhatsApp click-to-chat link of the phone number.
+  WaLink,
+}
+
+impl Column {
+  pub fn header(self) -> &'static str {
+    match self {
+      Column::OriginalPh => "original_ph",
+      Column::Operator => "operator",
+      Column::CountryCode => "country_code",
+      Column::CountryName => "country_name",
+      Column::Fixed => "fixed",
+      Column::Extracted => "extracted",
+      Column::LineType => "line_type",
+      Column::WaLink => "wa_link",
+    }
+  }
+
+  pub fn value(self, accepted: &Accepted) -> Cow<'_, str> {
+    let ph = &accepted.record.ph;
+    let value = match self {
+      Column::OriginalPh => &accepted.original_ph,
+      Column::Operator => operators::find(ph).map_or("", |o| o.name),
+      Column::CountryCode => countries::of(ph).map_or("", |c| &c.iso),
+      Column::CountryName => countries::of(ph).map_or("", |c| &c.name),
+      Column::Fixed => {
+        if accepted.fixed {
+          "true"
+        } else {
+          "false"
+        }
+      },
+      Column::Extracted => {
+        if accepted.extracted {
+          "true"
+        } else {
+          "false"
+        }
+      },
+      Column::LineType => rules::get().line_type(ph).as_str(),
+      Column::WaLink => return format!("https://wa.me/{}", ph).into(),
+    };
+    value.into()
+  }
+}
+
+/// The output CSV columns, the base ones, the `passed` through input ones,
+/// then the enabled extra columns.
+pub fn headers<'a>(config: &'a Config, passed: &'a [String]) -> Vec<&'a str> {
+  let passed = passed.iter().map(String::as_str);
+  let extra = config.columns.iter().map(|c| c.header());
+  let joined = config.join.iter().flat_map(|j| j.columns.iter());
+  let joined = joined.map(String::as_str);
+  let base = BASE_HEADERS.iter().cloned();
+  base.chain(passed).chain(extra).chain(joined).collect()
+}
+
+pub fn write_record(
+  wrt: &mut dyn Sink,
+  config: &Config,
+  accepted: &Accepted,
+) -> Result<(), Error> {
+  let record = &accepted.record;
+  wrt.write_field(&config.format.apply(&record.ph))?;
+  wrt.write_field(&record.name)?;
+  wrt.write_field(&record.count.to_string())?;
+  for value in &record.extra {
+    wrt.write_field(value)?;
+  }
+  for column in &config.columns {
+    wrt.write_field(&column.value(accepted))?;
+  }
+  if let Some(join) = &config.join {
+    for value in join.values(&record.ph) {
+      wrt.write_field(value)?;
+    }
+  }
+  wrt.end_record()
+}
+
+/// The columns of the rejects output, with the `passed` through input ones.
+pub fn reject_headers(passed: &[String]) -> Vec<&str> {
+  let passed = passed.iter().map(String::as_str);
+  let base = BASE_HEADERS.iter().cloned();
+  let reject = ["reject_reason", "suggestion"].iter().cloned();
+  base.chain(passed).chain(reject).collect()
+}
+
+/// The format of the rejects output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RejectsFormat {
+  /// The input columns of the record, with its `reject_reason` and the
+  /// `suggestion` of a corrected phone number, if any.
+  Csv,
+  /// A JSON object by record, with its `original` phone number, the
+  /// `normalized_attempt` at cleaning it, the `reason`, the input `line`, and
+  /// the `suggestion`.
+  Jsonl,
+}
+
+impl RejectsFormat {
+  pub const VARIANTS: &'static [&'static str] = &["csv", "jsonl"];
+}
+
+impl FromStr for RejectsFormat {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "csv" => Ok(RejectsFormat::Csv),
+      "jsonl" => Ok(RejectsFormat::Jsonl),
+      _ => Err(format!("unknown rejects format `{}`", s)),
+    }
+  }
+}
+
+/// The keys of the JSON objects of the rejects.
+pub const REJECT_FIELDS: &[&str] = &[
+  "original",
+  "normalized_attempt",
+  "reason",
+  "line",
+  "suggestion",
+];
+
+/// Writes a rejected record as the `REJECT_FIELDS`, with the `suggestion`
+/// of its phone number, null when there is none.
+pub fn write_rejected_json(
+  wrt: &mut dyn Sink,
+  rejected: &Rejected,
+  suggestion: Option<&str>,
+) -> Result<(), Error> {
+  let values = vec![
+    Value::from(rejected.record.ph.as_str()),
+    Value::from(rejected.attempt.as_str()),
+    Value::from(rejected.reason.as_str()),
+    Value::from(rejected.line),
+    suggestion.map_or(Value::Null, Value::from),
+  ];
+  let object: Map<String, Value> = REJECT_FIELDS
+    .iter()
+    .map(|&key| key.to_owned())
+    .zip(values)
+    .collect();
+  wrt.write_object(&object)
+}
+
+/// Writes a rejected record with its original fields, the reason and the
+/// `suggestion` of its phone number, if any.
+pub fn write_rejected(
+  wrt: &mut dyn Sink,
+  rejected: &Rejected,
+  suggestion: Option<&str>,
+) -> Result<(), Error> {
+  let record = &rejected.record;
+  wrt.write_field(&record.ph)?;
+  wrt.write_field(&record.name)?;
+  wrt.write_field(&record.count.to_string())?;
+  for value in &record.extra {
+    wrt.write_field(value)?;
+  }
+  wrt.write_field(rejected.reason.as_str())?;
+  wrt.write_field(suggestion.unwrap_or(""))?;
+  wrt.end_record()
+}
+
+/// The columns of the changed records output of `diff`.
+pub const CHANGED_HEADERS: &[&str] =
+  &["ph", "old_name", "new_name", "old_count", "new_count"];
+
+/// Writes the `old` and `new` name and count of the same phone number.
+pub fn write_changed(
+  wrt: &mut dyn Sink,
+  config: &Config,
+  old: &Accepted,
+  new: &Accepted,
+) -> Result<(), Error> {
+  let (old, new) = (&old.record, &new.record);
+  wrt.write_field(&config.format.apply(&new.ph))?;
+  wrt.write_field(&old.name)?;
+  wrt.write_field(&new.name)?;
+  wrt.write_field(&old.count.to_string())?;
+  wrt.write_field(&new.count.to_string())?;
+  wrt.end_record()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{format::Format, reject::RejectReason, sink::JsonlSink, Record};
+
+  fn write(config: &Config, ph: &str) -> String {
+    let mut wrt = csv::Writer::from_writer(vec![]);
+    wrt.write_record(headers(config, &[])).unwrap();
+    let accepted = Accepted {
+      record: Record::new(ph, "test1", 1),
+      original_ph: format!("+{}", ph),
+      fixed: false,
+      extracted: false,
+    };
+    write_record(&mut wrt, config, &accepted).unwrap();
+    String::from_utf8(wrt.into_inner().unwrap()).unwrap()
+  }
+
+  #[test]
+  fn should_write_operator_column() {
+    let config = Config {
+      columns: vec![Column::Operator],
+      ..Config::default()
+    };
+    assert_eq!(
+      write(&config, "201016613061"),
+      "ph,name,count,operator\n201016613061,test1,1,Vodafone\n"
+    );
+  }
+
+  #[test]
+  fn should_write_country_columns() {
+    let config = Config {
+      columns: vec![Column::CountryCode, Column::CountryName],
+      ..Config::default()
+    };
+    assert_eq!(
+      write(&config, "966540029129"),
+      "ph,name,count,country_code,country_name\n\
+       966540029129,test1,1,SA,Saudi Arabia\n"
+    );
+  }
+
+  #[test]
+  fn should_write_original_ph_column() {
+    let config = Config {
+      columns: vec![Column::OriginalPh],
+      ..Config::default()
+    };
+    assert_eq!(
+      write(&config, "201116613061"),
+      "ph,name,count,original_ph\n201116613061,test1,1,+201116613061\n"
+    );
+  }
+
+  #[test]
+  fn should_write_line_type_column() {
+    let config = Config {
+      columns: vec![Column::LineType],
+      ..Config::default()
+    };
+    assert_eq!(
+      write(&config, "20223456789"),
+      "ph,name,count,line_type\n20223456789,test1,1,landline\n"
+    );
+  }
+
+  #[test]
+  fn should_write_wa_link_column() {
+    let config = Config {
+      columns: vec![Column::WaLink],
+      format: Format::National,
+      ..Config::default()
+    };
+    assert_eq!(
+      write(&config, "201116613061"),
+      "ph,name,count,wa_link\n01116613061,test1,1,https://wa.me/201116613061\n"
+    );
+  }
+
+  #[test]
+  fn should_pass_through_columns() {
+    let config = Config {
+      columns: vec![Column::Operator],
+      ..Config::default()
+    };
+    let passed = ["email".to_owned(), "city".to_owned()];
+    let mut wrt = csv::Writer::from_writer(vec![]);
+    wrt.write_record(headers(&config, &passed)).unwrap();
+    let mut record = Record::new("201016613061", "test1", 1);
+    record.extra = vec!["a@b.c".into(), "Cairo".into()];
+    let accepted = Accepted {
+      record,
+      original_ph: "201016613061".into(),
+      fixed: false,
+      extracted: false,
+    };
+    write_record(&mut wrt, &config, &accepted).unwrap();
+    assert_eq!(
+      String::from_utf8(wrt.into_inner().unwrap()).unwrap(),
+      "ph,name,count,email,city,operator\n\
+       201016613061,test1,1,a@b.c,Cairo,Vodafone\n"
+    );
+  }
+
+  #[test]
+  fn should_write_rejected() {
+    let mut wrt = csv::Writer::from_writer(vec![]);
+    wrt.write_record(reject_headers(&["city".into()])).unwrap();
+    let mut record = Record::new("+20 111", "test1", 1);
+    record.extra.push("Cairo".into());
+    let rejected = Rejected {
+      record,
+      attempt: "20111".into(),
+      reason: RejectReason::TooShort,
+      line: 2,
+    };
+    write_rejected(&mut wrt, &rejected, Some("201112345678")).unwrap();
+    let out = String::from_utf8(wrt.into_inner().unwrap()).unwrap();
+    assert_eq!(
+      out,
+      "ph,name,count,city,reject_reason,suggestion\n\
+       +20 111,test1,1,Cairo,TooShort,201112345678\n"
+    );
+    let mut out = Vec::new();
+    let mut wrt = JsonlSink::new(&mut out);
+    wrt.write_row(REJECT_FIELDS).unwrap();
+    write_rejected_json(&mut wrt, &rejected, Some("201112345678")).unwrap();
+    assert_eq!(
+      String::from_utf8(out).unwrap(),
+      "{\"original\":\"+20 111\",\"normalized_attempt\":\"20111\",\
+       \"reason\":\"TooShort\",\"line\":2,\
+       \"suggestion\":\"201112345678\"}\n"
+    );
+    let mut out = Vec::new();
+    let mut wrt = JsonlSink::new(&mut out);
+    wrt.write_row(REJECT_FIELDS).unwrap();
+    write_rejected_json(&mut wrt, &rejected, None).unwrap();
+    let object: Value = serde_json::from_slice(&out).unwrap();
+    assert!(object["suggestion"].is_null());
+  }
+}
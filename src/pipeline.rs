@@ -0,0 +1,1098 @@
+//! The processing core shared by the subcommands, reading the input records
+//! and passing each one, cleaned and validated, to the subcommand.
+
+use std::{
+  cell::Cell,
+  fs::{self, File, OpenOptions},
+  io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+  ops::Range,
+  path::{Path, PathBuf},
+  rc::Rc,
+  sync::mpsc,
+};
+
+use failure::{bail, Error, ResultExt};
+use glob::Pattern;
+use log::{debug, info};
+use rayon::{prelude::*, ThreadPool, ThreadPoolBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  archive,
+  commands::Options,
+  compress::Compression,
+  encoding::{self, Encoding},
+  extract_ph, http, is_good_ph,
+  mmap::{self, Mapping},
+  other_phs,
+  progress::Progress,
+  reject::RejectError,
+  review::Review,
+  s3::{self, UploadSink},
+  schema::Schema,
+  sink::{
+    AtomicSink, JsonlSink, OutputFormat, ParquetSink, PresetSink, Sink,
+    SqlSink, SqliteSink, VcfSink, XlsxSink,
+  },
+  source::{CsvSource, InputFormat, JsonlSource, SheetSource, Source},
+  stats::Stats,
+  Accepted, Config, Record, Rejected,
+};
+
+const BUFFER_SIZE: usize = 64 * 1024;
+/// The records of the chunks cleaned in parallel.
+const CHUNK_RECORDS: usize = 8 * 1024;
+/// The bytes of the segments of the split files parsed in parallel.
+const SEGMENT_BYTES: usize = 1024 * 1024;
+
+/// The writer of an output file or the standard output.
+pub type Writer = Box<dyn Sink>;
+
+/// Reads, cleans and validates every record of the `inputs`, paths or glob
+/// patterns, one after the other, passing each one to `handle`, and
+/// accumulating the statistics into `stats`.
+///
+/// All the inputs must have the same headers, unless they have none.
+pub fn process<F>(
+  inputs: &[PathBuf],
+  options: &Options,
+  config: &Config,
+  stats: &mut Stats,
+  handle: F,
+) -> Result<(), Error>
+where
+  F: FnMut(Result<Accepted, Rejected>) -> Result<(), Error>,
+{
+  Inputs::open(inputs, options)?.process(config, stats, handle)
+}
+
+/// How far a run has processed its inputs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Position {
+  /// The index of the input being processed.
+  pub input: usize,
+  /// The records of that input handled so far.
+  pub records: u64,
+}
+
+/// The input files of a run, the first one opened right away for its
+/// headers to be known before processing any record.
+pub struct Inputs<'a> {
+  options: &'a Options,
+  paths: Vec<PathBuf>,
+  first: Option<Input>,
+  position: Rc<Cell<Position>>,
+  resume: Position,
+  review: Option<Review>,
+}
+
+impl<'a> Inputs<'a> {
+  /// Expands the `inputs` paths or glob patterns, and opens the first one.
+  pub fn open(inputs: &[PathBuf], options: &'a Options) -> Result<Self, Error> {
+    let paths = expand(inputs, &options.zip_entry)?;
+    let first = match paths.first() {
+      Some(path) => Some(Input::open(path, options)?),
+      None => None,
+    };
+    Ok(Inputs {
+      options,
+      paths,
+      first,
+      position: Rc::default(),
+      resume: Position::default(),
+      review: None,
+    })
+  }
+
+  /// The position of the processing, set before each record is handled.
+  pub fn position(&self) -> Rc<Cell<Position>> {
+    self.position.clone()
+  }
+
+  /// Skips the inputs and records before the `position`, already handled
+  /// by an interrupted run.
+  pub fn resume(&mut self, position: Position) {
+    self.resume = position;
+  }
+
+  /// Reviews the rejected records before they are handled, without drawing
+  /// the progress of the inputs.
+  pub fn review(&mut self, review: Review) {
+    if let Some(first) = &mut self.first {
+      first.progress.hide();
+    }
+    self.review = Some(review);
+  }
+
+  /// Whether the lines of the first input end with CRLF.
+  pub fn crlf(&self) -> bool {
+    self.first.as_ref().is_some_and(|input| input.crlf)
+  }
+
+  /// The headers of the input columns passed through to the output, in
+  /// order.
+  pub fn extra_headers(&self) -> &[String] {
+    self
+      .first
+      .as_ref()
+      .map_or(&[], |input| input.schema.extra_headers())
+  }
+
+  /// Processes all the inputs, see [`process`].
+  pub fn process<F>(
+    self,
+    config: &Config,
+    stats: &mut Stats,
+    mut handle: F,
+  ) -> Result<(), Error>
+  where
+    F: FnMut(Result<Accepted, Rejected>) -> Result<(), Error>,
+  {
+    let first = match self.first {
+      Some(first) => first,
+      None => return Ok(()),
+    };
+    let mut review = self.review;
+    let pool = match self.options.jobs {
+      1 => None,
+      jobs => Some(ThreadPoolBuilder::new().num_threads(jobs).build()?),
+    };
+    let pool = pool.as_ref();
+    let expected = first.headers.clone();
+    let mut first = Some(first);
+    // the `--skip` and `--limit` records are counted across the inputs.
+    let mut skip = self.options.skip;
+    let mut limit = self.options.limit;
+    for (index, path) in self.paths.iter().enumerate() {
+      if index < self.resume.input {
+        continue;
+      }
+      if limit == Some(0) {
+        break;
+      }
+      let mut input = match first.take() {
+        Some(first) => first,
+        None => Input::open(path, self.options)?,
+      };
+      if review.is_some() {
+        input.progress.hide();
+      }
+      if !self.options.no_headers && input.headers != expected {
+        bail!(
+          "{}: the headers `{}` don't match the headers `{}` of {}",
+          location(path, 1),
+          join(input.headers.as_byte_record()),
+          join(expected.as_byte_record()),
+          self.paths[0].display()
+        );
+      }
+      let records = if index == self.resume.input {
+        self.resume.records
+      } else {
+        0
+      };
+      self.position.set(Position {
+        input: index,
+        records,
+      });
+      if skip > 0 || limit.is_some() {
+        // the split files are parsed from their start to their end.
+        input.split = None;
+        skip -= input.skip(skip)?;
+        input.remaining = limit;
+      }
+      process_input(
+        &mut input,
+        self.options,
+        config,
+        pool,
+        stats,
+        &self.position,
+        review.as_mut(),
+        &mut handle,
+      )?;
+      limit = input.remaining;
+    }
+    Ok(())
+  }
+}
+
+/// An input file opened up to its records.
+struct Input {
+  path: PathBuf,
+  source: Box<dyn Source>,
+  progress: Progress,
+  headers: csv::StringRecord,
+  schema: Schema,
+  /// The row being read, reused by every record.
+  raw: csv::ByteRecord,
+  /// The mapping of a CSV file with `--mmap`, when it can be split.
+  split: Option<Split>,
+  /// The records left to read with `--limit`.
+  remaining: Option<u64>,
+  /// Whether its lines end with CRLF, like the Windows files.
+  crlf: bool,
+}
+
+/// A memory mapped CSV file whose rows are lines, with no quoted fields.
+struct Split {
+  mapping: Mapping,
+  delimiter: u8,
+}
+
+impl Input {
+  fn open(input: &Path, options: &Options) -> Result<Self, Error> {
+    info!("Reading from {:?}", input);
+    let format = options
+      .input_format
+      .unwrap_or_else(|| InputFormat::of(input));
+    let (mut source, progress, split, crlf) = match format {
+      InputFormat::Xlsx => {
+        let sheet = options.sheet.as_deref();
+        let source = SheetSource::open(input, sheet, !options.no_headers)?;
+        // the whole sheet is loaded at once.
+        (
+          Box::new(source) as Box<dyn Source>,
+          Progress::hidden(),
+          None,
+          false,
+        )
+      },
+      InputFormat::Csv | InputFormat::Jsonl => {
+        open_stream(input, format, options)?
+      },
+    };
+    let headers = source.headers().with_context(|_| {
+      format!("{}: could not read the headers", location(input, 1))
+    })?;
+    let schema = Schema::new(&headers, options).with_context(|_| {
+      format!("{}: could not map the columns", location(input, 1))
+    })?;
+    Ok(Input {
+      path: input.to_owned(),
+      source,
+      progress,
+      headers,
+      schema,
+      raw: csv::ByteRecord::new(),
+      split,
+      remaining: None,
+      crlf,
+    })
+  }
+
+  /// Skips the next `n` records at most, without parsing them, returning
+  /// how many there were.
+  fn skip(&mut self, n: u64) -> Result<u64, Error> {
+    let mut skipped = 0;
+    while skipped < n && self.source.read(&mut self.raw)? {
+      skipped += 1;
+    }
+    Ok(skipped)
+  }
+}
+
+/// The rows of an input with the progress bar of its reading, its split
+/// with `--mmap`, and whether its lines end with CRLF.
+type Stream = (Box<dyn Source>, Progress, Option<Split>, bool);
+
+/// Opens the CSV or JSON Lines file at `input`, or the standard input for
+/// `-`.
+fn open_stream(
+  input: &Path,
+  format: InputFormat,
+  options: &Options,
+) -> Result<Stream, Error> {
+  info!("I/O Buffer Size: {} byte", BUFFER_SIZE);
+  let mut mapping = None;
+  let (reader, len): (Box<dyn Read>, _) = if is_stdio(input) {
+    (Box::new(io::stdin()), None)
+  } else if http::is_url(input) {
+    http::open(input, &options.header)?
+  } else if s3::is_s3(input) {
+    s3::open(input)?
+  } else if let Some((archive, entry)) = archive::split(input) {
+    let (reader, len) = archive::open(archive, entry)?;
+    (reader, Some(len))
+  } else if options.mmap {
+    let (map, len) = Mapping::open(input)?;
+    mapping = Some(map.clone());
+    (Box::new(io::Cursor::new(map)), Some(len))
+  } else {
+    let c = File::open(input)
+      .with_context(|_| format!("could not open input file {:?}", input))?;
+    let len = c.metadata()?.len();
+    (Box::new(c), Some(len))
+  };
+  let progress = Progress::new(len, options.progress, options.quiet);
+  // the progress is the one of the compressed bytes.
+  let raw = BufReader::with_capacity(BUFFER_SIZE, progress.wrap(reader));
+  let decoded = Compression::decoder(raw)?;
+  let buffer = BufReader::with_capacity(BUFFER_SIZE, decoded);
+  let mut buffer = encoding::decode(buffer, options.encoding)?;
+  let crlf = sniff_crlf(buffer.fill_buf()?);
+  if format == InputFormat::Jsonl {
+    return Ok((Box::new(JsonlSource::new(buffer)), progress, None, crlf));
+  }
+  let delimiter = match options.delimiter {
+    Some(delimiter) => delimiter,
+    // peeking at the buffer doesn't consume it, even for the stdin.
+    None if options.detect_delimiter => {
+      let delimiter = sniff(buffer.fill_buf()?);
+      info!("Detected the {:?} delimiter", char::from(delimiter));
+      delimiter
+    },
+    None => b',',
+  };
+  // the lines of the compressed or quoted files aren't the rows, and the
+  // transcoded ones aren't their bytes.
+  let split = mapping
+    .filter(|_| options.encoding == Encoding::Utf8)
+    .filter(|m| Compression::sniff(m.as_ref()) == Compression::None)
+    .filter(|m| !m.as_ref().contains(&b'"'))
+    .map(|mapping| Split { mapping, delimiter });
+  if options.mmap && split.is_none() {
+    info!("The {:?} rows will be parsed by a single thread", input);
+  }
+  let wrapped: Box<dyn Read> = Box::new(buffer);
+  let rdr = csv::ReaderBuilder::new()
+    .has_headers(!options.no_headers)
+    .delimiter(delimiter)
+    .from_reader(wrapped);
+  Ok((Box::new(CsvSource::new(rdr)), progress, split, crlf))
+}
+
+/// The records of a chunk with the lines of the rows, in the input order.
+#[derive(Default)]
+struct Chunk {
+  lines: Vec<u64>,
+  /// The records read, or rejected already for their invalid count.
+  records: Vec<Result<Record, Rejected>>,
+  /// The raw rows, only kept for the errors of `--fail-fast`.
+  rows: Vec<csv::ByteRecord>,
+  /// The cells of several phone numbers split by `--split-cell`.
+  split: u64,
+}
+
+impl Chunk {
+  /// Adds the record of the `row` at the `line` of the input at `path`, or
+  /// a record by phone number of its cell with `--split-cell`, and of its
+  /// `--ph-columns` with `--explode`.
+  fn push(
+    &mut self,
+    schema: &Schema,
+    row: &csv::ByteRecord,
+    path: &Path,
+    line: u64,
+    keep_rows: bool,
+  ) -> Result<(), Error> {
+    let record = match schema.read(row) {
+      Ok(record) => record,
+      Err(e) => bail!(
+        "{}: malformed record `{}`: {}",
+        location(path, line),
+        join(row),
+        e
+      ),
+    };
+    let add = |chunk: &mut Chunk, record| {
+      chunk.records.push(record);
+      chunk.lines.push(line);
+      if keep_rows {
+        chunk.rows.push(row.clone());
+      }
+    };
+    let separators = schema.split_cell();
+    let record = match record {
+      Ok(record) if record.ph.contains(separators) || schema.explode() => {
+        record
+      },
+      record => {
+        add(self, record);
+        return Ok(());
+      },
+    };
+    let mut cells = vec![record.ph.as_str()];
+    if schema.explode() {
+      cells.extend(schema.phones().iter().map(|&i| record.extra[i].as_str()));
+    }
+    let mut phs = Vec::new();
+    for cell in cells {
+      if cell.contains(separators) {
+        self.split += 1;
+        phs.extend(cell.split(separators).map(str::trim));
+      } else {
+        phs.push(cell.trim());
+      }
+    }
+    phs.retain(|ph| !ph.is_empty());
+    if phs.is_empty() {
+      add(self, Ok(record.clone()));
+    }
+    for ph in phs {
+      let ph = ph.to_owned();
+      add(
+        self,
+        Ok(Record {
+          ph,
+          ..record.clone()
+        }),
+      );
+    }
+    Ok(())
+  }
+}
+
+impl Input {
+  /// Reads the next `size` records at most into the emptied `chunk`,
+  /// returning whether there was any.
+  fn read_chunk(
+    &mut self,
+    chunk: &mut Chunk,
+    size: usize,
+    keep_rows: bool,
+  ) -> Result<bool, Error> {
+    let path = &self.path;
+    let raw = &mut self.raw;
+    chunk.lines.clear();
+    chunk.records.clear();
+    chunk.rows.clear();
+    chunk.split = 0;
+    while chunk.lines.len() < size && self.remaining != Some(0) {
+      let next = self.source.line();
+      let more = self.source.read(raw).with_context(|_| {
+        format!("{}: could not read record", location(path, next))
+      })?;
+      if !more {
+        break;
+      }
+      let line = raw.position().map_or(next, |p| p.line());
+      chunk.push(&self.schema, raw, path, line, keep_rows)?;
+      if let Some(remaining) = &mut self.remaining {
+        *remaining -= 1;
+      }
+    }
+    self.progress.add_rows(chunk.lines.len());
+    Ok(!chunk.lines.is_empty())
+  }
+}
+
+/// Cleans and validates the `record`, if it was read, with the other phone
+/// numbers of the `schema`, then looking for its phone number in the
+/// `--extract-from` column, if any.
+fn validate(
+  config: &Config,
+  schema: &Schema,
+  record: Result<Record, Rejected>,
+) -> Result<Accepted, Rejected> {
+  let mut result = record.and_then(|record| is_good_ph(config, record));
+  if !schema.explode() && !schema.phones().is_empty() {
+    result = other_phs(config, result, schema.phones());
+  }
+  match (result, schema.extract()) {
+    (Err(rejected), Some(column)) => extract_ph(config, rejected, column),
+    (result, _) => result,
+  }
+}
+
+/// Parses the rows of a `segment` of a split file starting at its `line`,
+/// into a chunk, with their results.
+#[allow(clippy::too_many_arguments)]
+fn parse_segment(
+  segment: &[u8],
+  line: u64,
+  delimiter: u8,
+  path: &Path,
+  schema: &Schema,
+  config: &Config,
+  keep_rows: bool,
+) -> Result<(Chunk, Vec<Result<Accepted, Rejected>>), Error> {
+  let mut rdr = csv::ReaderBuilder::new()
+    .has_headers(false)
+    .delimiter(delimiter)
+    .from_reader(segment);
+  let mut chunk = Chunk::default();
+  let mut raw = csv::ByteRecord::new();
+  loop {
+    let next = line + rdr.position().line() - 1;
+    let more = rdr.read_byte_record(&mut raw).with_context(|_| {
+      format!("{}: could not read record", location(path, next))
+    })?;
+    if !more {
+      break;
+    }
+    let at = raw.position().map_or(next, |p| line + p.line() - 1);
+    chunk.push(schema, &raw, path, at, keep_rows)?;
+  }
+  let records = std::mem::take(&mut chunk.records);
+  let results = records
+    .into_iter()
+    .map(|r| validate(config, schema, r))
+    .collect();
+  Ok((chunk, results))
+}
+
+/// Processes the records of a single `input`, cleaning and validating them
+/// in chunks on the threads of the `pool`, if any, the rejected ones being
+/// reviewed in order.
+///
+/// With `--unordered`, the chunks are handled as soon as they are done,
+/// while the next ones are read. The split files are parsed on the threads
+/// too, a segment per thread.
+#[allow(clippy::too_many_arguments)]
+fn process_input<F>(
+  input: &mut Input,
+  options: &Options,
+  config: &Config,
+  pool: Option<&ThreadPool>,
+  stats: &mut Stats,
+  position: &Cell<Position>,
+  mut review: Option<&mut Review>,
+  handle: &mut F,
+) -> Result<(), Error>
+where
+  F: FnMut(Result<Accepted, Rejected>) -> Result<(), Error>,
+{
+  let thresholds = options.thresholds();
+  let path = input.path.clone();
+  let keep_rows = options.fail_fast;
+  // the records already handled by an interrupted run.
+  input.skip(position.get().records)?;
+  // the results are drained, in the order of the rows of the chunk.
+  let mut handle_chunk = |chunk: &Chunk,
+                          results: &mut Vec<Result<Accepted, Rejected>>|
+   -> Result<(), Error> {
+    stats.split_cells += chunk.split;
+    for (i, mut result) in results.drain(..).enumerate() {
+      if let (Err(_), Some(review)) = (&result, &mut review) {
+        let rejected = result.unwrap_err();
+        result = review.review(config, rejected, chunk.lines[i])?;
+      }
+      let reason = match &mut result {
+        Ok(accepted) => {
+          stats.accept_record(accepted, &path, chunk.lines[i]);
+          None
+        },
+        Err(rejected) => {
+          rejected.line = chunk.lines[i];
+          debug!(
+            path:% = path.display(),
+            line = chunk.lines[i],
+            reason:% = rejected.reason;
+            "{}: Not Acceptable ({}): {:?}",
+            location(&path, chunk.lines[i]),
+            rejected.reason,
+            rejected.record
+          );
+          stats.reject_record(rejected);
+          Some(rejected.reason)
+        },
+      };
+      let at = position.get();
+      position.set(Position {
+        records: at.records + 1,
+        ..at
+      });
+      handle(result)?;
+      if let Some(reason) = reason {
+        if options.fail_fast {
+          return Err(
+            RejectError::Rejected(format!(
+              "{}: rejected record `{}` ({})",
+              location(&path, chunk.lines[i]),
+              join(&chunk.rows[i]),
+              reason
+            ))
+            .into(),
+          );
+        }
+        thresholds.check_count(stats.rejected())?;
+      }
+    }
+    Ok(())
+  };
+  // the workers validate the records while the input reads the next ones.
+  let schema = input.schema.clone();
+  let clean = |record| validate(config, &schema, record);
+  let mut chunk = Chunk::default();
+  let mut results = Vec::new();
+  match pool {
+    Some(pool) if input.split.is_some() => {
+      let split = input.split.as_ref().unwrap();
+      let (data, schema) = (split.mapping.as_ref(), &input.schema);
+      let mut line = input.source.line();
+      let start = mmap::offset_of_line(data, line);
+      let segments = mmap::segments(data, start, SEGMENT_BYTES);
+      for batch in segments.chunks(pool.current_num_threads()) {
+        let parsed: Vec<_> = pool.install(|| {
+          batch
+            .par_iter()
+            .map(|(range, _)| {
+              let first = line + batch_lines(batch, range.start);
+              parse_segment(
+                &data[range.clone()],
+                first,
+                split.delimiter,
+                &path,
+                schema,
+                config,
+                keep_rows,
+              )
+            })
+            .collect()
+        });
+        for result in parsed {
+          let (chunk, mut results) = result?;
+          input.progress.add_rows(chunk.lines.len());
+          handle_chunk(&chunk, &mut results)?;
+        }
+        line += batch.iter().map(|(_, lines)| lines).sum::<u64>();
+        input
+          .progress
+          .set_bytes(batch.last().map_or(0, |(r, _)| r.end) as u64);
+      }
+    },
+    // the chunks bound the records held in memory, waiting for their turn.
+    Some(pool) if options.unordered => {
+      let (tx, rx) = mpsc::channel();
+      let max_in_flight = 2 * pool.current_num_threads();
+      pool.in_place_scope(|scope| -> Result<(), Error> {
+        let mut in_flight = 0;
+        let mut done = false;
+        loop {
+          while !done && in_flight < max_in_flight {
+            let mut chunk = Chunk::default();
+            if !input.read_chunk(&mut chunk, CHUNK_RECORDS, keep_rows)? {
+              done = true;
+              break;
+            }
+            let tx = tx.clone();
+            scope.spawn(move |_| {
+              let results = chunk.records.drain(..).map(clean).collect();
+              let _ = tx.send((chunk, results));
+            });
+            in_flight += 1;
+          }
+          if in_flight == 0 {
+            return Ok(());
+          }
+          let (chunk, mut results) = rx.recv()?;
+          in_flight -= 1;
+          handle_chunk(&chunk, &mut results)?;
+        }
+      })?;
+    },
+    Some(pool) => {
+      while input.read_chunk(&mut chunk, CHUNK_RECORDS, keep_rows)? {
+        pool.install(|| {
+          chunk
+            .records
+            .par_drain(..)
+            .map(clean)
+            .collect_into_vec(&mut results)
+        });
+        handle_chunk(&chunk, &mut results)?;
+      }
+    },
+    None => {
+      while input.read_chunk(&mut chunk, 1, keep_rows)? {
+        results.extend(chunk.records.drain(..).map(clean));
+        handle_chunk(&chunk, &mut results)?;
+      }
+    },
+  }
+  input.progress.finish();
+  Ok(())
+}
+
+/// The lines of the segments of the `batch` before the one at `start`.
+fn batch_lines(batch: &[(Range<usize>, u64)], start: usize) -> u64 {
+  let before = batch.iter().take_while(|(range, _)| range.start < start);
+  before.map(|(_, lines)| lines).sum()
+}
+
+/// Creates the output file at `path`, or writes to the standard output for
+/// `-`, in the output format of the `config`, writing the `headers` row.
+pub fn create(
+  path: &Path,
+  config: &Config,
+  headers: &[&str],
+) -> Result<Writer, Error> {
+  create_as(path, config, config.output_format, headers)
+}
+
+/// Creates the output file at `path` like `create`, in the `format`.
+pub fn create_as(
+  path: &Path,
+  config: &Config,
+  format: OutputFormat,
+  headers: &[&str],
+) -> Result<Writer, Error> {
+  let compression = config.compression.unwrap_or_else(|| Compression::of(path));
+  // the objects are written locally first, then uploaded when finished.
+  let url = path;
+  let local = if s3::is_s3(url) {
+    Some(UploadSink::local_path(url))
+  } else {
+    None
+  };
+  let exists = !is_stdio(url) && url.exists();
+  if exists && !config.overwrite && format != OutputFormat::Sqlite {
+    bail!(
+      "the output file {:?} already exists, use --force to overwrite it",
+      url
+    );
+  }
+  let partial = partial_path(url, config);
+  let path = local.as_deref().unwrap_or(&partial);
+  // sendable, as required by the parquet writer.
+  let open = || -> Result<BufWriter<Box<dyn Write + Send>>, Error> {
+    let out: Box<dyn Write + Send> = if is_stdio(path) {
+      Box::new(io::stdout())
+    } else {
+      let file = File::create(path)
+        .with_context(|_| format!("could not create output file {:?}", path))?;
+      Box::new(file)
+    };
+    let mut out = compression.encoder(out)?;
+    // the text of the other formats is always UTF-8.
+    if format != OutputFormat::Parquet {
+      out = encoding::encode(out, config.output_encoding)?;
+    }
+    Ok(BufWriter::with_capacity(BUFFER_SIZE, out))
+  };
+  if compression != Compression::None
+    && (format == OutputFormat::Xlsx || format == OutputFormat::Sqlite)
+  {
+    bail!("the {:?} output format can't be compressed", format);
+  }
+  let mut wrt: Writer = match format {
+    OutputFormat::Csv => Box::new(
+      csv::WriterBuilder::new()
+        .delimiter(config.output_delimiter)
+        .terminator(terminator(config))
+        .from_writer(open()?),
+    ),
+    OutputFormat::Xlsx => Box::new(XlsxSink::new(path)?),
+    OutputFormat::Jsonl => Box::new(JsonlSink::new(open()?)),
+    OutputFormat::Parquet => Box::new(ParquetSink::new(open()?)),
+    OutputFormat::Sqlite => Box::new(SqliteSink::new(path, &config.table)?),
+    OutputFormat::Vcf => Box::new(VcfSink::new(open()?)),
+    // the importers expect the commas.
+    OutputFormat::Contacts(preset) => {
+      let wrt = csv::WriterBuilder::new()
+        .terminator(terminator(config))
+        .from_writer(open()?);
+      Box::new(PresetSink::new(wrt, preset))
+    },
+    OutputFormat::Sql => {
+      Box::new(SqlSink::new(open()?, &config.table, config.sql_dialect))
+    },
+  };
+  if let Some(local) = local {
+    wrt = Box::new(UploadSink::new(wrt, local, url));
+  }
+  if is_atomic(url, config) {
+    wrt = Box::new(AtomicSink::new(wrt, url));
+  }
+  wrt.write_row(headers)?;
+  Ok(wrt)
+}
+
+/// The line ending of the CSV outputs.
+fn terminator(config: &Config) -> csv::Terminator {
+  if config.crlf {
+    csv::Terminator::CRLF
+  } else {
+    csv::Terminator::Any(b'\n')
+  }
+}
+
+/// Appends to the output file at `path`, truncated to its first `len`
+/// bytes, the rows written before a checkpoint, the `headers` already
+/// being there.
+///
+/// The atomic outputs are still in their temporary file.
+pub fn append(
+  url: &Path,
+  config: &Config,
+  headers: &[&str],
+  len: u64,
+) -> Result<Writer, Error> {
+  append_as(url, config, config.output_format, headers, len)
+}
+
+/// Appends to the output file at `path` like `append`, in the `format`.
+pub fn append_as(
+  url: &Path,
+  config: &Config,
+  format: OutputFormat,
+  headers: &[&str],
+  len: u64,
+) -> Result<Writer, Error> {
+  let path = &partial_path(url, config);
+  let mut file = OpenOptions::new()
+    .write(true)
+    .open(path)
+    .with_context(|_| format!("could not open output file {:?}", path))?;
+  file.set_len(len)?;
+  file.seek(SeekFrom::End(0))?;
+  let file = encoding::encode(file, config.output_encoding.appended())?;
+  let out = BufWriter::with_capacity(BUFFER_SIZE, file);
+  let mut wrt: Writer = match format {
+    OutputFormat::Csv => Box::new(
+      csv::WriterBuilder::new()
+        .delimiter(config.output_delimiter)
+        .terminator(terminator(config))
+        .from_writer(out),
+    ),
+    // the first row only sets the keys of the objects.
+    OutputFormat::Jsonl => {
+      let mut wrt = JsonlSink::new(out);
+      wrt.write_row(headers)?;
+      Box::new(wrt)
+    },
+    format => bail!("the {:?} output format can't be appended to", format),
+  };
+  if is_atomic(url, config) {
+    wrt = Box::new(AtomicSink::new(wrt, url));
+  }
+  Ok(wrt)
+}
+
+/// Appends to the output file at `path` if it exists, checking that its
+/// headers are the `headers`, or creates it.
+pub fn extend(
+  path: &Path,
+  config: &Config,
+  headers: &[&str],
+) -> Result<Writer, Error> {
+  if !path.exists() {
+    return create(path, config, headers);
+  }
+  if config.output_format == OutputFormat::Csv {
+    let mut rdr = csv::ReaderBuilder::new()
+      .delimiter(config.output_delimiter)
+      .from_path(path)
+      .with_context(|_| format!("could not open output file {:?}", path))?;
+    let existing = rdr.headers()?;
+    if existing.iter().ne(headers.iter().copied()) {
+      bail!(
+        "{}: the headers `{}` don't match the output headers `{}`",
+        location(path, 1),
+        join(existing.as_byte_record()),
+        headers.join(",")
+      );
+    }
+  }
+  let len = fs::metadata(path)?.len();
+  // the atomic outputs are appended to a copy.
+  if is_atomic(path, config) {
+    fs::copy(path, AtomicSink::tmp_path(path))
+      .with_context(|_| format!("could not copy output file {:?}", path))?;
+  }
+  append(path, config, headers, len)
+}
+
+/// Whether the output at `path` is written to a temporary file renamed
+/// once complete, see [`AtomicSink`].
+///
+/// The SQLite databases are updated in a transaction, and the objects are
+/// written locally first anyway.
+fn is_atomic(path: &Path, config: &Config) -> bool {
+  config.atomic
+    && config.output_format != OutputFormat::Sqlite
+    && !s3::is_s3(path)
+    && !is_stdio(path)
+}
+
+/// The local file the output at `path` is written to until it is finished.
+pub fn partial_path(path: &Path, config: &Config) -> PathBuf {
+  if is_atomic(path, config) {
+    AtomicSink::tmp_path(path)
+  } else {
+    path.to_owned()
+  }
+}
+
+/// Expands the glob `patterns`, like `exports/*.csv`, into the matching
+/// paths, in order, and the ZIP archives into their entries matching the
+/// `entry` pattern. The other paths are kept as they are.
+fn expand(
+  patterns: &[PathBuf],
+  entry: &Pattern,
+) -> Result<Vec<PathBuf>, Error> {
+  let mut paths = Vec::new();
+  for pattern in patterns {
+    let s = pattern.to_string_lossy();
+    if archive::is_archive(pattern) {
+      paths.extend(archive::entries(pattern, entry)?);
+      continue;
+    }
+    if http::is_url(pattern)
+      || pattern.exists()
+      || !s.contains(&['*', '?', '['][..])
+    {
+      paths.push(pattern.clone());
+      continue;
+    }
+    let matches = glob::glob(&s)
+      .with_context(|_| format!("invalid glob pattern {:?}", pattern))?
+      .collect::<Result<Vec<_>, _>>()?;
+    if matches.is_empty() {
+      bail!("no file matches {:?}", pattern);
+    }
+    for path in matches {
+      if archive::is_archive(&path) {
+        paths.extend(archive::entries(&path, entry)?);
+      } else {
+        paths.push(path);
+      }
+    }
+  }
+  Ok(paths)
+}
+
+/// Parses a delimiter of a single ASCII character, `\\t` or `tab` for the
+/// tabs.
+pub fn parse_delimiter(s: &str) -> Result<u8, String> {
+  match s {
+    "\\t" | "tab" => Ok(b'\t'),
+    _ if s.len() == 1 && s.is_ascii() => Ok(s.as_bytes()[0]),
+    _ => Err(format!("the delimiter `{}` is not a single character", s)),
+  }
+}
+
+/// The most frequent delimiter of the first line of `buf`, a comma if none
+/// is found.
+fn sniff(buf: &[u8]) -> u8 {
+  let line = buf.split(|&b| b == b'\n').next().unwrap_or(buf);
+  let count = |d: &u8| line.iter().filter(|&b| b == d).count();
+  [b',', b';', b'\t', b'|']
+    .iter()
+    .filter(|d| count(d) > 0)
+    .max_by_key(|d| count(d))
+    .map_or(b',', |&d| d)
+}
+
+/// Whether the first line of the `buf` ends with CRLF.
+fn sniff_crlf(buf: &[u8]) -> bool {
+  let end = buf.iter().position(|&b| b == b'\n');
+  end.is_some_and(|i| i > 0 && buf[i - 1] == b'\r')
+}
+
+/// Whether the `path` is `-`, standing for the standard input or output.
+pub fn is_stdio(path: &Path) -> bool {
+  path == Path::new("-")
+}
+
+/// The `file:line` of a record, lines are 1-based like the editors.
+fn location(path: &Path, line: u64) -> String {
+  if is_stdio(path) {
+    format!("<stdin>:{}", line)
+  } else {
+    format!("{}:{}", path.display(), line)
+  }
+}
+
+/// Joins the fields of a raw record back, for the error messages.
+fn join(raw: &csv::ByteRecord) -> String {
+  let fields: Vec<_> = raw.iter().map(String::from_utf8_lossy).collect();
+  fields.join(",")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn should_expand_globs() {
+    let entry = Pattern::new("*.csv").unwrap();
+    let paths = expand(&["src/*.rs".into(), "-".into()], &entry).unwrap();
+    assert!(paths.contains(&PathBuf::from("src/main.rs")));
+    assert_eq!(paths.last(), Some(&PathBuf::from("-")));
+    assert!(expand(&["src/*.nope".into()], &entry).is_err());
+  }
+
+  #[test]
+  fn should_sniff_delimiters() {
+    assert_eq!(sniff(b"ph;name;count\n0111,6;a;1\n"), b';');
+    assert_eq!(sniff(b"ph\tname\tcount"), b'\t');
+    assert_eq!(sniff(b"ph|name,x|count\n"), b'|');
+    assert_eq!(sniff(b"ph\n"), b',');
+    assert_eq!(parse_delimiter("\\t"), Ok(b'\t'));
+    assert_eq!(parse_delimiter(";"), Ok(b';'));
+    assert!(parse_delimiter(";;").is_err());
+  }
+
+  #[test]
+  fn should_keep_line_endings() {
+    assert!(sniff_crlf(b"ph,name\r\n0111,a\n"));
+    assert!(!sniff_crlf(b"ph,name\n0111,a\r\n"));
+    assert!(!sniff_crlf(b"\r"));
+    let path = std::env::temp_dir().join("mobcsv-crlf.csv");
+    let config = Config {
+      crlf: true,
+      overwrite: true,
+      ..Config::default()
+    };
+    let mut wrt = create(&path, &config, &["ph", "name"]).unwrap();
+    wrt.write_row(&["201116613061", "a"]).unwrap();
+    wrt.finish().unwrap();
+    assert_eq!(
+      fs::read_to_string(&path).unwrap(),
+      "ph,name\r\n201116613061,a\r\n"
+    );
+  }
+
+  #[test]
+  fn should_split_cells() {
+    use structopt::StructOpt;
+
+    let options = Options::from_iter(&["mobcsv", "--split-cell", "/;"]);
+    let headers = csv::StringRecord::from(vec!["ph", "name", "count"]);
+    let schema = Schema::new(&headers, &options).unwrap();
+    let mut chunk = Chunk::default();
+    let path = Path::new("in.csv");
+    let row = csv::ByteRecord::from(vec!["0111 / 0122;", "a", "3"]);
+    chunk.push(&schema, &row, path, 2, false).unwrap();
+    let row = csv::ByteRecord::from(vec!["0133", "b", "1"]);
+    chunk.push(&schema, &row, path, 3, false).unwrap();
+    let phs: Vec<_> = chunk
+      .records
+      .iter()
+      .map(|r| r.as_ref().unwrap().ph.as_str())
+      .collect();
+    assert_eq!(phs, ["0111", "0122", "0133"]);
+    assert_eq!(chunk.lines, [2, 2, 3]);
+    assert_eq!(chunk.split, 1);
+  }
+
+  #[test]
+  fn should_read_phone_columns() {
+    use structopt::StructOpt;
+
+    let headers =
+      csv::StringRecord::from(vec!["name", "count", "mobile", "whatsapp"]);
+    let row = csv::ByteRecord::from(vec!["a", "1", "0111", "01116613061"]);
+    let path = Path::new("in.csv");
+    let args = ["mobcsv", "--ph-columns", "mobile,WhatsApp", "--explode"];
+    let schema = Schema::new(&headers, &Options::from_iter(&args)).unwrap();
+    let mut chunk = Chunk::default();
+    chunk.push(&schema, &row, path, 2, false).unwrap();
+    let phs: Vec<_> = chunk
+      .records
+      .iter()
+      .map(|r| r.as_ref().unwrap().ph.as_str())
+      .collect();
+    assert_eq!(phs, ["0111", "01116613061"]);
+    let schema =
+      Schema::new(&headers, &Options::from_iter(&args[..3])).unwrap();
+    let record = schema.record(&row).unwrap();
+    let accepted = validate(&Config::default(), &schema, Ok(record)).unwrap();
+    assert_eq!(accepted.record.ph, "201116613061");
+    assert_eq!(accepted.record.extra, ["201116613061"]);
+    let args = ["mobcsv", "--ph-columns", "mobile,mobile2"];
+    assert!(Schema::new(&headers, &Options::from_iter(&args)).is_err());
+  }
+}
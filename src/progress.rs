@@ -0,0 +1,205 @@
+//! The progress of reading an input, the rows and bytes read so far.
+
+use std::{
+  cell::Cell,
+  io::{self, Read},
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+  },
+  time::{Duration, Instant},
+};
+
+use indicatif::{
+  HumanBytes, HumanDuration, ProgressBar, ProgressDrawTarget, ProgressStyle,
+};
+
+/// The rows between two updates of the progress.
+const UPDATE_ROWS: u64 = 4096;
+/// The time between two plain progress lines.
+const PLAIN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The progress bar of an input, drawn when the standard error is a
+/// terminal, or written as plain lines if forced with `--progress`.
+pub struct Progress {
+  pb: ProgressBar,
+  /// The bytes read, shared with the reader of the input.
+  bytes: Arc<AtomicU64>,
+  len: Option<u64>,
+  rows: Cell<u64>,
+  next_update: Cell<u64>,
+  started: Instant,
+  /// When the last plain line was written, if they are.
+  plain: Option<Cell<Instant>>,
+}
+
+impl Progress {
+  /// The progress of an input of `len` bytes, if known, shown unless
+  /// `quiet`.
+  pub fn new(len: Option<u64>, force: bool, quiet: bool) -> Self {
+    let pb = match len {
+      Some(len) => {
+        let pb = ProgressBar::new(len);
+        pb.set_style(
+          ProgressStyle::default_bar()
+            .template(
+              "{prefix:.bold.dim} {spinner:.green} [{elapsed_precise}] \
+               [{bar:40.cyan/blue}] {bytes}/{total_bytes} {percent}% {msg} \
+               ({eta})",
+            )
+            .tick_chars("∙∙∙●∙∙∙●∙∙∙●")
+            .progress_chars("=> "),
+        );
+        pb
+      },
+      // we can't tell how large the standard input is.
+      None => {
+        let pb = ProgressBar::new_spinner();
+        pb.set_style(
+          ProgressStyle::default_spinner()
+            .template(
+              "{prefix:.bold.dim} {spinner:.green} [{elapsed_precise}] \
+               {bytes} {msg}",
+            )
+            .tick_chars("∙∙∙●∙∙∙●∙∙∙●"),
+        );
+        pb
+      },
+    };
+    if quiet {
+      pb.set_draw_target(ProgressDrawTarget::hidden());
+    } else if let Some(len) = len {
+      pb.println(format!("The input file is {} large", HumanBytes(len)));
+    }
+    pb.set_prefix("Working");
+    let now = Instant::now();
+    // the bar is hidden when the standard error isn't a terminal.
+    let plain = if force && !quiet && pb.is_hidden() {
+      Some(Cell::new(now))
+    } else {
+      None
+    };
+    Progress {
+      plain,
+      pb,
+      bytes: Arc::default(),
+      len,
+      rows: Cell::new(0),
+      next_update: Cell::new(UPDATE_ROWS),
+      started: now,
+    }
+  }
+
+  /// No progress at all, like for the workbooks, loaded at once.
+  pub fn hidden() -> Self {
+    Progress {
+      pb: ProgressBar::hidden(),
+      bytes: Arc::default(),
+      len: None,
+      rows: Cell::new(0),
+      next_update: Cell::new(u64::MAX),
+      started: Instant::now(),
+      plain: None,
+    }
+  }
+
+  /// Stops drawing the progress, like while asking questions.
+  pub fn hide(&mut self) {
+    self.pb.set_draw_target(ProgressDrawTarget::hidden());
+    self.plain = None;
+  }
+
+  /// Counts the bytes read from the `reader`.
+  pub fn wrap<R: Read>(&self, reader: R) -> CountingRead<R> {
+    CountingRead {
+      inner: reader,
+      bytes: self.bytes.clone(),
+    }
+  }
+
+  /// Sets the bytes read so far, when they aren't read through [`wrap`].
+  pub fn set_bytes(&self, bytes: u64) {
+    self.bytes.store(bytes, Ordering::Relaxed);
+  }
+
+  /// Counts `n` more rows read.
+  pub fn add_rows(&self, n: usize) {
+    let rows = self.rows.get() + n as u64;
+    self.rows.set(rows);
+    if rows >= self.next_update.get() {
+      self.next_update.set(rows + UPDATE_ROWS);
+      self.update();
+    }
+  }
+
+  fn update(&self) {
+    let rows = self.rows.get();
+    let bytes = self.bytes.load(Ordering::Relaxed);
+    let elapsed = self.started.elapsed();
+    let rate = rows as f64 / elapsed.as_secs_f64().max(f64::EPSILON);
+    match &self.plain {
+      Some(last) if last.get().elapsed() >= PLAIN_INTERVAL => {
+        last.set(Instant::now());
+        let read = match self.len {
+          Some(len) if bytes > 0 => {
+            let left = len.saturating_sub(bytes) as f64 / bytes as f64;
+            let left = elapsed.mul_f64(left);
+            format!(
+              "{} of {} ({:.0}%), {} left",
+              HumanBytes(bytes),
+              HumanBytes(len),
+              bytes as f64 * 100.0 / len as f64,
+              HumanDuration(left)
+            )
+          },
+          _ => format!("{}", HumanBytes(bytes)),
+        };
+        eprintln!("Read {} rows ({:.0} rows/s), {}", rows, rate, read);
+      },
+      Some(_) => {},
+      None => {
+        self.pb.set_position(bytes);
+        self
+          .pb
+          .set_message(&format!("{} rows ({:.0}/s)", rows, rate));
+      },
+    }
+  }
+
+  pub fn finish(&self) {
+    self.pb.finish_and_clear();
+  }
+}
+
+/// A reader counting the bytes it reads.
+pub struct CountingRead<R> {
+  inner: R,
+  bytes: Arc<AtomicU64>,
+}
+
+impl<R: Read> Read for CountingRead<R> {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    let n = self.inner.read(buf)?;
+    self.bytes.fetch_add(n as u64, Ordering::Relaxed);
+    Ok(n)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn should_count_bytes_and_rows() {
+    let progress = Progress::new(Some(8), true, false);
+    let mut data = String::new();
+    progress
+      .wrap(&b"ph,name\n"[..])
+      .read_to_string(&mut data)
+      .unwrap();
+    assert_eq!(progress.bytes.load(Ordering::Relaxed), 8);
+    progress.add_rows(UPDATE_ROWS as usize + 1);
+    assert_eq!(progress.rows.get(), UPDATE_ROWS + 1);
+    assert_eq!(progress.next_update.get(), 2 * UPDATE_ROWS + 1);
+  }
+}
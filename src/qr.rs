@@ -0,0 +1,103 @@
+//! The QR codes of the accepted records, a PNG image per phone number.
+
+use std::{fs::File, io::BufWriter, path::Path, str::FromStr};
+
+use failure::{Error, ResultExt};
+use qrcode::{Color, QrCode};
+
+/// The pixels of a QR code module.
+const SCALE: usize = 8;
+/// The light modules around the code, as required by the readers.
+const QUIET_ZONE: usize = 4;
+
+/// The URI encoded in the QR codes.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum QrUri {
+  /// `tel:+201116613061`, calling the number.
+  #[default]
+  Tel,
+  /// `https://wa.me/201116613061`, chatting with it on WhatsApp.
+  Wa,
+}
+
+impl QrUri {
+  pub const VARIANTS: &'static [&'static str] = &["tel", "wa"];
+
+  /// The URI of a normalized number.
+  pub fn of(self, ph: &str) -> String {
+    match self {
+      QrUri::Tel => format!("tel:+{}", ph),
+      QrUri::Wa => format!("https://wa.me/{}", ph),
+    }
+  }
+}
+
+impl FromStr for QrUri {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "tel" => Ok(QrUri::Tel),
+      "wa" => Ok(QrUri::Wa),
+      _ => Err(format!("unknown QR code URI `{}`", s)),
+    }
+  }
+}
+
+/// The grayscale pixels of the QR code of `data`, with its width.
+fn render(data: &str) -> Result<(Vec<u8>, usize), Error> {
+  let code = QrCode::new(data)?;
+  let modules = code.width();
+  let colors = code.to_colors();
+  let width = (modules + 2 * QUIET_ZONE) * SCALE;
+  let mut pixels = vec![0xff; width * width];
+  for (i, color) in colors.iter().enumerate() {
+    if *color != Color::Dark {
+      continue;
+    }
+    let (x, y) = (i % modules + QUIET_ZONE, i / modules + QUIET_ZONE);
+    for row in y * SCALE..(y + 1) * SCALE {
+      let start = row * width + x * SCALE;
+      pixels[start..start + SCALE].fill(0);
+    }
+  }
+  Ok((pixels, width))
+}
+
+/// Writes the QR code of the normalized number `ph` to `dir/<ph>.png`.
+pub fn write(dir: &Path, uri: QrUri, ph: &str) -> Result<(), Error> {
+  let path = dir.join(format!("{}.png", ph));
+  let (pixels, width) = render(&uri.of(ph))?;
+  let file = File::create(&path)
+    .with_context(|_| format!("could not create QR code {:?}", path))?;
+  let mut encoder =
+    png::Encoder::new(BufWriter::new(file), width as u32, width as u32);
+  encoder.set_color(png::ColorType::Grayscale);
+  encoder.set_depth(png::BitDepth::Eight);
+  let mut png = encoder.write_header()?;
+  png.write_image_data(&pixels)?;
+  png.finish()?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn should_write_png_codes() {
+    assert_eq!(QrUri::Tel.of("201116613061"), "tel:+201116613061");
+    assert_eq!("wa".parse(), Ok(QrUri::Wa));
+    let dir = std::env::temp_dir();
+    write(&dir, QrUri::Wa, "201116613061").unwrap();
+    let file = File::open(dir.join("201116613061.png")).unwrap();
+    let mut reader = png::Decoder::new(std::io::BufReader::new(file))
+      .read_info()
+      .unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size().unwrap()];
+    let info = reader.next_frame(&mut pixels).unwrap();
+    // a version 2 code of 25 modules, with the quiet zones.
+    assert_eq!(info.width as usize, (25 + 2 * QUIET_ZONE) * SCALE);
+    assert_eq!(pixels, render("https://wa.me/201116613061").unwrap().0);
+  }
+}
@@ -0,0 +1,238 @@
+//! Why a record was rejected.
+
+use std::fmt;
+
+use failure::Error;
+use serde::Serialize;
+
+/// The machine-readable reason of a rejected record.
+#[derive(
+  Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize,
+)]
+pub enum RejectReason {
+  /// Nothing is left of the phone number after cleaning it.
+  EmptyPhone,
+  /// The cleaned phone number still has characters other than digits.
+  NonDigitChars,
+  /// The invalid number has characters looking like digits, like the letter
+  /// O, or digits of different scripts.
+  ConfusableCharacters,
+  TooShort,
+  TooLong,
+  /// The number doesn't start with the dialing code of a known country.
+  UnknownCountry,
+  /// The number doesn't start with any of the known prefixes of its country.
+  BadPrefix,
+  /// The number doesn't match the user provided `--mob-regex`.
+  NoRegexMatch,
+  /// The `libphonenumber` engine says this is not a valid number.
+  InvalidNumber,
+  /// The country of the number is filtered out.
+  CountryNotAllowed,
+  /// Only mobile numbers are accepted, and this is not one of them.
+  NotMobile,
+  /// The number is in the `--blacklist`.
+  Blacklisted,
+  /// There is an `--allowlist`, and the number is not in it.
+  NotAllowlisted,
+  /// The `count` is below `--min-count` or above `--max-count`.
+  CountOutOfRange,
+  /// The `count` is not a number, with `--lenient-count`.
+  InvalidCount,
+}
+
+impl RejectReason {
+  pub fn as_str(self) -> &'static str {
+    match self {
+      RejectReason::EmptyPhone => "EmptyPhone",
+      RejectReason::NonDigitChars => "NonDigitChars",
+      RejectReason::ConfusableCharacters => "ConfusableCharacters",
+      RejectReason::TooShort => "TooShort",
+      RejectReason::TooLong => "TooLong",
+      RejectReason::UnknownCountry => "UnknownCountry",
+      RejectReason::BadPrefix => "BadPrefix",
+      RejectReason::NoRegexMatch => "NoRegexMatch",
+      RejectReason::InvalidNumber => "InvalidNumber",
+      RejectReason::CountryNotAllowed => "CountryNotAllowed",
+      RejectReason::NotMobile => "NotMobile",
+      RejectReason::Blacklisted => "Blacklisted",
+      RejectReason::NotAllowlisted => "NotAllowlisted",
+      RejectReason::CountOutOfRange => "CountOutOfRange",
+      RejectReason::InvalidCount => "InvalidCount",
+    }
+  }
+
+  /// Whether the phone number itself is invalid, and not filtered out.
+  pub fn is_invalid_phone(self) -> bool {
+    matches!(
+      self,
+      RejectReason::EmptyPhone
+        | RejectReason::NonDigitChars
+        | RejectReason::ConfusableCharacters
+        | RejectReason::TooShort
+        | RejectReason::TooLong
+        | RejectReason::UnknownCountry
+        | RejectReason::BadPrefix
+        | RejectReason::NoRegexMatch
+        | RejectReason::InvalidNumber
+    )
+  }
+
+  /// Checks a `len` against the `allowed` lengths.
+  pub fn check_length(len: usize, allowed: &[usize]) -> Result<(), Self> {
+    if allowed.contains(&len) {
+      Ok(())
+    } else if allowed.iter().all(|&a| len > a) {
+      Err(RejectReason::TooLong)
+    } else {
+      Err(RejectReason::TooShort)
+    }
+  }
+}
+
+impl fmt::Display for RejectReason {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+/// Parses a reject rate percentage, like `5%` or `2.5`.
+pub fn parse_rate(s: &str) -> Result<f64, String> {
+  let rate: f64 = s
+    .trim()
+    .trim_end_matches('%')
+    .parse()
+    .map_err(|_| format!("invalid rate `{}`, expected a percentage", s))?;
+  if (0.0..=100.0).contains(&rate) {
+    Ok(rate)
+  } else {
+    Err(format!("invalid rate `{}`, must be between 0% and 100%", s))
+  }
+}
+
+/// The percentage of the `rejected` records out of the `total` ones.
+pub fn rate(rejected: u64, total: u64) -> f64 {
+  if total == 0 {
+    0.0
+  } else {
+    rejected as f64 * 100.0 / total as f64
+  }
+}
+
+/// The failures of a run because of its rejected records, told apart from
+/// the other ones by their exit codes.
+#[derive(Debug)]
+pub enum RejectError {
+  /// Some records are rejected.
+  Rejected(String),
+  /// The rejected records exceed the `--max-rejects` or the
+  /// `--max-reject-rate`.
+  Threshold(String),
+}
+
+impl RejectError {
+  /// The failure of a run with `rejected` records.
+  pub fn rejected(rejected: u64) -> Error {
+    RejectError::Rejected(format!("{} records rejected", rejected)).into()
+  }
+
+  pub fn exit_code(&self) -> i32 {
+    match self {
+      RejectError::Rejected(_) => 2,
+      RejectError::Threshold(_) => 3,
+    }
+  }
+}
+
+impl fmt::Display for RejectError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      RejectError::Rejected(msg) | RejectError::Threshold(msg) => {
+        f.write_str(msg)
+      },
+    }
+  }
+}
+
+impl std::error::Error for RejectError {}
+
+/// The limits of the rejected records before failing the whole run.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Thresholds {
+  pub max_rejects: Option<u64>,
+  /// In percent of the records read.
+  pub max_reject_rate: Option<f64>,
+}
+
+impl Thresholds {
+  /// Checks the rejected records count, can be called while processing.
+  pub fn check_count(&self, rejected: u64) -> Result<(), Error> {
+    match self.max_rejects {
+      Some(max) if rejected > max => Err(
+        RejectError::Threshold(format!(
+          "more than {} records rejected, exceeding --max-rejects",
+          max
+        ))
+        .into(),
+      ),
+      _ => Ok(()),
+    }
+  }
+
+  /// Checks the rate of the rejected records, once all the `total` records
+  /// are processed.
+  pub fn check_rate(&self, rejected: u64, total: u64) -> Result<(), Error> {
+    let rate = rate(rejected, total);
+    match self.max_reject_rate {
+      Some(max) if rate > max => Err(
+        RejectError::Threshold(format!(
+          "{:.2}% of the records rejected, exceeding --max-reject-rate {}%",
+          rate, max
+        ))
+        .into(),
+      ),
+      _ => Ok(()),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn should_check_lengths() {
+    assert_eq!(RejectReason::check_length(10, &[9, 10]), Ok(()));
+    assert_eq!(
+      RejectReason::check_length(8, &[9, 10]),
+      Err(RejectReason::TooShort)
+    );
+    assert_eq!(
+      RejectReason::check_length(11, &[9, 10]),
+      Err(RejectReason::TooLong)
+    );
+  }
+
+  #[test]
+  fn should_parse_rate() {
+    assert_eq!(parse_rate("5%"), Ok(5.0));
+    assert_eq!(parse_rate("2.5"), Ok(2.5));
+    assert!(parse_rate("101%").is_err());
+    assert!(parse_rate("five").is_err());
+  }
+
+  #[test]
+  fn should_check_thresholds() {
+    let thresholds = Thresholds {
+      max_rejects: Some(2),
+      max_reject_rate: Some(5.0),
+    };
+    assert!(thresholds.check_count(2).is_ok());
+    let e = thresholds.check_count(3).unwrap_err();
+    assert_eq!(e.downcast_ref::<RejectError>().unwrap().exit_code(), 3);
+    assert!(thresholds.check_rate(5, 100).is_ok());
+    assert!(thresholds.check_rate(6, 100).is_err());
+    assert!(thresholds.check_rate(0, 0).is_ok());
+    assert!(Thresholds::default().check_count(100).is_ok());
+  }
+}
@@ -0,0 +1,348 @@
+//! The reports of a run, a single HTML page with its charts and samples to
+//! be reviewed in a browser, or a Markdown summary to be pasted in a ticket.
+
+use std::{
+  cmp::Reverse,
+  collections::{BTreeMap, HashMap},
+  fmt::{Display, Write as _},
+  fs::{self, File},
+  io::Read,
+  path::Path,
+};
+
+use failure::{Error, ResultExt};
+use sha2::{Digest, Sha256};
+
+use crate::{reject, stats::Stats, Rejected};
+
+/// The rejected records kept as a sample.
+const SAMPLE_REJECTS: usize = 100;
+/// The duplicate phone numbers listed, the most repeated ones first.
+const TOP_CLUSTERS: usize = 20;
+
+/// The width of the charts, their labels and bars.
+const CHART_WIDTH: usize = 640;
+const LABEL_WIDTH: usize = 180;
+const BAR_WIDTH: usize = 380;
+const ROW_HEIGHT: usize = 24;
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em auto;\
+max-width:960px;color:#222}h2{margin-top:2em;border-bottom:1px solid #ddd}\
+table{border-collapse:collapse}td,th{padding:4px 12px;text-align:left;\
+border-bottom:1px solid #eee}.n{text-align:right}\
+.accepted{color:#2e7d32}.rejected{color:#c62828}";
+
+/// The records of a run kept for its report, besides its statistics.
+#[derive(Debug, Default)]
+pub struct Details {
+  /// The first rejected records.
+  pub rejected: Vec<Rejected>,
+  /// The times an accepted phone number was seen again.
+  pub repeats: HashMap<String, u64>,
+}
+
+impl Details {
+  pub fn reject(&mut self, rejected: &Rejected) {
+    if self.rejected.len() < SAMPLE_REJECTS {
+      self.rejected.push(rejected.clone());
+    }
+  }
+
+  pub fn repeat(&mut self, ph: &str) {
+    *self.repeats.entry(ph.to_owned()).or_insert(0) += 1;
+  }
+
+  pub fn merge(&mut self, other: Details) {
+    let room = SAMPLE_REJECTS - self.rejected.len();
+    self.rejected.extend(other.rejected.into_iter().take(room));
+    for (ph, n) in other.repeats {
+      *self.repeats.entry(ph).or_insert(0) += n;
+    }
+  }
+}
+
+/// Writes the report of the `stats` to the HTML file at `path`.
+pub fn write(path: &Path, stats: &Stats) -> Result<(), Error> {
+  fs::write(path, render(stats))
+    .with_context(|_| format!("could not write report {:?}", path))?;
+  Ok(())
+}
+
+fn render(stats: &Stats) -> String {
+  let mut html = String::new();
+  html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+  html.push_str("<title>mobcsv report</title>\n");
+  let _ = writeln!(html, "<style>{}</style>\n</head>\n<body>", STYLE);
+  html.push_str("<h1>mobcsv report</h1>\n<table>\n");
+  let rows = [
+    ("Read", stats.total, ""),
+    ("Accepted", stats.accepted, "accepted"),
+    ("Rejected", stats.rejected(), "rejected"),
+    ("Duplicates", stats.duplicates, ""),
+  ];
+  for (label, n, class) in &rows {
+    let _ = writeln!(
+      html,
+      "<tr><th>{}</th><td class=\"n {}\">{}</td><td class=\"n\">{:.2}%</td>\
+       </tr>",
+      label,
+      class,
+      n,
+      reject::rate(*n, stats.total)
+    );
+  }
+  html.push_str("</table>\n");
+  section(&mut html, "Countries", &stats.countries, "#1565c0");
+  section(&mut html, "Operators", &stats.operators, "#6a1b9a");
+  section(&mut html, "Reject reasons", &stats.rejected, "#c62828");
+  if let Some(details) = &stats.details {
+    clusters(&mut html, details);
+    rejected(&mut html, details);
+  }
+  html.push_str("</body>\n</html>\n");
+  html
+}
+
+/// Writes the `title` section of a bar chart of the `counts`, the largest
+/// first.
+fn section<K: Display>(
+  html: &mut String,
+  title: &str,
+  counts: &BTreeMap<K, u64>,
+  color: &str,
+) {
+  let _ = writeln!(html, "<h2>{}</h2>", title);
+  if counts.is_empty() {
+    html.push_str("<p>None</p>\n");
+    return;
+  }
+  let mut counts: Vec<_> = counts.iter().collect();
+  counts.sort_by_key(|&(_, &n)| Reverse(n));
+  let max = *counts[0].1;
+  let _ = writeln!(
+    html,
+    "<svg width=\"{}\" height=\"{}\" font-size=\"13\">",
+    CHART_WIDTH,
+    counts.len() * ROW_HEIGHT
+  );
+  for (i, (key, &n)) in counts.into_iter().enumerate() {
+    let y = i * ROW_HEIGHT;
+    let width = (n as f64 / max as f64 * BAR_WIDTH as f64).max(1.0);
+    let _ = writeln!(
+      html,
+      "<text x=\"0\" y=\"{text}\">{key}</text>\
+       <rect x=\"{x}\" y=\"{bar}\" width=\"{width:.0}\" height=\"{height}\" \
+       fill=\"{color}\"/>\
+       <text x=\"{value:.0}\" y=\"{text}\">{n}</text>",
+      text = y + 16,
+      key = escape(&key.to_string()),
+      x = LABEL_WIDTH,
+      bar = y + 4,
+      width = width,
+      height = ROW_HEIGHT - 8,
+      color = color,
+      value = LABEL_WIDTH as f64 + width + 6.0,
+      n = n
+    );
+  }
+  html.push_str("</svg>\n");
+}
+
+/// Writes the phone numbers found the most times.
+fn clusters(html: &mut String, details: &Details) {
+  html.push_str("<h2>Duplicate clusters</h2>\n");
+  if details.repeats.is_empty() {
+    html.push_str("<p>None</p>\n");
+    return;
+  }
+  let mut repeats: Vec<_> = details.repeats.iter().collect();
+  repeats.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+  html.push_str("<table>\n<tr><th>Phone</th><th>Records</th></tr>\n");
+  for (ph, n) in repeats.into_iter().take(TOP_CLUSTERS) {
+    let _ = writeln!(
+      html,
+      "<tr><td>{}</td><td class=\"n\">{}</td></tr>",
+      escape(ph),
+      n + 1
+    );
+  }
+  html.push_str("</table>\n");
+}
+
+/// Writes the sample of the rejected records.
+fn rejected(html: &mut String, details: &Details) {
+  let _ =
+    writeln!(html, "<h2>Rejected records (first {})</h2>", SAMPLE_REJECTS);
+  if details.rejected.is_empty() {
+    html.push_str("<p>None</p>\n");
+    return;
+  }
+  html.push_str(
+    "<table>\n<tr><th>Phone</th><th>Name</th><th>Count</th><th>Reason</th>\
+     </tr>\n",
+  );
+  for rejected in &details.rejected {
+    let record = &rejected.record;
+    let _ = writeln!(
+      html,
+      "<tr><td>{}</td><td>{}</td><td class=\"n\">{}</td>\
+       <td class=\"rejected\">{}</td></tr>",
+      escape(&record.ph),
+      escape(&record.name),
+      record.count,
+      rejected.reason
+    );
+  }
+  html.push_str("</table>\n");
+}
+
+/// Writes the summary of the `stats` to the Markdown file at `path`, with
+/// the checksums of the `files` found locally.
+pub fn write_markdown(
+  path: &Path,
+  stats: &Stats,
+  files: &[&Path],
+) -> Result<(), Error> {
+  let mut checksums = Vec::new();
+  for file in files.iter().filter(|file| file.is_file()) {
+    checksums.push(Checksum::of(file)?);
+  }
+  fs::write(path, markdown(stats, &checksums))
+    .with_context(|_| format!("could not write report {:?}", path))?;
+  Ok(())
+}
+
+/// The SHA-256 checksum of a file.
+struct Checksum {
+  name: String,
+  len: u64,
+  sha256: String,
+}
+
+impl Checksum {
+  fn of(path: &Path) -> Result<Self, Error> {
+    let mut file = File::open(path)
+      .with_context(|_| format!("could not open {:?}", path))?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 64 * 1024];
+    let mut len = 0;
+    loop {
+      let n = file
+        .read(&mut buf)
+        .with_context(|_| format!("could not read {:?}", path))?;
+      if n == 0 {
+        break;
+      }
+      hasher.update(&buf[..n]);
+      len += n as u64;
+    }
+    let sha256 = hasher.finalize().iter().fold(String::new(), |mut s, b| {
+      let _ = write!(s, "{:02x}", b);
+      s
+    });
+    Ok(Checksum {
+      name: path.display().to_string(),
+      len,
+      sha256,
+    })
+  }
+}
+
+fn markdown(stats: &Stats, checksums: &[Checksum]) -> String {
+  let mut md = String::from("# mobcsv report\n\n");
+  md.push_str("| Records | Count | % |\n|---|---:|---:|\n");
+  let rows = [
+    ("Read", stats.total),
+    ("Accepted", stats.accepted),
+    ("Rejected", stats.rejected()),
+    ("Duplicates", stats.duplicates),
+  ];
+  for (label, n) in &rows {
+    let rate = reject::rate(*n, stats.total);
+    let _ = writeln!(md, "| {} | {} | {:.2}% |", label, n, rate);
+  }
+  if !stats.rejected.is_empty() {
+    md.push_str("\n## Rejects\n\n| Reason | Count | % |\n|---|---:|---:|\n");
+    let mut reasons: Vec<_> = stats.rejected.iter().collect();
+    reasons.sort_by_key(|&(_, &n)| Reverse(n));
+    for (reason, &n) in reasons {
+      let rate = reject::rate(n, stats.total);
+      let _ = writeln!(md, "| {} | {} | {:.2}% |", reason, n, rate);
+    }
+  }
+  if !checksums.is_empty() {
+    md.push_str("\n## Files\n\n| File | Bytes | SHA-256 |\n|---|---:|---|\n");
+    for checksum in checksums {
+      let _ = writeln!(
+        md,
+        "| `{}` | {} | `{}` |",
+        checksum.name.replace('|', "\\|"),
+        checksum.len,
+        checksum.sha256
+      );
+    }
+  }
+  md
+}
+
+fn escape(s: &str) -> String {
+  let mut escaped = String::with_capacity(s.len());
+  for c in s.chars() {
+    match c {
+      '&' => escaped.push_str("&amp;"),
+      '<' => escaped.push_str("&lt;"),
+      '>' => escaped.push_str("&gt;"),
+      '"' => escaped.push_str("&quot;"),
+      '\'' => escaped.push_str("&#39;"),
+      c => escaped.push(c),
+    }
+  }
+  escaped
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{reject::RejectReason, Accepted, Record};
+
+  #[test]
+  fn should_render_the_report() {
+    let mut stats = Stats::new(true, false);
+    for ph in &["201116613061", "201116613061", "966540029129"] {
+      stats.accept(&Accepted {
+        record: Record::new(ph, "", 1),
+        original_ph: ph.to_string(),
+        fixed: false,
+        extracted: false,
+      });
+    }
+    stats.reject_record(&Rejected {
+      record: Record::new("<12>", "a & b", 1),
+      attempt: "12".into(),
+      reason: RejectReason::NonDigitChars,
+      line: 2,
+    });
+    let html = render(&stats);
+    assert!(html.contains("<h2>Countries</h2>\n<svg"));
+    assert!(html.contains("<tr><td>201116613061</td><td class=\"n\">2</td>"));
+    assert!(html.contains("<td>&lt;12&gt;</td><td>a &amp; b</td>"));
+    assert!(html.ends_with("</html>\n"));
+  }
+
+  #[test]
+  fn should_write_the_markdown_summary() {
+    let path = std::env::temp_dir().join("mobcsv-checksum.txt");
+    fs::write(&path, "abc").unwrap();
+    let checksum = Checksum::of(&path).unwrap();
+    assert_eq!(
+      checksum.sha256,
+      "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    let mut stats = Stats::default();
+    stats.reject(RejectReason::TooShort);
+    let md = markdown(&stats, &[checksum]);
+    assert!(md.contains("| Rejected | 1 | 100.00% |\n"));
+    assert!(md.contains("| TooShort | 1 | 100.00% |\n"));
+    assert!(md.contains("| 3 | `ba7816bf"));
+  }
+}
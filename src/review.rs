@@ -0,0 +1,355 @@
+//! The review of the borderline rejected records, the ones a single edit
+//! away from a valid phone number, asking the user to accept one of the
+//! corrections, to edit it, or to skip it, with `--interactive`.
+//!
+//! The decisions are recorded to a `--patch-file`, a `ph,fix` CSV file by
+//! rejected phone number, the `fix` being empty when it is skipped, replayed
+//! by the next runs. With `--apply-suggestions`, the records without a
+//! decision are accepted with their first correction instead.
+
+use std::{
+  collections::HashMap,
+  fs::{File, OpenOptions},
+  io::{self, BufRead, Write},
+  path::Path,
+};
+
+use failure::{Error, ResultExt};
+
+use crate::{is_good_ph, Accepted, Config, Record, Rejected};
+
+/// The corrections suggested at most.
+const MAX_SUGGESTIONS: usize = 5;
+
+/// The columns of the patch files.
+const HEADERS: &[&str] = &["ph", "fix"];
+
+/// Where the questions are asked, the terminal unless tested.
+struct Prompt {
+  input: Box<dyn BufRead>,
+  output: Box<dyn Write>,
+}
+
+/// The decisions of the rejected records, replayed from the patch file, or
+/// asked to the user.
+pub struct Review {
+  /// The fix of every rejected phone number decided so far, none when it is
+  /// skipped.
+  decisions: HashMap<String, Option<String>>,
+  prompt: Option<Prompt>,
+  patch: Option<csv::Writer<File>>,
+  /// Whether the records without a decision are accepted with the first
+  /// suggestion, when they aren't asked about.
+  apply: bool,
+}
+
+impl Review {
+  /// Replays the decisions of the patch file at `path`, if any, recording the
+  /// new ones to it, asking for them on the terminal when `interactive`.
+  pub fn open(interactive: bool, path: Option<&Path>) -> Result<Self, Error> {
+    let mut decisions = HashMap::new();
+    let patch = match path {
+      Some(path) => {
+        let exists = path.is_file();
+        if exists {
+          let mut rdr = csv::Reader::from_path(path).with_context(|_| {
+            format!("could not open patch file {:?}", path)
+          })?;
+          for row in rdr.records() {
+            let row =
+              row.with_context(|_| format!("invalid patch file {:?}", path))?;
+            let fix = row.get(1).unwrap_or("").trim();
+            let fix = Some(fix.to_owned()).filter(|fix| !fix.is_empty());
+            decisions.insert(row.get(0).unwrap_or("").to_owned(), fix);
+          }
+        }
+        let file = OpenOptions::new()
+          .create(true)
+          .append(true)
+          .open(path)
+          .with_context(|_| format!("could not open patch file {:?}", path))?;
+        let mut wrt = csv::Writer::from_writer(file);
+        if !exists {
+          wrt.write_record(HEADERS)?;
+          wrt.flush()?;
+        }
+        Some(wrt)
+      },
+      None => None,
+    };
+    let prompt = if interactive {
+      Some(Prompt {
+        input: Box::new(io::BufReader::new(io::stdin())),
+        output: Box::new(io::stderr()),
+      })
+    } else {
+      None
+    };
+    Ok(Review {
+      decisions,
+      prompt,
+      patch,
+      apply: false,
+    })
+  }
+
+  /// Accepts the records without a decision with their first suggestion
+  /// when `apply`, without recording it.
+  pub fn apply_suggestions(self, apply: bool) -> Self {
+    Review { apply, ..self }
+  }
+
+  /// Accepts the `rejected` record at the `line` with its decided fix, or
+  /// with the one of the user if it is borderline.
+  pub fn review(
+    &mut self,
+    config: &Config,
+    rejected: Rejected,
+    line: u64,
+  ) -> Result<Result<Accepted, Rejected>, Error> {
+    if !rejected.reason.is_invalid_phone() {
+      return Ok(Err(rejected));
+    }
+    let fix = match self.decisions.get(&rejected.record.ph) {
+      Some(fix) => fix.clone(),
+      None => {
+        let suggestions = suggest(config, &rejected, MAX_SUGGESTIONS);
+        let prompt = match (&mut self.prompt, suggestions.first()) {
+          (Some(prompt), Some(_)) => prompt,
+          (None, Some(ph)) if self.apply => {
+            return Ok(accept(config, rejected, ph.clone()))
+          },
+          _ => return Ok(Err(rejected)),
+        };
+        let fix = match prompt.ask(config, &rejected, line, &suggestions)? {
+          Some(fix) => fix,
+          // the input is over, the next records aren't asked about.
+          None => {
+            self.prompt = None;
+            return Ok(Err(rejected));
+          },
+        };
+        self.decide(&rejected.record.ph, fix.as_deref())?;
+        fix
+      },
+    };
+    Ok(match fix {
+      Some(ph) => accept(config, rejected, ph),
+      None => Err(rejected),
+    })
+  }
+
+  /// Records the `fix` of the rejected `ph`, none to skip it.
+  fn decide(&mut self, ph: &str, fix: Option<&str>) -> Result<(), Error> {
+    self.decisions.insert(ph.to_owned(), fix.map(str::to_owned));
+    if let Some(wrt) = &mut self.patch {
+      wrt.write_record([ph, fix.unwrap_or("")])?;
+      wrt.flush()?;
+    }
+    Ok(())
+  }
+}
+
+impl Prompt {
+  /// Asks which of the `suggestions` fixes the `rejected` record, if any,
+  /// returning none when the input is over.
+  fn ask(
+    &mut self,
+    config: &Config,
+    rejected: &Rejected,
+    line: u64,
+    suggestions: &[String],
+  ) -> Result<Option<Option<String>>, Error> {
+    let record = &rejected.record;
+    loop {
+      writeln!(
+        self.output,
+        "Line {}: `{}` ({}), name `{}`, count {}",
+        line, record.ph, rejected.reason, record.name, record.count
+      )?;
+      for (i, suggestion) in suggestions.iter().enumerate() {
+        writeln!(self.output, "  {}) {}", i + 1, suggestion)?;
+      }
+      write!(
+        self.output,
+        "Accept [1-{}], edit [e], or skip [s]? ",
+        suggestions.len()
+      )?;
+      let answer = match self.read_line()? {
+        Some(answer) => answer,
+        None => return Ok(None),
+      };
+      match answer.as_str() {
+        "s" | "skip" => return Ok(Some(None)),
+        "e" | "edit" => {
+          write!(self.output, "Phone number: ")?;
+          let ph = match self.read_line()? {
+            Some(ph) => ph,
+            None => return Ok(None),
+          };
+          let record = Record {
+            ph: ph.clone(),
+            ..record.clone()
+          };
+          match is_good_ph(config, record) {
+            Ok(_) => return Ok(Some(Some(ph))),
+            Err(e) => writeln!(self.output, "Not Acceptable ({})", e.reason)?,
+          }
+        },
+        answer => match answer.parse::<usize>() {
+          Ok(n) if (1..=suggestions.len()).contains(&n) => {
+            return Ok(Some(Some(suggestions[n - 1].clone())))
+          },
+          _ => writeln!(self.output, "Unknown answer `{}`", answer)?,
+        },
+      }
+    }
+  }
+
+  fn read_line(&mut self) -> Result<Option<String>, Error> {
+    self.output.flush()?;
+    let mut line = String::new();
+    if self.input.read_line(&mut line)? == 0 {
+      return Ok(None);
+    }
+    Ok(Some(line.trim().to_owned()))
+  }
+}
+
+/// Accepts the `rejected` record with the fixed phone number `ph`, unless it
+/// is invalid too.
+pub fn accept(
+  config: &Config,
+  rejected: Rejected,
+  ph: String,
+) -> Result<Accepted, Rejected> {
+  let record = Record {
+    ph,
+    ..rejected.record.clone()
+  };
+  match is_good_ph(config, record) {
+    Ok(accepted) => Ok(Accepted {
+      original_ph: rejected.record.ph,
+      fixed: true,
+      ..accepted
+    }),
+    Err(_) => Err(rejected),
+  }
+}
+
+/// The `limit` first valid phone numbers a single edit away from the
+/// `rejected` one, its typos fixed like a doubled dialing code, a missing
+/// leading zero, or a dropped, doubled or swapped digit.
+pub fn suggest(
+  config: &Config,
+  rejected: &Rejected,
+  limit: usize,
+) -> Vec<String> {
+  let digits: Vec<char> = rejected.attempt.chars().collect();
+  let mut candidates = vec![
+    config.cleaner.fix_typos(&rejected.record.ph),
+    format!("0{}", rejected.attempt),
+  ];
+  // the likeliest edits first, a stray digit, then a missed or swapped one.
+  for i in 0..digits.len() {
+    let mut edited = digits.clone();
+    edited.remove(i);
+    candidates.push(edited.iter().collect());
+  }
+  for i in 0..digits.len() {
+    let mut edited = digits.clone();
+    edited.insert(i, digits[i]);
+    candidates.push(edited.iter().collect());
+  }
+  for i in 1..digits.len() {
+    if digits[i - 1] != digits[i] {
+      let mut edited = digits.clone();
+      edited.swap(i - 1, i);
+      candidates.push(edited.iter().collect());
+    }
+  }
+  let mut suggestions: Vec<String> = Vec::new();
+  for ph in candidates {
+    let record = Record {
+      ph,
+      ..rejected.record.clone()
+    };
+    if let Ok(accepted) = is_good_ph(config, record) {
+      if !suggestions.contains(&accepted.record.ph) {
+        suggestions.push(accepted.record.ph);
+      }
+    }
+    if suggestions.len() == limit {
+      break;
+    }
+  }
+  suggestions
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn should_review_borderline_rejects() {
+    let config = Config::default();
+    let rejected = is_good_ph(&config, Record::new("0111661306", "a", 1));
+    let rejected = rejected.unwrap_err();
+    let suggestions = suggest(&config, &rejected, MAX_SUGGESTIONS);
+    assert_eq!(suggestions.len(), MAX_SUGGESTIONS);
+    assert!(suggestions.contains(&"201116661306".to_owned()));
+    let path = std::env::temp_dir().join("mobcsv-patch.csv");
+    let _ = std::fs::remove_file(&path);
+    let mut review = Review::open(false, Some(&path)).unwrap();
+    review.prompt = Some(Prompt {
+      input: Box::new(&b"x\ne\n12\n2\n"[..]),
+      output: Box::new(io::sink()),
+    });
+    let accepted = review.review(&config, rejected.clone(), 2).unwrap();
+    let accepted = accepted.unwrap();
+    assert_eq!(accepted.record.ph, suggestions[1]);
+    assert_eq!(accepted.original_ph, "0111661306");
+    assert!(review.review(&config, rejected.clone(), 3).unwrap().is_ok());
+    drop(review);
+    let mut review = Review::open(false, Some(&path)).unwrap();
+    let replayed = review.review(&config, rejected.clone(), 4).unwrap();
+    assert_eq!(replayed.unwrap().record.ph, suggestions[1]);
+    let mut review = Review::open(false, None).unwrap().apply_suggestions(true);
+    let applied = review.review(&config, rejected, 5).unwrap().unwrap();
+    assert_eq!(applied.record.ph, suggestions[0]);
+    assert_eq!(
+      std::fs::read_to_string(&path).unwrap(),
+      format!("ph,fix\n0111661306,{}\n", suggestions[1])
+    );
+  }
+
+  #[test]
+  fn should_replay_patches_and_apply_suggestions() {
+    let config = Config::default();
+    let reject = |ph| is_good_ph(&config, Record::new(ph, "a", 1)).unwrap_err();
+    let (fixed, skipped, other) = (
+      reject("0111661306"),
+      reject("0101661306"),
+      reject("0121661306"),
+    );
+    let path = std::env::temp_dir().join("mobcsv-replayed-patch.csv");
+    let patch = "ph,fix\n0111661306,01116613061\n0101661306,\n";
+    std::fs::write(&path, patch).unwrap();
+    let mut review = Review::open(false, Some(&path))
+      .unwrap()
+      .apply_suggestions(true);
+    let accepted = review.review(&config, fixed, 2).unwrap().unwrap();
+    assert_eq!(accepted.record.ph, "201116613061");
+    assert!(accepted.fixed);
+    // skipped, even if it has suggestions.
+    assert!(!suggest(&config, &skipped, 1).is_empty());
+    assert!(review.review(&config, skipped, 3).unwrap().is_err());
+    let first = suggest(&config, &other, 1).pop().unwrap();
+    let accepted = review.review(&config, other, 4).unwrap().unwrap();
+    assert_eq!(accepted.record.ph, first);
+    assert_eq!(accepted.original_ph, "0121661306");
+    // the applied suggestions aren't decisions.
+    drop(review);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), patch);
+  }
+}
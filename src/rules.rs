@@ -0,0 +1,221 @@
+//! The data driven validation rules, embedded in the binary by default and
+//! overridable by the user with `--rules`, see `rules.toml` for the format.
+
+use std::{fs, path::Path, sync::OnceLock};
+
+use failure::{Error, ResultExt};
+use serde::Deserialize;
+
+use crate::{countries::Country, reject::RejectReason};
+
+const DEFAULT_RULES: &str = include_str!("rules.toml");
+
+static RULES: OnceLock<Rules> = OnceLock::new();
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Rules {
+  /// Replaces the country and prefix rules when validating the numbers.
+  pub mob_regex: Option<String>,
+  /// Replaces the pattern of the characters removed from the numbers.
+  pub replacer_regex: Option<String>,
+  #[serde(rename = "country", default)]
+  pub countries: Vec<Country>,
+  #[serde(rename = "prefix", default)]
+  pub prefixes: Vec<PrefixRule>,
+}
+
+/// The allowed lengths of the numbers starting with a prefix.
+#[derive(Debug, Deserialize)]
+pub struct PrefixRule {
+  /// Leading digits of a normalized number, dialing code included.
+  pub prefix: String,
+  /// The allowed total lengths, dialing code included.
+  pub lengths: Vec<usize>,
+  #[serde(default)]
+  pub line_type: LineType,
+}
+
+/// The kind of line a number belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineType {
+  Mobile,
+  Landline,
+  #[default]
+  Unknown,
+}
+
+impl LineType {
+  pub fn as_str(self) -> &'static str {
+    match self {
+      LineType::Mobile => "mobile",
+      LineType::Landline => "landline",
+      LineType::Unknown => "unknown",
+    }
+  }
+}
+
+impl Rules {
+  pub fn from_toml(s: &str) -> Result<Self, Error> {
+    Ok(toml::from_str(s)?)
+  }
+
+  pub fn from_json(s: &str) -> Result<Self, Error> {
+    Ok(serde_json::from_str(s)?)
+  }
+
+  /// Takes the sections missing from these rules from the `defaults`.
+  fn or(mut self, defaults: Rules) -> Self {
+    if self.countries.is_empty() {
+      self.countries = defaults.countries;
+    }
+    if self.prefixes.is_empty() {
+      self.prefixes = defaults.prefixes;
+    }
+    self.mob_regex = self.mob_regex.or(defaults.mob_regex);
+    self.replacer_regex = self.replacer_regex.or(defaults.replacer_regex);
+    self
+  }
+
+  /// The longest prefix rule matching the normalized number `ph`.
+  fn prefix_of(&self, ph: &str) -> Option<&PrefixRule> {
+    self
+      .prefixes
+      .iter()
+      .filter(|r| ph.starts_with(&r.prefix))
+      .max_by_key(|r| r.prefix.len())
+  }
+
+  /// Classifies the normalized number `ph` by its matching prefix rule.
+  pub fn line_type(&self, ph: &str) -> LineType {
+    self
+      .prefix_of(ph)
+      .map_or(LineType::Unknown, |r| r.line_type)
+  }
+
+  /// Checks the normalized number `ph` of `country` against the prefix
+  /// rules.
+  ///
+  /// Countries without any prefix rule are always allowed.
+  pub fn check(&self, ph: &str, country: &Country) -> Result<(), RejectReason> {
+    match self.prefix_of(ph) {
+      Some(rule) => RejectReason::check_length(ph.len(), &rule.lengths),
+      None => {
+        let has_rules = self
+          .prefixes
+          .iter()
+          .any(|r| r.prefix.starts_with(&country.dialing_code));
+        if has_rules {
+          Err(RejectReason::BadPrefix)
+        } else {
+          Ok(())
+        }
+      },
+    }
+  }
+}
+
+/// Loads the rules file at `path` in place of the embedded rules, must be
+/// called before the first use of the rules.
+pub fn load(path: &Path) -> Result<(), Error> {
+  RULES
+    .set(read(path)?)
+    .map_err(|_| failure::err_msg("the rules are already loaded"))
+}
+
+/// Reads the rules file at `path`, the sections it doesn't have being the
+/// embedded ones.
+///
+/// Files with the `.json` extension are read as JSON, and anything else as
+/// TOML.
+pub fn read(path: &Path) -> Result<Rules, Error> {
+  let content = fs::read_to_string(path)
+    .with_context(|_| format!("could not read rules file {:?}", path))?;
+  let is_json = path.extension().is_some_and(|ext| ext == "json");
+  let rules = if is_json {
+    Rules::from_json(&content)
+  } else {
+    Rules::from_toml(&content)
+  };
+  let rules = rules
+    .with_context(|_| format!("invalid rules file {:?}", path))?
+    .or(embedded());
+  Ok(rules)
+}
+
+/// The rules in use, the embedded ones unless [`load`] was called.
+pub fn get() -> &'static Rules {
+  RULES.get_or_init(embedded)
+}
+
+fn embedded() -> Rules {
+  Rules::from_toml(DEFAULT_RULES).expect("the embedded rules are valid")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::countries;
+
+  #[test]
+  fn should_parse_embedded_rules() {
+    let rules = embedded();
+    assert!(!rules.countries.is_empty());
+    assert!(!rules.prefixes.is_empty());
+  }
+
+  #[test]
+  fn should_parse_json_rules() {
+    let rules = Rules::from_json(
+      r#"{
+        "country": [{
+          "iso": "US",
+          "name": "United States",
+          "dialing_code": "1",
+          "lengths": [10]
+        }]
+      }"#,
+    )
+    .unwrap()
+    .or(embedded());
+    assert_eq!(rules.countries.len(), 1);
+    assert_eq!(rules.countries[0].trunk_prefix, None);
+    assert!(!rules.prefixes.is_empty());
+  }
+
+  #[test]
+  fn should_check_prefix_lengths() {
+    let rules = Rules::from_toml(
+      r#"
+      [[prefix]]
+      prefix = "2010"
+      lengths = [12]
+
+      [[prefix]]
+      prefix = "20100"
+      lengths = [13]
+      "#,
+    )
+    .unwrap();
+    let eg = countries::by_iso(countries::all(), "EG").unwrap();
+    let sa = countries::by_iso(countries::all(), "SA").unwrap();
+    assert_eq!(rules.check("201016613061", eg), Ok(()));
+    assert_eq!(rules.check("2010166130611", eg), Err(RejectReason::TooLong));
+    assert_eq!(rules.check("2010016613061", eg), Ok(()));
+    assert_eq!(
+      rules.check("201916613061", eg),
+      Err(RejectReason::BadPrefix)
+    );
+    assert_eq!(rules.check("966540029129", sa), Ok(()));
+  }
+
+  #[test]
+  fn should_classify_line_types() {
+    let rules = embedded();
+    assert_eq!(rules.line_type("201116613061"), LineType::Mobile);
+    assert_eq!(rules.line_type("966540029129"), LineType::Mobile);
+    assert_eq!(rules.line_type("20223456789"), LineType::Landline);
+    assert_eq!(rules.line_type("96612345678"), LineType::Landline);
+    assert_eq!(rules.line_type("14155552671"), LineType::Unknown);
+  }
+}
@@ -0,0 +1,198 @@
+//! Reading the inputs from, and writing the outputs to, S3 object storage,
+//! with the credentials of the standard `AWS_*` environment variables, or
+//! the instance ones.
+
+use std::{
+  fs::File,
+  io::{self, Read},
+  path::{Path, PathBuf},
+  sync::{Arc, OnceLock},
+};
+
+use bytes::Bytes;
+use failure::{bail, Error, ResultExt};
+use futures::{stream::BoxStream, StreamExt};
+use log::info;
+use object_store::{
+  aws::AmazonS3Builder, path::Path as Key, ObjectStore, ObjectStoreExt,
+  WriteMultipart,
+};
+use serde_json::{Map, Value};
+use tokio::runtime::Runtime;
+
+use crate::sink::Sink;
+
+/// Whether the `path` is an `s3://bucket/key` URL.
+pub fn is_s3(path: &Path) -> bool {
+  path.to_string_lossy().starts_with("s3://")
+}
+
+/// The bucket and the key of the `s3://bucket/key` URL.
+fn parse(url: &Path) -> Result<(String, Key), Error> {
+  let url = url.to_string_lossy();
+  let rest = url.trim_start_matches("s3://");
+  match rest.split_once('/') {
+    Some((bucket, key)) if !bucket.is_empty() && !key.is_empty() => {
+      Ok((bucket.to_owned(), Key::from(key)))
+    },
+    _ => bail!("expected an `s3://bucket/key` URL, got `{}`", url),
+  }
+}
+
+fn store(bucket: &str) -> Result<Arc<dyn ObjectStore>, Error> {
+  let store = AmazonS3Builder::from_env()
+    .with_bucket_name(bucket)
+    .build()
+    .with_context(|_| {
+      format!("could not connect to the `{}` bucket", bucket)
+    })?;
+  Ok(Arc::new(store))
+}
+
+/// The runtime of the object storage requests, the rest is synchronous.
+fn runtime() -> io::Result<&'static Runtime> {
+  static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+  if let Some(runtime) = RUNTIME.get() {
+    return Ok(runtime);
+  }
+  let runtime = tokio::runtime::Builder::new_current_thread()
+    .enable_all()
+    .build()?;
+  Ok(RUNTIME.get_or_init(|| runtime))
+}
+
+/// Starts downloading the object at `url`, returning it with its size.
+pub fn open(url: &Path) -> Result<(Box<dyn Read>, Option<u64>), Error> {
+  let (bucket, key) = parse(url)?;
+  info!("Downloading {}", url.display());
+  let store = store(&bucket)?;
+  let result = runtime()?
+    .block_on(store.get(&key))
+    .with_context(|_| format!("could not download {}", url.display()))?;
+  let size = result.meta.size;
+  let reader = ObjectReader {
+    stream: result.into_stream(),
+    chunk: Bytes::new(),
+  };
+  Ok((Box::new(reader), Some(size)))
+}
+
+/// The body of an object, read chunk by chunk.
+struct ObjectReader {
+  stream: BoxStream<'static, object_store::Result<Bytes>>,
+  /// The rest of the last downloaded chunk.
+  chunk: Bytes,
+}
+
+impl Read for ObjectReader {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    while self.chunk.is_empty() {
+      match runtime()?.block_on(self.stream.next()) {
+        Some(chunk) => self.chunk = chunk.map_err(io::Error::other)?,
+        None => return Ok(0),
+      }
+    }
+    let n = buf.len().min(self.chunk.len());
+    buf[..n].copy_from_slice(&self.chunk.split_to(n));
+    Ok(n)
+  }
+}
+
+/// A sink writing to a local temporary file, uploaded to `url` when the
+/// sink finishes.
+pub struct UploadSink {
+  inner: Option<Box<dyn Sink>>,
+  local: PathBuf,
+  url: PathBuf,
+}
+
+impl UploadSink {
+  /// The local temporary path of the output `url`, with the same file name.
+  pub fn local_path(url: &Path) -> PathBuf {
+    let name = url.file_name().unwrap_or_default().to_string_lossy();
+    std::env::temp_dir().join(format!("mobcsv-{}-{}", std::process::id(), name))
+  }
+
+  pub fn new(inner: Box<dyn Sink>, local: PathBuf, url: &Path) -> Self {
+    UploadSink {
+      inner: Some(inner),
+      local,
+      url: url.to_owned(),
+    }
+  }
+
+  fn upload(&self) -> Result<(), Error> {
+    let (bucket, key) = parse(&self.url)?;
+    info!("Uploading {}", self.url.display());
+    let store = store(&bucket)?;
+    let mut file = File::open(&self.local)?;
+    runtime()?.block_on(async {
+      let mut upload = WriteMultipart::new(store.put_multipart(&key).await?);
+      let mut buf = vec![0; 8 * 1024 * 1024];
+      loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+          break;
+        }
+        upload.write(&buf[..n]);
+      }
+      upload.finish().await?;
+      Ok::<_, Error>(())
+    })
+  }
+}
+
+impl Sink for UploadSink {
+  fn write_field(&mut self, field: &str) -> Result<(), Error> {
+    match &mut self.inner {
+      Some(inner) => inner.write_field(field),
+      None => bail!("the upload of {} is finished", self.url.display()),
+    }
+  }
+
+  fn end_record(&mut self) -> Result<(), Error> {
+    match &mut self.inner {
+      Some(inner) => inner.end_record(),
+      None => bail!("the upload of {} is finished", self.url.display()),
+    }
+  }
+
+  fn write_object(&mut self, object: &Map<String, Value>) -> Result<(), Error> {
+    match &mut self.inner {
+      Some(inner) => inner.write_object(object),
+      None => bail!("the upload of {} is finished", self.url.display()),
+    }
+  }
+
+  fn finish(&mut self) -> Result<(), Error> {
+    if let Some(mut inner) = self.inner.take() {
+      inner.finish()?;
+      // closes the local file, finishing any compressed stream.
+      drop(inner);
+      let uploaded = self
+        .upload()
+        .with_context(|_| format!("could not upload {}", self.url.display()));
+      let _ = std::fs::remove_file(&self.local);
+      uploaded?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn should_parse_urls() {
+    let url = Path::new("s3://exports/nightly/contacts.csv.gz");
+    assert!(is_s3(url));
+    let (bucket, key) = parse(url).unwrap();
+    assert_eq!(bucket, "exports");
+    assert_eq!(key.as_ref(), "nightly/contacts.csv.gz");
+    assert!(parse(Path::new("s3://exports")).is_err());
+    assert!(!is_s3(Path::new("exports/contacts.csv")));
+    let local = UploadSink::local_path(url);
+    assert!(local.to_string_lossy().ends_with("-contacts.csv.gz"));
+  }
+}
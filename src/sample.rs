@@ -0,0 +1,135 @@
+//! Random samples of the accepted records.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::Accepted;
+
+/// A SplitMix64 generator, good enough for sampling and the same for the
+/// same seed everywhere.
+struct Rng(u64);
+
+impl Rng {
+  fn next_u64(&mut self) -> u64 {
+    self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = self.0;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+  }
+
+  /// A number in `[0, 1)`.
+  fn next_f64(&mut self) -> f64 {
+    (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+  }
+}
+
+/// How the records are sampled.
+enum Mode {
+  /// Exactly this many records at most, held until the end.
+  Size(usize),
+  /// Every record with this probability, passed through right away.
+  Rate(f64),
+}
+
+/// A uniform random sample of the records, in their input order.
+pub struct Sample {
+  rng: Rng,
+  mode: Mode,
+  /// The records of the `Size` sample, with their index.
+  reservoir: Vec<(u64, Accepted)>,
+  seen: u64,
+}
+
+impl Sample {
+  /// A sample of `size` records, by reservoir sampling.
+  pub fn size(size: usize, seed: Option<u64>) -> Self {
+    Sample::new(Mode::Size(size), seed)
+  }
+
+  /// A sample of about the `rate` of the records, between 0 and 1.
+  pub fn rate(rate: f64, seed: Option<u64>) -> Self {
+    Sample::new(Mode::Rate(rate), seed)
+  }
+
+  /// Seeded with the current time when there is no `seed`.
+  fn new(mode: Mode, seed: Option<u64>) -> Self {
+    let seed = seed.unwrap_or_else(|| {
+      let now = SystemTime::now().duration_since(UNIX_EPOCH);
+      now.map_or(0, |now| now.as_nanos() as u64)
+    });
+    Sample {
+      rng: Rng(seed),
+      mode,
+      reservoir: Vec::new(),
+      seen: 0,
+    }
+  }
+
+  /// Returns the record if it is sampled right away.
+  pub fn push(&mut self, accepted: Accepted) -> Option<Accepted> {
+    let index = self.seen;
+    self.seen += 1;
+    match self.mode {
+      Mode::Rate(rate) => Some(accepted).filter(|_| self.rng.next_f64() < rate),
+      Mode::Size(size) if self.reservoir.len() < size => {
+        self.reservoir.push((index, accepted));
+        None
+      },
+      Mode::Size(size) => {
+        let i = (self.rng.next_u64() % self.seen) as usize;
+        if i < size {
+          self.reservoir[i] = (index, accepted);
+        }
+        None
+      },
+    }
+  }
+
+  /// The records held until the end, in the input order.
+  pub fn finish(mut self) -> Vec<Accepted> {
+    self.reservoir.sort_by_key(|(index, _)| *index);
+    self.reservoir.into_iter().map(|(_, a)| a).collect()
+  }
+}
+
+/// Parses a sample rate, between 0 and 1.
+pub fn parse_rate(s: &str) -> Result<f64, String> {
+  match s.trim().parse() {
+    Ok(rate) if (0.0..=1.0).contains(&rate) => Ok(rate),
+    _ => Err(format!("invalid sample rate `{}`, expected 0 to 1", s)),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::Record;
+
+  fn sample(mut sample: Sample, n: u64) -> Vec<u64> {
+    let mut counts = Vec::new();
+    for count in 0..n {
+      let accepted = Accepted {
+        record: Record::new("201116613061", "", count),
+        original_ph: String::new(),
+        fixed: false,
+        extracted: false,
+      };
+      counts.extend(sample.push(accepted).map(|a| a.record.count));
+    }
+    counts.extend(sample.finish().into_iter().map(|a| a.record.count));
+    counts
+  }
+
+  #[test]
+  fn should_sample_records() {
+    let counts = sample(Sample::size(10, Some(7)), 1000);
+    assert_eq!(counts.len(), 10);
+    assert!(counts.windows(2).all(|w| w[0] < w[1]));
+    assert_eq!(counts, sample(Sample::size(10, Some(7)), 1000));
+    let counts = sample(Sample::rate(0.1, Some(7)), 1000);
+    assert!((50..150).contains(&counts.len()));
+    assert_eq!(sample(Sample::size(10, Some(7)), 5), [0, 1, 2, 3, 4]);
+    assert_eq!(parse_rate("0.1"), Ok(0.1));
+    assert!(parse_rate("10").is_err());
+  }
+}
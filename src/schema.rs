@@ -0,0 +1,404 @@
+//! Mapping the input columns to the fields of the records.
+
+use std::str::FromStr;
+
+use failure::{bail, Error};
+
+use crate::{commands::Options, reject::RejectReason, Record, Rejected};
+
+/// A field of the records.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Field {
+  Ph,
+  Name,
+  Count,
+}
+
+impl Field {
+  pub const ALL: [Field; 3] = [Field::Ph, Field::Name, Field::Count];
+  pub const VARIANTS: &'static [&'static str] = &["ph", "name", "count"];
+
+  pub fn as_str(self) -> &'static str {
+    match self {
+      Field::Ph => "ph",
+      Field::Name => "name",
+      Field::Count => "count",
+    }
+  }
+
+  /// Whether the input column of the field must be there, the `ph` one
+  /// always is, the others unless `--require-columns` doesn't have them.
+  fn is_required(self, options: &Options) -> bool {
+    let required = &options.require_columns;
+    self == Field::Ph || required.is_empty() || required.contains(&self)
+  }
+}
+
+impl FromStr for Field {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match Field::ALL
+      .iter()
+      .find(|f| f.as_str().eq_ignore_ascii_case(s))
+    {
+      Some(&field) => Ok(field),
+      None => Err(format!("unknown field `{}`", s)),
+    }
+  }
+}
+
+/// A `field=Header` mapping of `--map`, the input column of a field.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mapping {
+  pub field: Field,
+  pub header: String,
+}
+
+impl FromStr for Mapping {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let (field, header) = match s.split_once('=') {
+      Some((field, header)) => (field.trim(), header.trim()),
+      None => return Err(format!("expected `field=Header`, got `{}`", s)),
+    };
+    Ok(Mapping {
+      field: field.parse()?,
+      header: header.to_owned(),
+    })
+  }
+}
+
+/// The positions of the record fields in the input rows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schema {
+  ph: usize,
+  /// The name and count columns, empty and zero when they are missing.
+  name: Option<usize>,
+  count: Option<usize>,
+  /// The other columns, passed through as they are.
+  extra: Vec<usize>,
+  extra_headers: Vec<String>,
+  /// Whether the empty counts are zero, and the invalid ones rejected.
+  lenient_count: bool,
+  /// The separators of the phone numbers of a single cell, if any.
+  split_cell: Vec<char>,
+  /// The other column of the `--extract-from` text, if any.
+  extract: Option<usize>,
+  /// The other columns of the `--ph-columns`, and whether they are a record
+  /// each.
+  phones: Vec<usize>,
+  explode: bool,
+}
+
+impl Schema {
+  /// Finds the columns named by the `options` in the `headers`, ignoring
+  /// the case, the `--map` ones taking precedence over the `--*-column`
+  /// ones.
+  ///
+  /// With `--no-headers`, the `headers` are the first row, and the columns
+  /// are found by their `--*-index` positions instead.
+  pub fn new(
+    headers: &csv::StringRecord,
+    options: &Options,
+  ) -> Result<Self, Error> {
+    if options.no_headers {
+      return Self::by_index(headers.len(), options);
+    }
+    let column = |field: Field| {
+      let mapped = options.map.iter().rev().find(|m| m.field == field);
+      match (mapped, field) {
+        (Some(mapping), _) => &mapping.header,
+        (None, Field::Ph) => {
+          options.ph_columns.first().unwrap_or(&options.ph_column)
+        },
+        (None, Field::Name) => &options.name_column,
+        (None, Field::Count) => &options.count_column,
+      }
+    };
+    let find = |field: Field| {
+      let name = column(field);
+      headers
+        .iter()
+        .position(|h| h.trim().eq_ignore_ascii_case(name))
+    };
+    let unmapped: Vec<_> = Field::ALL
+      .iter()
+      .filter(|&&f| f.is_required(options) && find(f).is_none())
+      .map(|&f| format!("{} (`{}`)", f.as_str(), column(f)))
+      .collect();
+    if !unmapped.is_empty() {
+      bail!(
+        "unmapped required columns {} in the headers `{}`",
+        unmapped.join(", "),
+        headers.iter().collect::<Vec<_>>().join(",")
+      );
+    }
+    let fields = [find(Field::Ph), find(Field::Name), find(Field::Count)];
+    let headers = headers.iter().map(String::from).collect();
+    Self::with_fields(fields, headers, options)
+  }
+
+  /// Finds the columns by their `--*-index` positions in rows of `len`
+  /// columns, the other ones headed by their position, like `column_3`.
+  fn by_index(len: usize, options: &Options) -> Result<Self, Error> {
+    let mut fields = [
+      Some(options.ph_index.unwrap_or(0)),
+      Some(options.name_index.unwrap_or(1)),
+      Some(options.count_index.unwrap_or(2)),
+    ];
+    for (field, index) in Field::ALL.iter().zip(&mut fields) {
+      let i = index.unwrap_or_default();
+      if i >= len && !field.is_required(options) {
+        *index = None;
+      } else if i >= len {
+        bail!(
+          "no column {} for the {} field in rows of {} columns",
+          i,
+          field.as_str(),
+          len
+        );
+      }
+    }
+    let headers = (0..len).map(|i| format!("column_{}", i)).collect();
+    Self::with_fields(fields, headers, options)
+  }
+
+  /// The schema of the ph, name and count `fields`, passing through the
+  /// other columns of the `headers`, among them the `--extract-from` one and
+  /// the other `--ph-columns`, if any.
+  fn with_fields(
+    fields: [Option<usize>; 3],
+    headers: Vec<String>,
+    options: &Options,
+  ) -> Result<Self, Error> {
+    let extra: Vec<_> = (0..headers.len())
+      .filter(|&i| !fields.contains(&Some(i)))
+      .collect();
+    let find = |name: &str| {
+      extra
+        .iter()
+        .position(|&i| headers[i].trim().eq_ignore_ascii_case(name))
+    };
+    let extract = match &options.extract_from {
+      Some(name) => match find(name) {
+        Some(i) => Some(i),
+        None => bail!("no `{}` column to extract the phone numbers from", name),
+      },
+      None => None,
+    };
+    let ph = fields[0].unwrap_or_default();
+    let mut phones = Vec::new();
+    for name in &options.ph_columns {
+      match find(name) {
+        Some(i) => phones.push(i),
+        None if headers[ph].trim().eq_ignore_ascii_case(name) => {},
+        None => bail!("no `{}` column of phone numbers", name),
+      }
+    }
+    Ok(Schema {
+      // the ph column is always found.
+      ph,
+      name: fields[1],
+      count: fields[2],
+      extra_headers: extra.iter().map(|&i| headers[i].clone()).collect(),
+      extra,
+      lenient_count: options.lenient_count,
+      split_cell: options.split_cell.iter().flat_map(|s| s.chars()).collect(),
+      extract,
+      phones,
+      explode: options.explode,
+    })
+  }
+
+  /// The other column of the `--extract-from` text, if any.
+  pub fn extract(&self) -> Option<usize> {
+    self.extract
+  }
+
+  /// The other columns of the `--ph-columns`, none by default.
+  pub fn phones(&self) -> &[usize] {
+    &self.phones
+  }
+
+  /// Whether the phone numbers of the `phones` columns are a record each.
+  pub fn explode(&self) -> bool {
+    self.explode
+  }
+
+  /// The separators of the phone numbers of a single cell, none by default.
+  pub fn split_cell(&self) -> &[char] {
+    &self.split_cell
+  }
+
+  /// The headers of the other columns, in order.
+  pub fn extra_headers(&self) -> &[String] {
+    &self.extra_headers
+  }
+
+  /// Reads the record of the `row`, only its used fields must be UTF-8.
+  pub fn record(&self, row: &csv::ByteRecord) -> Result<Record, Error> {
+    match self.read(row)? {
+      Ok(record) => Ok(record),
+      Err(rejected) => bail!("invalid count of `{}`", rejected.record.ph),
+    }
+  }
+
+  /// Reads the record of the `row` like `record`, rejecting it when its
+  /// count is invalid with `--lenient-count`.
+  pub fn read(
+    &self,
+    row: &csv::ByteRecord,
+  ) -> Result<Result<Record, Rejected>, Error> {
+    let field = |i: usize| -> Result<&str, Error> {
+      let bytes = row.get(i).unwrap_or(b"");
+      match std::str::from_utf8(bytes) {
+        Ok(field) => Ok(field),
+        Err(e) => bail!("invalid UTF-8 in field {}: {}", i + 1, e),
+      }
+    };
+    let mut extra = Vec::with_capacity(self.extra.len());
+    for &i in &self.extra {
+      extra.push(field(i)?.to_owned());
+    }
+    let mut record = Record {
+      ph: field(self.ph)?.to_owned(),
+      name: match self.name {
+        Some(i) => field(i)?.to_owned(),
+        None => String::new(),
+      },
+      count: 0,
+      extra,
+    };
+    let i = match self.count {
+      Some(i) => i,
+      None => return Ok(Ok(record)),
+    };
+    let mut count = field(i)?;
+    if self.lenient_count {
+      count = count.trim();
+    }
+    match count.parse() {
+      Ok(count) => record.count = count,
+      Err(_) if self.lenient_count && count.is_empty() => {},
+      Err(_) if self.lenient_count => {
+        return Ok(Err(Rejected {
+          attempt: record.ph.clone(),
+          record,
+          reason: RejectReason::InvalidCount,
+          line: 0,
+        }))
+      },
+      Err(e) => bail!("invalid count `{}`: {}", count, e),
+    }
+    Ok(Ok(record))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use structopt::StructOpt;
+
+  use super::*;
+
+  #[test]
+  fn should_map_columns() {
+    let options = Options::from_iter(&[
+      "mobcsv",
+      "--ph-column",
+      "mobile_no",
+      "--count-column",
+      "sends",
+    ]);
+    let headers =
+      csv::StringRecord::from(vec!["sends", "city", "name", "mobile_no", "x"]);
+    let schema = Schema::new(&headers, &options).unwrap();
+    assert_eq!(schema.extra_headers(), ["city", "x"]);
+    let row = csv::ByteRecord::from(vec!["3", "Cairo", "a", "01116613061"]);
+    let record = schema.record(&row).unwrap();
+    assert_eq!(record.ph, "01116613061");
+    assert_eq!(record.name, "a");
+    assert_eq!(record.count, 3);
+    assert_eq!(record.extra, ["Cairo", ""]);
+    let row = csv::ByteRecord::from(vec!["x", "", "a", "01116613061"]);
+    assert!(schema.record(&row).is_err());
+    let row = csv::ByteRecord::from(vec![&b"3"[..], b"", b"a", b"0111\xff"]);
+    assert!(schema.record(&row).is_err());
+    let headers = csv::StringRecord::from(vec!["ph", "name", "count"]);
+    assert!(Schema::new(&headers, &options).is_err());
+  }
+
+  #[test]
+  fn should_default_missing_columns() {
+    let options = Options::from_iter(&["mobcsv", "--require-columns", "ph"]);
+    let headers = csv::StringRecord::from(vec!["city", "Ph"]);
+    let schema = Schema::new(&headers, &options).unwrap();
+    assert_eq!(schema.extra_headers(), ["city"]);
+    let row = csv::ByteRecord::from(vec!["Cairo", "01116613061"]);
+    let record = schema.record(&row).unwrap();
+    assert_eq!((record.name.as_str(), record.count), ("", 0));
+    let headers = csv::StringRecord::from(vec!["name", "count"]);
+    assert!(Schema::new(&headers, &options).is_err());
+  }
+
+  #[test]
+  fn should_map_headers() {
+    let options = Options::from_iter(&[
+      "mobcsv",
+      "--map",
+      "ph=Mobile,name=Customer Name,count=Sends",
+    ]);
+    let headers =
+      csv::StringRecord::from(vec!["customer name", "MOBILE", "sends"]);
+    let schema = Schema::new(&headers, &options).unwrap();
+    let row = csv::ByteRecord::from(vec!["a", "01116613061", "3"]);
+    assert_eq!(schema.record(&row).unwrap().ph, "01116613061");
+    let headers = csv::StringRecord::from(vec!["Mobile", "name", "count"]);
+    let err = Schema::new(&headers, &options).unwrap_err().to_string();
+    assert!(
+      err.contains("name (`Customer Name`), count (`Sends`)"),
+      "{}",
+      err
+    );
+    assert!("phone=Mobile".parse::<Mapping>().is_err());
+    assert!("ph".parse::<Mapping>().is_err());
+  }
+
+  #[test]
+  fn should_map_indexes() {
+    let options = Options::from_iter(&[
+      "mobcsv",
+      "--no-headers",
+      "--ph-index",
+      "1",
+      "--name-index",
+      "0",
+    ]);
+    let first = csv::StringRecord::from(vec!["a", "01116613061", "3", "x"]);
+    let schema = Schema::new(&first, &options).unwrap();
+    assert_eq!(schema.extra_headers(), ["column_3"]);
+    let record = schema.record(first.as_byte_record()).unwrap();
+    assert_eq!(record.ph, "01116613061");
+    assert_eq!(record.name, "a");
+    assert_eq!(record.extra, ["x"]);
+    let first = csv::StringRecord::from(vec!["a", "01116613061"]);
+    assert!(Schema::new(&first, &options).is_err());
+  }
+
+  #[test]
+  fn should_read_lenient_counts() {
+    let options = Options::from_iter(&["mobcsv", "--lenient-count"]);
+    let headers = csv::StringRecord::from(vec!["ph", "name", "count"]);
+    let schema = Schema::new(&headers, &options).unwrap();
+    let row = csv::ByteRecord::from(vec!["01116613061", "a", "100000"]);
+    assert_eq!(schema.read(&row).unwrap().unwrap().count, 100_000);
+    let row = csv::ByteRecord::from(vec!["01116613061", "a", " "]);
+    assert_eq!(schema.read(&row).unwrap().unwrap().count, 0);
+    let row = csv::ByteRecord::from(vec!["01116613061", "a"]);
+    assert_eq!(schema.read(&row).unwrap().unwrap().count, 0);
+    let row = csv::ByteRecord::from(vec!["01116613061", "a", "-1"]);
+    let rejected = schema.read(&row).unwrap().unwrap_err();
+    assert_eq!(rejected.reason, RejectReason::InvalidCount);
+  }
+}
@@ -0,0 +1,431 @@
+//! The default flags of the subcommands, read from a `mobcsv.toml` file and
+//! the `MOBCSV_*` environment variables, overridden by the ones of the
+//! command line.
+//!
+//! The top level keys of the file are the flags of every subcommand, and the
+//! ones of a `[clean]` (or `[validate]`, ...) table only the flags of that
+//! subcommand:
+//!
+//! ```toml
+//! countries = [20, 966]
+//! map = ["ph=Mobile", "name=Customer Name"]
+//!
+//! [clean]
+//! output-format = "jsonl"
+//! rejects-path = "rejects.csv"
+//! ```
+//!
+//! With `--profile egypt-sms`, the flags of a `[profile.egypt-sms]` table,
+//! and of its own `[clean]` table, override the flags with the same keys.
+//!
+//! The environment variables, like `MOBCSV_DEFAULT_COUNTRY=EG`, replace the
+//! flags of the file.
+
+use std::{
+  env,
+  ffi::OsString,
+  fs,
+  path::{Path, PathBuf},
+};
+
+use failure::{bail, Error, ResultExt};
+use structopt::{
+  clap::{AppSettings, ErrorKind},
+  StructOpt,
+};
+use toml::{value::Table, Value};
+
+use crate::commands::Cli;
+
+/// The config file searched in the current directory, then in the
+/// `mobcsv` config directory of the user.
+const FILE_NAME: &str = "mobcsv.toml";
+
+/// The prefix of the environment variables of the flags.
+const ENV_PREFIX: &str = "MOBCSV_";
+
+/// A flag of the config file.
+#[derive(Debug)]
+struct Flag {
+  key: String,
+  args: Vec<OsString>,
+  /// Whether it is a flag of the subcommand table, and not one shared by
+  /// all the subcommands.
+  own: bool,
+}
+
+/// Parses the command line `args`, adding the flags of the `MOBCSV_*`
+/// environment variables and of the config file they don't have.
+pub fn parse(args: Vec<OsString>) -> Result<Cli, Error> {
+  parse_with(args, env::vars_os())
+}
+
+/// Parses the command line `args` like `parse`, with the environment `vars`.
+fn parse_with<I>(args: Vec<OsString>, vars: I) -> Result<Cli, Error>
+where
+  I: IntoIterator<Item = (OsString, OsString)>,
+{
+  let app = || Cli::clap().global_setting(AppSettings::AllArgsOverrideSelf);
+  let command = match args.get(1).and_then(|arg| arg.to_str()) {
+    Some(command) if !command.starts_with('-') => command.to_owned(),
+    _ => return Ok(Cli::from_clap(&app().get_matches_from(args))),
+  };
+  let mut env = from_env(vars);
+  env.retain(|flag| !is_given(&args, &flag.key));
+  // the `--config` and `--profile` may be environment variables too.
+  let mut given = args.clone();
+  given.extend(env.iter().flat_map(|flag| flag.args.iter().cloned()));
+  let profile = value_of(&given, "profile");
+  let mut flags = match (config_path(&given)?, &profile) {
+    (Some(path), profile) => {
+      let mut flags = load(&path, &command, profile.as_deref())?;
+      // the file found is also the `--config` of the subcommand.
+      flags.push(Flag {
+        key: "config".to_owned(),
+        args: vec![format!("--config={}", path.display()).into()],
+        own: false,
+      });
+      flags
+    },
+    (None, Some(_)) => {
+      bail!("--profile needs a config file, `{}` or --config", FILE_NAME)
+    },
+    (None, None) => Vec::new(),
+  };
+  for flag in env {
+    flags.retain(|f| f.key != flag.key);
+    flags.push(flag);
+  }
+  flags.retain(|flag| !is_given(&args, &flag.key));
+  loop {
+    let mut all = args.clone();
+    let defaults = flags.iter().flat_map(|flag| flag.args.iter().cloned());
+    all.splice(2..2, defaults);
+    let e = match app().get_matches_from_safe(all) {
+      Ok(matches) => return Ok(Cli::from_clap(&matches)),
+      Err(e) => e,
+    };
+    // the shared flags are only added to the subcommands having them.
+    let unknown = e.info.as_ref().and_then(|info| info.first());
+    let index = unknown
+      .filter(|_| e.kind == ErrorKind::UnknownArgument)
+      .and_then(|arg| flags.iter().position(|f| *arg == long(&f.key)));
+    match index {
+      Some(i) if flags[i].own => bail!(
+        "`{}` of the config file is not a flag of `mobcsv {}`",
+        flags[i].key,
+        command
+      ),
+      Some(i) => {
+        flags.remove(i);
+      },
+      None => e.exit(),
+    }
+  }
+}
+
+/// The flags of the `MOBCSV_*` environment `vars`, like `--jobs=4` for
+/// `MOBCSV_JOBS=4`, or `--dedupe` for `MOBCSV_DEDUPE=true`.
+fn from_env<I>(vars: I) -> Vec<Flag>
+where
+  I: IntoIterator<Item = (OsString, OsString)>,
+{
+  let mut flags = Vec::new();
+  for (name, value) in vars {
+    let (name, value) = match (name.into_string(), value.into_string()) {
+      (Ok(name), Ok(value)) => (name, value),
+      _ => continue,
+    };
+    let key = match name.strip_prefix(ENV_PREFIX) {
+      Some(key) if !key.is_empty() => key.to_lowercase().replace('_', "-"),
+      _ => continue,
+    };
+    let args = match value.as_str() {
+      "true" => vec![long(&key).into()],
+      "false" | "" => vec![],
+      value => vec![format!("{}={}", long(&key), value).into()],
+    };
+    flags.push(Flag {
+      key,
+      args,
+      own: false,
+    });
+  }
+  flags
+}
+
+/// The value of the `--name` flag of the command line `args`.
+fn value_of(args: &[OsString], name: &str) -> Option<String> {
+  let flag = long(name);
+  let prefix = format!("{}=", flag);
+  args.iter().enumerate().find_map(|(i, arg)| {
+    let arg = arg.to_string_lossy();
+    if arg == flag {
+      args
+        .get(i + 1)
+        .map(|value| value.to_string_lossy().into_owned())
+    } else {
+      arg.strip_prefix(&prefix).map(str::to_owned)
+    }
+  })
+}
+
+/// The config file given with `--config`, or found in the current or the
+/// config directory.
+fn config_path(args: &[OsString]) -> Result<Option<PathBuf>, Error> {
+  if let Some(path) = value_of(args, "config").map(PathBuf::from) {
+    if !path.is_file() {
+      bail!("the config file {:?} doesn't exist", path);
+    }
+    return Ok(Some(path));
+  }
+  let dir = env::var_os("XDG_CONFIG_HOME")
+    .map(PathBuf::from)
+    .or_else(|| {
+      env::var_os("HOME").map(|home| Path::new(&home).join(".config"))
+    });
+  let found = Some(PathBuf::from(FILE_NAME))
+    .into_iter()
+    .chain(dir.map(|dir| dir.join("mobcsv").join(FILE_NAME)))
+    .find(|path| path.is_file());
+  Ok(found)
+}
+
+/// Loads the flags of the `command` subcommand from the config file at
+/// `path`, the ones of its table replacing the shared ones, then the ones
+/// of the `profile` replacing them all.
+fn load(
+  path: &Path,
+  command: &str,
+  profile: Option<&str>,
+) -> Result<Vec<Flag>, Error> {
+  let content = fs::read_to_string(path)
+    .with_context(|_| format!("could not read config file {:?}", path))?;
+  let table: Table = toml::from_str(&content)
+    .with_context(|_| format!("invalid config file {:?}", path))?;
+  let mut flags = Vec::new();
+  add(&mut flags, path, &table, command)?;
+  if let Some(name) = profile {
+    let profile = match table.get("profile").and_then(|p| p.get(name)) {
+      Some(Value::Table(profile)) => profile,
+      Some(_) => bail!("{:?}: the profile `{}` must be a table", path, name),
+      None => bail!("{:?}: there is no `{}` profile", path, name),
+    };
+    add(&mut flags, path, profile, command)?;
+  }
+  Ok(flags)
+}
+
+/// Adds the flags of the `table` and of its `command` table to the `flags`,
+/// replacing the ones with the same keys.
+fn add(
+  flags: &mut Vec<Flag>,
+  path: &Path,
+  table: &Table,
+  command: &str,
+) -> Result<(), Error> {
+  let mut set = |key: &str, value: &Value, own: bool| {
+    flags.retain(|flag| flag.key != key);
+    flags.push(flag(path, key, value, own)?);
+    Ok::<_, Error>(())
+  };
+  for (key, value) in table {
+    match value {
+      Value::Table(_) => {},
+      value => set(key, value, false)?,
+    }
+  }
+  if let Some(value) = table.get(command) {
+    let own = match value {
+      Value::Table(own) => own,
+      _ => bail!("{:?}: `{}` must be a table of flags", path, command),
+    };
+    for (key, value) in own {
+      set(key, value, true)?;
+    }
+  }
+  Ok(())
+}
+
+/// The flag of the `key` with the `value`, like `--jobs=4`, or `--dedupe`
+/// when it is true.
+fn flag(
+  path: &Path,
+  key: &str,
+  value: &Value,
+  own: bool,
+) -> Result<Flag, Error> {
+  let values = match value {
+    Value::Array(values) => values.iter().collect(),
+    Value::Boolean(true) => vec![],
+    value => vec![value],
+  };
+  let mut args = Vec::new();
+  if let Value::Boolean(true) = value {
+    args.push(long(key).into());
+  }
+  for value in values {
+    let value = match value {
+      Value::String(s) => s.clone(),
+      Value::Integer(n) => n.to_string(),
+      Value::Float(n) => n.to_string(),
+      Value::Boolean(false) => continue,
+      _ => bail!("{:?}: invalid value of `{}`", path, key),
+    };
+    args.push(format!("{}={}", long(key), value).into());
+  }
+  Ok(Flag {
+    key: key.to_owned(),
+    args,
+    own,
+  })
+}
+
+/// Whether the flag of the `key` is one of the command line `args`.
+fn is_given(args: &[OsString], key: &str) -> bool {
+  let flag = long(key);
+  let prefix = format!("{}=", flag);
+  args.iter().any(|arg| {
+    let arg = arg.to_string_lossy();
+    arg == flag || arg.starts_with(&prefix)
+  })
+}
+
+fn long(key: &str) -> String {
+  format!("--{}", key)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn args(args: &[&str]) -> Vec<OsString> {
+    args.iter().map(OsString::from).collect()
+  }
+
+  #[test]
+  fn should_add_the_flags_of_the_config_file() {
+    let path = env::temp_dir().join("mobcsv-settings.toml");
+    fs::write(
+      &path,
+      "jobs = 4\ncountries = [20, 966]\noutput-format = \"jsonl\"\n\
+       [validate]\nfail-fast = true\nph-column = \"Mobile\"\n",
+    )
+    .unwrap();
+    let path = path.to_str().unwrap();
+    let cli = parse(args(&[
+      "mobcsv", "validate", "in.csv", "--config", path, "-j2",
+    ]))
+    .unwrap();
+    let options = cli.options().unwrap();
+    assert_eq!(options.jobs, 2);
+    assert_eq!(options.countries.len(), 2);
+    assert!(options.fail_fast);
+    assert_eq!(options.ph_column, "Mobile");
+    let cli = parse(args(&[
+      "mobcsv",
+      "validate",
+      "in.csv",
+      "--config",
+      path,
+      "--countries",
+      "20",
+      "--ph-column=ph",
+    ]))
+    .unwrap();
+    assert_eq!(cli.options().unwrap().countries.len(), 1);
+    assert_eq!(cli.options().unwrap().ph_column, "ph");
+    fs::write(
+      path,
+      "jobs = 4\n[profile.sa]\njobs = 3\n[profile.sa.validate]\nlimit = 5\n",
+    )
+    .unwrap();
+    let cli = parse(args(&[
+      "mobcsv",
+      "validate",
+      "in.csv",
+      "--config",
+      path,
+      "--profile",
+      "sa",
+    ]))
+    .unwrap();
+    assert_eq!(cli.options().unwrap().jobs, 3);
+    assert_eq!(cli.options().unwrap().limit, Some(5));
+    assert!(parse(args(&[
+      "mobcsv",
+      "validate",
+      "in.csv",
+      "--config",
+      path,
+      "--profile=eg",
+    ]))
+    .is_err());
+    let vars = vec![
+      ("MOBCSV_JOBS".into(), "4".into()),
+      ("MOBCSV_FAIL_FAST".into(), "true".into()),
+      ("HOME".into(), "/root".into()),
+    ];
+    let flags = from_env(vars);
+    assert_eq!(flags.len(), 2);
+    assert_eq!(flags[0].args, args(&["--jobs=4"]));
+    assert_eq!(flags[1].args, args(&["--fail-fast"]));
+    fs::write(path, "[validate]\noutput-format = \"jsonl\"\n").unwrap();
+    assert!(
+      parse(args(&["mobcsv", "validate", "in.csv", "--config", path])).is_err()
+    );
+  }
+
+  #[test]
+  fn should_load_the_documented_example() {
+    let example: String = include_str!("settings.rs")
+      .lines()
+      .skip_while(|line| *line != "//! ```toml")
+      .skip(1)
+      .take_while(|line| *line != "//! ```")
+      .map(|line| format!("{}\n", line.trim_start_matches("//!").trim()))
+      .collect();
+    let path = env::temp_dir().join("mobcsv-example.toml");
+    fs::write(&path, example).unwrap();
+    let path = path.to_str().unwrap();
+    let cli = parse(args(&[
+      "mobcsv", "clean", "in.csv", "-o", "out.csv", "--config", path,
+    ]))
+    .unwrap();
+    let args = match cli {
+      Cli::Clean(args) => args,
+      _ => unreachable!(),
+    };
+    assert_eq!(args.options.countries.len(), 2);
+    assert_eq!(args.options.map.len(), 2);
+    assert_eq!(args.output.rejects_path, Some(PathBuf::from("rejects.csv")));
+  }
+
+  #[test]
+  fn should_prefer_the_env_vars_to_the_config_file() {
+    let path = env::temp_dir().join("mobcsv-env.toml");
+    fs::write(
+      &path,
+      "jobs = 4\n[validate]\nlimit = 5\n\
+       [profile.sa]\njobs = 3\n[profile.sa.validate]\nskip = 1\n",
+    )
+    .unwrap();
+    let path = path.to_str().unwrap();
+    let parse = |argv: &[&str], vars: &[(&str, &str)]| {
+      let mut all = args(&["mobcsv", "validate", "in.csv", "--config", path]);
+      all.extend(args(argv));
+      let vars = vars.iter().map(|(k, v)| (k.into(), v.into()));
+      let cli = parse_with(all, vars.collect::<Vec<_>>()).unwrap();
+      let options = cli.options().unwrap();
+      (options.jobs, options.limit, options.skip)
+    };
+    assert_eq!(parse(&[], &[]), (4, Some(5), 0));
+    let vars = [("MOBCSV_JOBS", "6"), ("MOBCSV_LIMIT", "7")];
+    assert_eq!(parse(&[], &vars), (6, Some(7), 0));
+    assert_eq!(parse(&["--profile", "sa"], &vars), (6, Some(7), 1));
+    assert_eq!(parse(&["-j2", "--limit=8"], &vars), (2, Some(8), 0));
+    // the profile may be chosen by the environment too.
+    let vars = [("MOBCSV_PROFILE", "sa")];
+    assert_eq!(parse(&[], &vars), (3, Some(5), 1));
+  }
+}
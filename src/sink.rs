@@ -0,0 +1,1107 @@
+//! The output sinks of the records, whatever their file format.
+
+use std::{
+  io::{self, Write},
+  path::{Path, PathBuf},
+  str::FromStr,
+  sync::Arc,
+};
+
+use arrow_array::{ArrayRef, RecordBatch, StringArray, UInt64Array};
+use arrow_schema::{DataType, Field, Schema};
+use failure::{bail, Error, ResultExt};
+use parquet::{
+  arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties,
+};
+use rust_xlsxwriter::{Format, Workbook, Worksheet};
+use serde_json::{Map, Value};
+
+/// The file format of the outputs.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OutputFormat {
+  #[default]
+  Csv,
+  /// An Excel workbook of a single sheet.
+  Xlsx,
+  /// A JSON object per line, its keys are the headers.
+  Jsonl,
+  /// An Apache Parquet file, for the analytics tools.
+  Parquet,
+  /// A table of a SQLite database, unique by phone number.
+  Sqlite,
+  /// A vCard per record, for the phone contacts apps.
+  Vcf,
+  /// A CSV file for the import of a contacts app.
+  Contacts(Preset),
+  /// The SQL statements loading the records into a table.
+  Sql,
+}
+
+impl OutputFormat {
+  pub const VARIANTS: &'static [&'static str] = &[
+    "csv",
+    "xlsx",
+    "jsonl",
+    "parquet",
+    "sqlite",
+    "vcf",
+    "google-contacts",
+    "outlook",
+    "sql",
+  ];
+
+  /// Whether the outputs are contacts, with their numbers in E.164.
+  pub fn is_contacts(self) -> bool {
+    matches!(self, OutputFormat::Vcf | OutputFormat::Contacts(_))
+  }
+}
+
+impl FromStr for OutputFormat {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "csv" => Ok(OutputFormat::Csv),
+      "xlsx" => Ok(OutputFormat::Xlsx),
+      "jsonl" => Ok(OutputFormat::Jsonl),
+      "parquet" => Ok(OutputFormat::Parquet),
+      "sqlite" => Ok(OutputFormat::Sqlite),
+      "vcf" => Ok(OutputFormat::Vcf),
+      "google-contacts" => Ok(OutputFormat::Contacts(Preset::GoogleContacts)),
+      "outlook" => Ok(OutputFormat::Contacts(Preset::Outlook)),
+      "sql" => Ok(OutputFormat::Sql),
+      _ => Err(format!("unknown output format `{}`", s)),
+    }
+  }
+}
+
+/// A destination of output rows, written field by field.
+pub trait Sink {
+  fn write_field(&mut self, field: &str) -> Result<(), Error>;
+
+  /// Ends the current row.
+  fn end_record(&mut self) -> Result<(), Error>;
+
+  /// Writes the buffered rows out, the sink is not to be used afterwards.
+  fn finish(&mut self) -> Result<(), Error>;
+
+  /// Writes the rows so far out, for the streamed formats, which are kept
+  /// in the buffers until the end otherwise.
+  fn flush(&mut self) -> Result<(), Error> {
+    Ok(())
+  }
+
+  fn write_row(&mut self, fields: &[&str]) -> Result<(), Error> {
+    for field in fields {
+      self.write_field(field)?;
+    }
+    self.end_record()
+  }
+
+  /// Writes the values of the `object` as a row, as they are by the JSON
+  /// Lines sinks, as strings by the other ones.
+  fn write_object(&mut self, object: &Map<String, Value>) -> Result<(), Error> {
+    for value in object.values() {
+      match value {
+        Value::String(s) => self.write_field(s)?,
+        Value::Null => self.write_field("")?,
+        value => self.write_field(&value.to_string())?,
+      }
+    }
+    self.end_record()
+  }
+}
+
+impl<W: Write> Sink for csv::Writer<W> {
+  fn write_field(&mut self, field: &str) -> Result<(), Error> {
+    Ok(csv::Writer::write_field(self, field)?)
+  }
+
+  fn end_record(&mut self) -> Result<(), Error> {
+    Ok(self.write_record(None::<&[u8]>)?)
+  }
+
+  fn finish(&mut self) -> Result<(), Error> {
+    Ok(csv::Writer::flush(self)?)
+  }
+
+  fn flush(&mut self) -> Result<(), Error> {
+    Ok(csv::Writer::flush(self)?)
+  }
+}
+
+/// The rows of a single sheet workbook, held in memory until the end.
+///
+/// The first row is frozen as the headers one, and the phone numbers column
+/// is formatted as text, for Excel not to turn them into numbers, losing
+/// their leading zeros.
+pub struct XlsxSink {
+  path: PathBuf,
+  sheet: Worksheet,
+  text: Format,
+  row: u32,
+  col: u16,
+}
+
+impl XlsxSink {
+  pub fn new(path: &Path) -> Result<Self, Error> {
+    let mut sheet = Worksheet::new();
+    let text = Format::new().set_num_format("@");
+    sheet.set_freeze_panes(1, 0)?;
+    sheet.set_column_format(0, &text)?;
+    Ok(XlsxSink {
+      path: path.to_owned(),
+      sheet,
+      text,
+      row: 0,
+      col: 0,
+    })
+  }
+}
+
+impl Sink for XlsxSink {
+  fn write_field(&mut self, field: &str) -> Result<(), Error> {
+    if self.col == 0 {
+      self
+        .sheet
+        .write_string_with_format(self.row, self.col, field, &self.text)?;
+    } else {
+      self.sheet.write_string(self.row, self.col, field)?;
+    }
+    self.col += 1;
+    Ok(())
+  }
+
+  fn end_record(&mut self) -> Result<(), Error> {
+    self.row += 1;
+    self.col = 0;
+    Ok(())
+  }
+
+  fn finish(&mut self) -> Result<(), Error> {
+    let mut workbook = Workbook::new();
+    workbook.push_worksheet(std::mem::take(&mut self.sheet));
+    let buffer = workbook.save_to_buffer()?;
+    if self.path == Path::new("-") {
+      io::stdout().write_all(&buffer)?;
+    } else {
+      std::fs::write(&self.path, buffer).with_context(|_| {
+        format!("could not write output file {:?}", self.path)
+      })?;
+    }
+    Ok(())
+  }
+}
+
+/// The rows written as JSON objects, one per line, keyed by the headers,
+/// the first row.
+///
+/// The `count` columns values are numbers, or null when empty, the other
+/// ones are strings.
+pub struct JsonlSink<W> {
+  out: W,
+  keys: Option<Vec<String>>,
+  fields: Vec<String>,
+}
+
+impl<W: Write> JsonlSink<W> {
+  pub fn new(out: W) -> Self {
+    JsonlSink {
+      out,
+      keys: None,
+      fields: Vec::new(),
+    }
+  }
+}
+
+impl<W: Write> Sink for JsonlSink<W> {
+  fn write_field(&mut self, field: &str) -> Result<(), Error> {
+    self.fields.push(field.to_owned());
+    Ok(())
+  }
+
+  fn end_record(&mut self) -> Result<(), Error> {
+    let fields = std::mem::take(&mut self.fields);
+    let keys = match &self.keys {
+      Some(keys) => keys,
+      None => {
+        self.keys = Some(fields);
+        return Ok(());
+      },
+    };
+    let value = |(key, field): (&String, String)| {
+      let value = if is_count(key) {
+        field.parse::<u64>().map_or(Value::Null, Value::from)
+      } else {
+        Value::String(field)
+      };
+      (key.clone(), value)
+    };
+    let object: Map<String, Value> =
+      keys.iter().zip(fields).map(value).collect();
+    serde_json::to_writer(&mut self.out, &object)?;
+    self.out.write_all(b"\n")?;
+    Ok(())
+  }
+
+  fn write_object(&mut self, object: &Map<String, Value>) -> Result<(), Error> {
+    serde_json::to_writer(&mut self.out, object)?;
+    self.out.write_all(b"\n")?;
+    Ok(())
+  }
+
+  fn finish(&mut self) -> Result<(), Error> {
+    Ok(self.out.flush()?)
+  }
+
+  fn flush(&mut self) -> Result<(), Error> {
+    Ok(self.out.flush()?)
+  }
+}
+
+/// The rows written as the row groups of a Parquet file, its columns are
+/// typed by the headers, the first row.
+///
+/// The `count` columns, like `count` or `old_count`, are 64 bits unsigned
+/// integers, the other ones are strings.
+pub struct ParquetSink<W: Write + Send> {
+  out: Option<W>,
+  writer: Option<ArrowWriter<W>>,
+  schema: Option<Arc<Schema>>,
+  /// The buffered rows, by column.
+  columns: Vec<Vec<String>>,
+  col: usize,
+  rows: usize,
+}
+
+impl<W: Write + Send> ParquetSink<W> {
+  /// The rows buffered into every row group.
+  const BATCH_ROWS: usize = 64 * 1024;
+
+  pub fn new(out: W) -> Self {
+    ParquetSink {
+      out: Some(out),
+      writer: None,
+      schema: None,
+      columns: Vec::new(),
+      col: 0,
+      rows: 0,
+    }
+  }
+
+  /// Starts the file with the `headers` schema.
+  fn start(&mut self, headers: &[String]) -> Result<(), Error> {
+    let fields: Vec<_> = headers
+      .iter()
+      .map(|h| {
+        let data_type = if is_count(h) {
+          DataType::UInt64
+        } else {
+          DataType::Utf8
+        };
+        Field::new(h.as_str(), data_type, true)
+      })
+      .collect();
+    let schema = Arc::new(Schema::new(fields));
+    let props = WriterProperties::builder()
+      .set_compression(Compression::SNAPPY)
+      .build();
+    let out = self
+      .out
+      .take()
+      .expect("the parquet sink is already started");
+    self.writer = Some(ArrowWriter::try_new(out, schema.clone(), Some(props))?);
+    self.schema = Some(schema);
+    self.columns = vec![Vec::new(); headers.len()];
+    Ok(())
+  }
+
+  /// Writes the buffered rows as a row group.
+  fn write_batch(&mut self) -> Result<(), Error> {
+    let (writer, schema) = match (&mut self.writer, &self.schema) {
+      (Some(writer), Some(schema)) if self.rows > 0 => (writer, schema),
+      _ => return Ok(()),
+    };
+    let arrays: Vec<ArrayRef> = schema
+      .fields()
+      .iter()
+      .zip(&mut self.columns)
+      .map(|(field, values)| -> ArrayRef {
+        let values = std::mem::take(values);
+        if field.data_type() == &DataType::UInt64 {
+          let counts: Vec<Option<u64>> =
+            values.iter().map(|v| v.parse().ok()).collect();
+          Arc::new(UInt64Array::from(counts))
+        } else {
+          Arc::new(StringArray::from(values))
+        }
+      })
+      .collect();
+    writer.write(&RecordBatch::try_new(schema.clone(), arrays)?)?;
+    self.rows = 0;
+    Ok(())
+  }
+}
+
+impl<W: Write + Send> Sink for ParquetSink<W> {
+  fn write_field(&mut self, field: &str) -> Result<(), Error> {
+    if self.writer.is_none() {
+      self.columns.push(vec![field.to_owned()]);
+    } else if let Some(values) = self.columns.get_mut(self.col) {
+      values.push(field.to_owned());
+    }
+    self.col += 1;
+    Ok(())
+  }
+
+  fn end_record(&mut self) -> Result<(), Error> {
+    self.col = 0;
+    if self.writer.is_none() {
+      let headers: Vec<_> =
+        self.columns.drain(..).flat_map(|c| c.into_iter()).collect();
+      return self.start(&headers);
+    }
+    self.rows += 1;
+    if self.rows >= Self::BATCH_ROWS {
+      self.write_batch()?;
+    }
+    Ok(())
+  }
+
+  fn finish(&mut self) -> Result<(), Error> {
+    self.write_batch()?;
+    if let Some(writer) = self.writer.take() {
+      let mut out = writer.into_inner()?;
+      out.flush()?;
+    }
+    Ok(())
+  }
+}
+
+/// The rows upserted into a table of a SQLite database, created if needed,
+/// with the headers, the first row, as its columns.
+///
+/// The first column, the phone numbers one, is the unique key of the table,
+/// a row of an existing number replaces the old one.
+pub struct SqliteSink {
+  conn: rusqlite::Connection,
+  table: String,
+  /// The upsert statement, once the columns are known.
+  insert: Option<String>,
+  fields: Vec<String>,
+}
+
+impl SqliteSink {
+  pub fn new(path: &Path, table: &str) -> Result<Self, Error> {
+    if path == Path::new("-") {
+      bail!("a SQLite database can't be written to the standard output");
+    }
+    let conn = rusqlite::Connection::open(path)
+      .with_context(|_| format!("could not open database {:?}", path))?;
+    // a single transaction is much faster than a commit per row.
+    conn.execute_batch("BEGIN")?;
+    Ok(SqliteSink {
+      conn,
+      table: table.to_owned(),
+      insert: None,
+      fields: Vec::new(),
+    })
+  }
+
+  /// Creates the table of the `columns` if needed, and prepares the upsert
+  /// statement of its rows.
+  fn start(&mut self, columns: &[String]) -> Result<(), Error> {
+    let quoted: Vec<_> = columns.iter().map(|c| quote(c)).collect();
+    let (key, others) = match quoted.split_first() {
+      Some(split) => split,
+      None => bail!("no columns for the `{}` table", self.table),
+    };
+    let table = quote(&self.table);
+    let definitions: Vec<_> = others
+      .iter()
+      .zip(&columns[1..])
+      .map(|(q, c)| {
+        let kind = if c == "count" { "INTEGER" } else { "TEXT" };
+        format!("{} {}", q, kind)
+      })
+      .collect();
+    self.conn.execute_batch(&format!(
+      "CREATE TABLE IF NOT EXISTS {} ({} TEXT NOT NULL PRIMARY KEY{}{})",
+      table,
+      key,
+      if definitions.is_empty() { "" } else { ", " },
+      definitions.join(", ")
+    ))?;
+    let params: Vec<_> =
+      (1..=quoted.len()).map(|i| format!("?{}", i)).collect();
+    let updates: Vec<_> = others
+      .iter()
+      .map(|q| format!("{} = excluded.{}", q, q))
+      .collect();
+    let on_conflict = if updates.is_empty() {
+      "DO NOTHING".to_owned()
+    } else {
+      format!("DO UPDATE SET {}", updates.join(", "))
+    };
+    self.insert = Some(format!(
+      "INSERT INTO {} ({}) VALUES ({}) ON CONFLICT({}) {}",
+      table,
+      quoted.join(", "),
+      params.join(", "),
+      key,
+      on_conflict
+    ));
+    Ok(())
+  }
+}
+
+/// Whether the column of the `header` is a count, like `count` or
+/// `old_count`.
+fn is_count(header: &str) -> bool {
+  header == "count" || header.ends_with("_count")
+}
+
+/// Quotes the SQL identifier `name`.
+fn quote(name: &str) -> String {
+  format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+impl Sink for SqliteSink {
+  fn write_field(&mut self, field: &str) -> Result<(), Error> {
+    self.fields.push(field.to_owned());
+    Ok(())
+  }
+
+  fn end_record(&mut self) -> Result<(), Error> {
+    let fields = std::mem::take(&mut self.fields);
+    match &self.insert {
+      Some(insert) => {
+        let mut statement = self.conn.prepare_cached(insert)?;
+        statement.execute(rusqlite::params_from_iter(&fields))?;
+        Ok(())
+      },
+      None => self.start(&fields),
+    }
+  }
+
+  fn finish(&mut self) -> Result<(), Error> {
+    Ok(self.conn.execute_batch("COMMIT")?)
+  }
+}
+
+/// The rows written as vCards, the `ph` and `name` columns are found by the
+/// headers, the first row, the other columns are left out.
+pub struct VcfSink<W> {
+  out: W,
+  /// The indexes of the `ph` and `name` columns, once the headers are known.
+  columns: Option<(usize, Option<usize>)>,
+  fields: Vec<String>,
+}
+
+impl<W: Write> VcfSink<W> {
+  pub fn new(out: W) -> Self {
+    VcfSink {
+      out,
+      columns: None,
+      fields: Vec::new(),
+    }
+  }
+}
+
+/// The indexes of the `ph` and `name` columns of the contacts `format`
+/// `headers`.
+fn contact_columns(
+  headers: &[String],
+  format: &str,
+) -> Result<(usize, Option<usize>), Error> {
+  let find = |header: &str| headers.iter().position(|h| h == header);
+  match find("ph") {
+    Some(ph) => Ok((ph, find("name"))),
+    None => bail!("the {} output format needs the `ph` column", format),
+  }
+}
+
+/// Escapes a vCard text value.
+fn escape(value: &str) -> String {
+  let mut out = String::with_capacity(value.len());
+  for c in value.chars() {
+    match c {
+      '\\' | ',' | ';' => {
+        out.push('\\');
+        out.push(c);
+      },
+      '\n' => out.push_str("\\n"),
+      '\r' => {},
+      _ => out.push(c),
+    }
+  }
+  out
+}
+
+impl<W: Write> Sink for VcfSink<W> {
+  fn write_field(&mut self, field: &str) -> Result<(), Error> {
+    self.fields.push(field.to_owned());
+    Ok(())
+  }
+
+  fn end_record(&mut self) -> Result<(), Error> {
+    let fields = std::mem::take(&mut self.fields);
+    let (ph, name) = match self.columns {
+      Some(columns) => columns,
+      None => {
+        self.columns = Some(contact_columns(&fields, "vcf")?);
+        return Ok(());
+      },
+    };
+    let ph = &fields[ph];
+    // a contact with no name is named by its number.
+    let name = name
+      .map(|name| fields[name].as_str())
+      .filter(|name| !name.is_empty())
+      .unwrap_or(ph);
+    let name = escape(name);
+    write!(
+      self.out,
+      "BEGIN:VCARD\r\nVERSION:3.0\r\nN:;{};;;\r\nFN:{}\r\n\
+       TEL;TYPE=CELL:{}\r\nEND:VCARD\r\n",
+      name,
+      name,
+      escape(ph)
+    )?;
+    Ok(())
+  }
+
+  fn finish(&mut self) -> Result<(), Error> {
+    Ok(self.out.flush()?)
+  }
+}
+
+/// A CSV layout of the contacts importers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Preset {
+  /// `Name`, `Phone 1 - Type` and `Phone 1 - Value`.
+  GoogleContacts,
+  /// `First Name`, `Last Name` and `Mobile Phone`.
+  Outlook,
+}
+
+impl Preset {
+  fn name(self) -> &'static str {
+    match self {
+      Preset::GoogleContacts => "google-contacts",
+      Preset::Outlook => "outlook",
+    }
+  }
+
+  fn headers(self) -> &'static [&'static str] {
+    match self {
+      Preset::GoogleContacts => &["Name", "Phone 1 - Type", "Phone 1 - Value"],
+      Preset::Outlook => &["First Name", "Last Name", "Mobile Phone"],
+    }
+  }
+}
+
+/// The rows remapped to the columns of a contacts importer `Preset`, the
+/// `ph` and `name` columns are found by the headers, the first row, the
+/// other columns are left out.
+pub struct PresetSink<S> {
+  inner: S,
+  preset: Preset,
+  /// The indexes of the `ph` and `name` columns, once the headers are known.
+  columns: Option<(usize, Option<usize>)>,
+  fields: Vec<String>,
+}
+
+impl<S: Sink> PresetSink<S> {
+  pub fn new(inner: S, preset: Preset) -> Self {
+    PresetSink {
+      inner,
+      preset,
+      columns: None,
+      fields: Vec::new(),
+    }
+  }
+}
+
+impl<S: Sink> Sink for PresetSink<S> {
+  fn write_field(&mut self, field: &str) -> Result<(), Error> {
+    self.fields.push(field.to_owned());
+    Ok(())
+  }
+
+  fn end_record(&mut self) -> Result<(), Error> {
+    let fields = std::mem::take(&mut self.fields);
+    let (ph, name) = match self.columns {
+      Some(columns) => columns,
+      None => {
+        self.columns = Some(contact_columns(&fields, self.preset.name())?);
+        return self.inner.write_row(self.preset.headers());
+      },
+    };
+    let ph = fields[ph].as_str();
+    let name = name.map_or("", |name| fields[name].trim());
+    match self.preset {
+      Preset::GoogleContacts => self.inner.write_row(&[name, "Mobile", ph]),
+      Preset::Outlook => {
+        let (first, last) = name.split_once(' ').unwrap_or((name, ""));
+        self.inner.write_row(&[first, last.trim_start(), ph])
+      },
+    }
+  }
+
+  fn finish(&mut self) -> Result<(), Error> {
+    self.inner.finish()
+  }
+}
+
+/// The SQL dialect of the `sql` output format.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SqlDialect {
+  /// Batched `INSERT` statements.
+  #[default]
+  Ansi,
+  /// A `COPY ... FROM stdin` statement of tab separated rows, for `psql`.
+  Postgres,
+}
+
+impl SqlDialect {
+  pub const VARIANTS: &'static [&'static str] = &["ansi", "postgres"];
+}
+
+impl FromStr for SqlDialect {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "ansi" => Ok(SqlDialect::Ansi),
+      "postgres" => Ok(SqlDialect::Postgres),
+      _ => Err(format!("unknown SQL dialect `{}`", s)),
+    }
+  }
+}
+
+/// The line ending of the CSV outputs.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum LineEnding {
+  #[default]
+  Lf,
+  /// For the Windows tools.
+  Crlf,
+  /// The one of the first input.
+  Preserve,
+}
+
+impl LineEnding {
+  pub const VARIANTS: &'static [&'static str] = &["lf", "crlf", "preserve"];
+}
+
+impl FromStr for LineEnding {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "lf" => Ok(LineEnding::Lf),
+      "crlf" => Ok(LineEnding::Crlf),
+      "preserve" => Ok(LineEnding::Preserve),
+      _ => Err(format!("unknown line ending `{}`", s)),
+    }
+  }
+}
+
+/// The rows written as the SQL statements loading them into a table, with
+/// the headers, the first row, as its columns.
+///
+/// The `count` columns values are written as numbers, the other ones as
+/// strings.
+pub struct SqlSink<W> {
+  out: W,
+  table: String,
+  dialect: SqlDialect,
+  /// Whether each column is a count one, once the headers are known.
+  counts: Option<Vec<bool>>,
+  /// The statement every batch of rows starts with.
+  statement: String,
+  /// The rows of the current `INSERT` batch.
+  rows: usize,
+  fields: Vec<String>,
+}
+
+impl<W: Write> SqlSink<W> {
+  /// The rows of every `INSERT` statement.
+  const BATCH_ROWS: usize = 1000;
+
+  pub fn new(out: W, table: &str, dialect: SqlDialect) -> Self {
+    SqlSink {
+      out,
+      table: quote(table),
+      dialect,
+      counts: None,
+      statement: String::new(),
+      rows: 0,
+      fields: Vec::new(),
+    }
+  }
+
+  fn start(&mut self, headers: &[String]) -> Result<(), Error> {
+    let columns: Vec<_> = headers.iter().map(|h| quote(h)).collect();
+    let columns = columns.join(", ");
+    self.counts = Some(headers.iter().map(|h| is_count(h)).collect());
+    match self.dialect {
+      SqlDialect::Ansi => {
+        self.statement =
+          format!("INSERT INTO {} ({}) VALUES\n", self.table, columns);
+      },
+      SqlDialect::Postgres => {
+        writeln!(self.out, "COPY {} ({}) FROM stdin;", self.table, columns)?;
+      },
+    }
+    Ok(())
+  }
+
+  /// Ends the current `INSERT` statement, if any.
+  fn end_batch(&mut self) -> Result<(), Error> {
+    if self.rows > 0 {
+      self.out.write_all(b";\n")?;
+      self.rows = 0;
+    }
+    Ok(())
+  }
+}
+
+/// The SQL string literal of `value`.
+fn literal(value: &str) -> String {
+  format!("'{}'", value.replace('\'', "''"))
+}
+
+/// Escapes a field of the Postgres `COPY` text format.
+fn copy_escape(value: &str) -> String {
+  let mut out = String::with_capacity(value.len());
+  for c in value.chars() {
+    match c {
+      '\\' => out.push_str("\\\\"),
+      '\t' => out.push_str("\\t"),
+      '\n' => out.push_str("\\n"),
+      '\r' => out.push_str("\\r"),
+      _ => out.push(c),
+    }
+  }
+  out
+}
+
+impl<W: Write> Sink for SqlSink<W> {
+  fn write_field(&mut self, field: &str) -> Result<(), Error> {
+    self.fields.push(field.to_owned());
+    Ok(())
+  }
+
+  fn end_record(&mut self) -> Result<(), Error> {
+    let fields = std::mem::take(&mut self.fields);
+    let counts = match &self.counts {
+      Some(counts) => counts,
+      None => return self.start(&fields),
+    };
+    let is_number = |i: usize, value: &str| {
+      counts[i]
+        && !value.is_empty()
+        && value.bytes().all(|b| b.is_ascii_digit())
+    };
+    match self.dialect {
+      SqlDialect::Ansi => {
+        let values: Vec<_> = fields
+          .iter()
+          .enumerate()
+          .map(|(i, v)| {
+            if is_number(i, v) {
+              v.clone()
+            } else {
+              literal(v)
+            }
+          })
+          .collect();
+        if self.rows == 0 {
+          self.out.write_all(self.statement.as_bytes())?;
+        } else {
+          self.out.write_all(b",\n")?;
+        }
+        write!(self.out, "  ({})", values.join(", "))?;
+        self.rows += 1;
+        if self.rows == Self::BATCH_ROWS {
+          self.end_batch()?;
+        }
+      },
+      SqlDialect::Postgres => {
+        let values: Vec<_> = fields
+          .iter()
+          .enumerate()
+          .map(|(i, v)| {
+            if counts[i] && v.is_empty() {
+              "\\N".to_owned()
+            } else {
+              copy_escape(v)
+            }
+          })
+          .collect();
+        writeln!(self.out, "{}", values.join("\t"))?;
+      },
+    }
+    Ok(())
+  }
+
+  fn finish(&mut self) -> Result<(), Error> {
+    match self.dialect {
+      SqlDialect::Ansi => self.end_batch()?,
+      SqlDialect::Postgres => self.out.write_all(b"\\.\n")?,
+    }
+    Ok(self.out.flush()?)
+  }
+}
+
+/// A sink writing to a temporary file, renamed to `path` when the sink
+/// finishes, for an interrupted run not to leave an output looking
+/// complete.
+pub struct AtomicSink {
+  inner: Option<Box<dyn Sink>>,
+  tmp: PathBuf,
+  path: PathBuf,
+}
+
+impl AtomicSink {
+  /// The temporary path of the output `path`, like `output.csv.tmp`.
+  pub fn tmp_path(path: &Path) -> PathBuf {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    tmp.into()
+  }
+
+  pub fn new(inner: Box<dyn Sink>, path: &Path) -> Self {
+    AtomicSink {
+      inner: Some(inner),
+      tmp: Self::tmp_path(path),
+      path: path.to_owned(),
+    }
+  }
+
+  fn inner(&mut self) -> Result<&mut Box<dyn Sink>, Error> {
+    match &mut self.inner {
+      Some(inner) => Ok(inner),
+      None => bail!("the output {:?} is finished", self.path),
+    }
+  }
+}
+
+impl Sink for AtomicSink {
+  fn write_field(&mut self, field: &str) -> Result<(), Error> {
+    self.inner()?.write_field(field)
+  }
+
+  fn end_record(&mut self) -> Result<(), Error> {
+    self.inner()?.end_record()
+  }
+
+  fn write_object(&mut self, object: &Map<String, Value>) -> Result<(), Error> {
+    self.inner()?.write_object(object)
+  }
+
+  fn finish(&mut self) -> Result<(), Error> {
+    if let Some(mut inner) = self.inner.take() {
+      inner.finish()?;
+      // closes the file, finishing any compressed stream.
+      drop(inner);
+      std::fs::rename(&self.tmp, &self.path).with_context(|_| {
+        format!("could not rename {:?} to {:?}", self.tmp, self.path)
+      })?;
+    }
+    Ok(())
+  }
+
+  fn flush(&mut self) -> Result<(), Error> {
+    self.inner()?.flush()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::source::{SheetSource, Source};
+
+  #[test]
+  fn should_write_csv_rows() {
+    let mut wrt = csv::Writer::from_writer(vec![]);
+    wrt.write_row(&["ph", "name"]).unwrap();
+    Sink::write_field(&mut wrt, "201116613061").unwrap();
+    Sink::write_field(&mut wrt, "a").unwrap();
+    wrt.end_record().unwrap();
+    let out = String::from_utf8(wrt.into_inner().unwrap()).unwrap();
+    assert_eq!(out, "ph,name\n201116613061,a\n");
+  }
+
+  #[test]
+  fn should_write_jsonl_objects() {
+    let mut sink = JsonlSink::new(vec![]);
+    sink.write_row(&["ph", "name"]).unwrap();
+    sink.write_row(&["201116613061", "a \"b\""]).unwrap();
+    sink.finish().unwrap();
+    let out = String::from_utf8(sink.out).unwrap();
+    assert_eq!(out, "{\"ph\":\"201116613061\",\"name\":\"a \\\"b\\\"\"}\n");
+    let mut sink = JsonlSink::new(vec![]);
+    sink.write_row(&["ph", "count", "old_count"]).unwrap();
+    sink.write_row(&["201116613061", "2", ""]).unwrap();
+    let object: Value = serde_json::from_slice(&sink.out).unwrap();
+    assert_eq!(object["ph"], Value::from("201116613061"));
+    assert_eq!(object["count"].as_u64(), Some(2));
+    assert!(object["old_count"].is_null());
+  }
+
+  #[test]
+  fn should_write_vcards() {
+    let mut sink = VcfSink::new(vec![]);
+    sink.write_row(&["ph", "name", "count"]).unwrap();
+    sink
+      .write_row(&["+201116613061", "Doe, Jane", "2"])
+      .unwrap();
+    sink.write_row(&["+201116613062", "", "1"]).unwrap();
+    sink.finish().unwrap();
+    let out = String::from_utf8(sink.out).unwrap();
+    assert_eq!(
+      out,
+      "BEGIN:VCARD\r\nVERSION:3.0\r\nN:;Doe\\, Jane;;;\r\nFN:Doe\\, Jane\r\n\
+       TEL;TYPE=CELL:+201116613061\r\nEND:VCARD\r\n\
+       BEGIN:VCARD\r\nVERSION:3.0\r\nN:;+201116613062;;;\r\n\
+       FN:+201116613062\r\nTEL;TYPE=CELL:+201116613062\r\nEND:VCARD\r\n"
+    );
+    let mut sink = VcfSink::new(vec![]);
+    assert!(sink.write_row(&["name"]).is_err());
+  }
+
+  #[test]
+  fn should_remap_contacts_presets() {
+    let rows = [["ph", "name", "count"], ["+201116613061", "Jane Doe", "2"]];
+    let mut sink =
+      PresetSink::new(csv::Writer::from_writer(vec![]), Preset::Outlook);
+    for row in &rows {
+      sink.write_row(row).unwrap();
+    }
+    let out = String::from_utf8(sink.inner.into_inner().unwrap()).unwrap();
+    assert_eq!(
+      out,
+      "First Name,Last Name,Mobile Phone\nJane,Doe,+201116613061\n"
+    );
+    let format: OutputFormat = "google-contacts".parse().unwrap();
+    assert_eq!(format, OutputFormat::Contacts(Preset::GoogleContacts));
+    assert!(format.is_contacts());
+    let mut sink =
+      PresetSink::new(csv::Writer::from_writer(vec![]), Preset::GoogleContacts);
+    for row in &rows {
+      sink.write_row(row).unwrap();
+    }
+    let out = String::from_utf8(sink.inner.into_inner().unwrap()).unwrap();
+    assert_eq!(
+      out,
+      "Name,Phone 1 - Type,Phone 1 - Value\nJane Doe,Mobile,+201116613061\n"
+    );
+  }
+
+  #[test]
+  fn should_write_sql_statements() {
+    let rows = [
+      ["ph", "name", "count"],
+      ["201116613061", "O'Brien", "2"],
+      ["201116613062", "a\tb", "1"],
+    ];
+    let mut sink = SqlSink::new(vec![], "contacts", SqlDialect::Ansi);
+    for row in &rows {
+      sink.write_row(row).unwrap();
+    }
+    sink.finish().unwrap();
+    assert_eq!(
+      String::from_utf8(sink.out).unwrap(),
+      "INSERT INTO \"contacts\" (\"ph\", \"name\", \"count\") VALUES\n  \
+       ('201116613061', 'O''Brien', 2),\n  ('201116613062', 'a\tb', 1);\n"
+    );
+    let mut sink = SqlSink::new(vec![], "contacts", SqlDialect::Postgres);
+    for row in &rows {
+      sink.write_row(row).unwrap();
+    }
+    sink.finish().unwrap();
+    assert_eq!(
+      String::from_utf8(sink.out).unwrap(),
+      "COPY \"contacts\" (\"ph\", \"name\", \"count\") FROM stdin;\n\
+       201116613061\tO'Brien\t2\n201116613062\ta\\tb\t1\n\\.\n"
+    );
+  }
+
+  #[test]
+  fn should_write_parquet_file() {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    let path = std::env::temp_dir().join("mobcsv-sink.parquet");
+    let mut sink = ParquetSink::new(std::fs::File::create(&path).unwrap());
+    sink.write_row(&["ph", "name", "count"]).unwrap();
+    sink.write_row(&["201116613061", "a", "3"]).unwrap();
+    sink.write_row(&["966540029129", "b", "1"]).unwrap();
+    sink.finish().unwrap();
+    let reader =
+      SerializedFileReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+    assert_eq!(reader.metadata().file_metadata().num_rows(), 2);
+    let schema = reader.metadata().file_metadata().schema_descr();
+    assert_eq!(schema.column(2).name(), "count");
+  }
+
+  #[test]
+  fn should_upsert_sqlite_rows() {
+    let path = std::env::temp_dir().join("mobcsv-sink.sqlite");
+    let _ = std::fs::remove_file(&path);
+    for rows in &[
+      &[["201116613061", "a", "3"], ["966540029129", "b", "1"]][..],
+      &[["201116613061", "c", "5"]][..],
+    ] {
+      let mut sink = SqliteSink::new(&path, "contacts").unwrap();
+      sink.write_row(&["ph", "name", "count"]).unwrap();
+      for row in rows.iter() {
+        sink.write_row(row).unwrap();
+      }
+      sink.finish().unwrap();
+    }
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    let rows: Vec<(String, String, i64)> = conn
+      .prepare("SELECT ph, name, count FROM contacts ORDER BY ph")
+      .unwrap()
+      .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+      .unwrap()
+      .collect::<Result<_, _>>()
+      .unwrap();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0], ("201116613061".to_owned(), "c".to_owned(), 5));
+    assert!(SqliteSink::new(Path::new("-"), "contacts").is_err());
+  }
+
+  #[test]
+  fn should_write_xlsx_workbook() {
+    let path = std::env::temp_dir().join("mobcsv-sink.xlsx");
+    let mut sink = XlsxSink::new(&path).unwrap();
+    sink.write_row(&["ph", "name"]).unwrap();
+    sink.write_row(&["0201116613061", "a"]).unwrap();
+    sink.finish().unwrap();
+    let mut source = SheetSource::open(&path, None, true).unwrap();
+    assert_eq!(source.headers().unwrap(), vec!["ph", "name"]);
+    let mut row = csv::ByteRecord::new();
+    assert!(source.read(&mut row).unwrap());
+    assert_eq!(row, vec!["0201116613061", "a"]);
+    assert_eq!("xlsx".parse(), Ok(OutputFormat::Xlsx));
+  }
+
+  #[test]
+  fn should_rename_atomic_output_when_finished() {
+    let path = std::env::temp_dir().join("mobcsv-atomic.csv");
+    let tmp = AtomicSink::tmp_path(&path);
+    let _ = std::fs::remove_file(&path);
+    let wrt = csv::Writer::from_path(&tmp).unwrap();
+    let mut sink = AtomicSink::new(Box::new(wrt), &path);
+    sink.write_row(&["ph", "name"]).unwrap();
+    assert!(!path.exists());
+    sink.finish().unwrap();
+    assert!(!tmp.exists());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "ph,name\n");
+    assert!(sink.write_row(&["201116613061", "a"]).is_err());
+  }
+}
@@ -0,0 +1,453 @@
+//! Sorting the accepted records as a final stage, spilling the sorted runs
+//! to temporary files when they don't fit in memory, or keeping only the
+//! top ones.
+
+use std::{
+  cmp::{Ordering, Reverse},
+  collections::BinaryHeap,
+  fs::File,
+  mem,
+  path::PathBuf,
+  str::FromStr,
+};
+
+use failure::{Error, ResultExt};
+use log::debug;
+
+use crate::{Accepted, Record};
+
+/// The runs merged at once, each one an open file.
+const MAX_RUNS: usize = 64;
+
+/// A field the records are sorted by, and its order.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SortKey {
+  field: SortField,
+  desc: bool,
+}
+
+/// A field of the records they are sorted by.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortField {
+  Ph,
+  Name,
+  Count,
+}
+
+impl SortField {
+  pub const VARIANTS: &'static [&'static str] = &["ph", "name", "count"];
+
+  fn compare(self, a: &Record, b: &Record) -> Ordering {
+    match self {
+      SortField::Ph => a.ph.cmp(&b.ph),
+      SortField::Name => a.name.cmp(&b.name),
+      SortField::Count => a.count.cmp(&b.count),
+    }
+  }
+}
+
+impl FromStr for SortField {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "ph" => Ok(SortField::Ph),
+      "name" => Ok(SortField::Name),
+      "count" => Ok(SortField::Count),
+      _ => Err(format!("unknown sort field `{}`", s)),
+    }
+  }
+}
+
+impl FromStr for SortKey {
+  type Err = String;
+
+  /// Parses `field[:asc|desc]`, like `count:desc`, ascending by default.
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let (field, order) = s.split_once(':').unwrap_or((s, "asc"));
+    let field = field.parse()?;
+    let desc = match order {
+      "asc" => false,
+      "desc" => true,
+      _ => return Err(format!("unknown sort order `{}`", order)),
+    };
+    Ok(SortKey { field, desc })
+  }
+}
+
+/// Compares the records by the `keys`, in order.
+fn compare(keys: &[SortKey], a: &Accepted, b: &Accepted) -> Ordering {
+  let (a, b) = (&a.record, &b.record);
+  for key in keys {
+    let ordering = key.field.compare(a, b);
+    let ordering = if key.desc {
+      ordering.reverse()
+    } else {
+      ordering
+    };
+    if ordering != Ordering::Equal {
+      return ordering;
+    }
+  }
+  Ordering::Equal
+}
+
+/// The estimated memory of a record, its strings and the structs.
+fn size_of(accepted: &Accepted) -> usize {
+  let record = &accepted.record;
+  let extra: usize = record.extra.iter().map(String::len).sum();
+  mem::size_of::<Accepted>()
+    + record.ph.len()
+    + record.name.len()
+    + accepted.original_ph.len()
+    + extra
+    + record.extra.len() * mem::size_of::<String>()
+}
+
+/// Sorts the records pushed to it, keeping the input order of the equal
+/// ones.
+pub struct Sorter {
+  keys: Vec<SortKey>,
+  /// The bytes of records held before spilling them to a run.
+  limit: usize,
+  buffer: Vec<Accepted>,
+  size: usize,
+  runs: Vec<PathBuf>,
+  /// The run files created so far, for their names.
+  created: usize,
+}
+
+impl Sorter {
+  pub fn new(keys: Vec<SortKey>, limit: usize) -> Self {
+    Sorter {
+      keys,
+      limit,
+      buffer: Vec::new(),
+      size: 0,
+      runs: Vec::new(),
+      created: 0,
+    }
+  }
+
+  pub fn push(&mut self, accepted: Accepted) -> Result<(), Error> {
+    self.size += size_of(&accepted);
+    self.buffer.push(accepted);
+    if self.size > self.limit {
+      self.spill()?;
+    }
+    Ok(())
+  }
+
+  /// A new run file.
+  fn create_run(&mut self) -> Result<(csv::Writer<File>, PathBuf), Error> {
+    let path = std::env::temp_dir().join(format!(
+      "mobcsv-sort-{}-{}.csv",
+      std::process::id(),
+      self.created
+    ));
+    self.created += 1;
+    let wrt = csv::WriterBuilder::new()
+      .flexible(true)
+      .from_path(&path)
+      .with_context(|_| format!("could not create sort run {:?}", path))?;
+    Ok((wrt, path))
+  }
+
+  /// Writes the sorted records held to a new run file.
+  fn spill(&mut self) -> Result<(), Error> {
+    let keys = &self.keys;
+    self.buffer.sort_by(|a, b| compare(keys, a, b));
+    let (mut wrt, path) = self.create_run()?;
+    debug!(
+      "Spilling {} sorted records to {:?}",
+      self.buffer.len(),
+      path
+    );
+    self.runs.push(path);
+    for accepted in self.buffer.drain(..) {
+      write_run_record(&mut wrt, &accepted)?;
+    }
+    wrt.flush()?;
+    self.size = 0;
+    Ok(())
+  }
+
+  /// Passes the records to `handle`, sorted, merged from the runs if any.
+  pub fn finish<F>(mut self, mut handle: F) -> Result<(), Error>
+  where
+    F: FnMut(Accepted) -> Result<(), Error>,
+  {
+    if self.runs.is_empty() {
+      let keys = &self.keys;
+      self.buffer.sort_by(|a, b| compare(keys, a, b));
+      return self.buffer.into_iter().try_for_each(handle);
+    }
+    self.spill()?;
+    // the runs are merged in several passes, not to open too many files.
+    while self.runs.len() > MAX_RUNS {
+      let runs = self.runs.drain(..MAX_RUNS).collect();
+      let mut merge = Merge::open(self.keys.clone(), runs)?;
+      let (mut wrt, path) = self.create_run()?;
+      self.runs.push(path);
+      while let Some(accepted) = merge.next()? {
+        write_run_record(&mut wrt, &accepted)?;
+      }
+      wrt.flush()?;
+    }
+    let runs = mem::take(&mut self.runs);
+    let mut merge = Merge::open(mem::take(&mut self.keys), runs)?;
+    while let Some(accepted) = merge.next()? {
+      handle(accepted)?;
+    }
+    Ok(())
+  }
+}
+
+/// Writes the `accepted` record to a run file.
+fn write_run_record(
+  wrt: &mut csv::Writer<File>,
+  accepted: &Accepted,
+) -> Result<(), Error> {
+  let record = &accepted.record;
+  let count = record.count.to_string();
+  let flags = match (accepted.fixed, accepted.extracted) {
+    (true, true) => "fe",
+    (true, false) => "f",
+    (false, true) => "e",
+    (false, false) => "",
+  };
+  let fields = [&record.ph, &accepted.original_ph, flags, &record.name];
+  wrt.write_record(
+    fields
+      .iter()
+      .copied()
+      .chain(Some(count.as_str()))
+      .chain(record.extra.iter().map(String::as_str)),
+  )?;
+  Ok(())
+}
+
+/// The merge of the sorted runs, removed when dropped.
+struct Merge {
+  keys: Vec<SortKey>,
+  readers: Vec<csv::Reader<File>>,
+  /// The next record of every run.
+  heads: Vec<Option<Accepted>>,
+  paths: Vec<PathBuf>,
+}
+
+impl Merge {
+  /// Opens the run files at `paths`, reading their first records.
+  fn open(keys: Vec<SortKey>, paths: Vec<PathBuf>) -> Result<Self, Error> {
+    let mut merge = Merge {
+      keys,
+      readers: Vec::new(),
+      heads: Vec::new(),
+      paths,
+    };
+    for path in &merge.paths {
+      let file = File::open(path)
+        .with_context(|_| format!("could not open sort run {:?}", path))?;
+      let rdr = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(file);
+      merge.readers.push(rdr);
+    }
+    for i in 0..merge.readers.len() {
+      let head = merge.read(i)?;
+      merge.heads.push(head);
+    }
+    Ok(merge)
+  }
+
+  /// Reads the next record of the `run`.
+  fn read(&mut self, run: usize) -> Result<Option<Accepted>, Error> {
+    let mut row = csv::StringRecord::new();
+    if !self.readers[run].read_record(&mut row)? {
+      return Ok(None);
+    }
+    let field = |i| row.get(i).unwrap_or_default().to_owned();
+    Ok(Some(Accepted {
+      record: Record {
+        ph: field(0),
+        name: field(3),
+        count: row.get(4).unwrap_or_default().parse()?,
+        extra: row.iter().skip(5).map(str::to_owned).collect(),
+      },
+      original_ph: field(1),
+      fixed: row.get(2).unwrap_or_default().contains('f'),
+      extracted: row.get(2).unwrap_or_default().contains('e'),
+    }))
+  }
+
+  /// The smallest head of the runs, the first run on ties, for the merge to
+  /// be stable.
+  fn next(&mut self) -> Result<Option<Accepted>, Error> {
+    let mut min: Option<usize> = None;
+    for (i, head) in self.heads.iter().enumerate() {
+      if let Some(head) = head {
+        let smaller = match min.and_then(|m| self.heads[m].as_ref()) {
+          Some(current) => compare(&self.keys, head, current) == Ordering::Less,
+          None => true,
+        };
+        if smaller {
+          min = Some(i);
+        }
+      }
+    }
+    match min {
+      Some(i) => {
+        let next = self.read(i)?;
+        Ok(mem::replace(&mut self.heads[i], next))
+      },
+      None => Ok(None),
+    }
+  }
+}
+
+impl Drop for Merge {
+  fn drop(&mut self) {
+    for path in &self.paths {
+      let _ = std::fs::remove_file(path);
+    }
+  }
+}
+
+/// A record ranked by a field, the first one read on ties.
+struct Ranked {
+  accepted: Accepted,
+  /// The index of the record, in the input order.
+  index: u64,
+  by: SortField,
+}
+
+impl Ord for Ranked {
+  fn cmp(&self, other: &Self) -> Ordering {
+    let (a, b) = (&self.accepted.record, &other.accepted.record);
+    self
+      .by
+      .compare(a, b)
+      .then_with(|| other.index.cmp(&self.index))
+  }
+}
+
+impl PartialOrd for Ranked {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl PartialEq for Ranked {
+  fn eq(&self, other: &Self) -> bool {
+    self.cmp(other) == Ordering::Equal
+  }
+}
+
+impl Eq for Ranked {}
+
+/// Keeps the `n` records with the highest values of a field, in a heap of
+/// at most `n` records.
+pub struct Top {
+  n: usize,
+  by: SortField,
+  /// The lowest ranked record on top, dropped first.
+  heap: BinaryHeap<Reverse<Ranked>>,
+  pushed: u64,
+}
+
+impl Top {
+  pub fn new(n: usize, by: SortField) -> Self {
+    Top {
+      n,
+      by,
+      heap: BinaryHeap::with_capacity(n + 1),
+      pushed: 0,
+    }
+  }
+
+  pub fn push(&mut self, accepted: Accepted) {
+    let ranked = Ranked {
+      accepted,
+      index: self.pushed,
+      by: self.by,
+    };
+    self.pushed += 1;
+    self.heap.push(Reverse(ranked));
+    if self.heap.len() > self.n {
+      self.heap.pop();
+    }
+  }
+
+  /// The records kept, the highest first.
+  pub fn finish(self) -> Vec<Accepted> {
+    let sorted = self.heap.into_sorted_vec();
+    sorted.into_iter().map(|ranked| ranked.0.accepted).collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn accepted(ph: &str, name: &str, count: u64) -> Accepted {
+    Accepted {
+      record: Record::new(ph, name, count),
+      original_ph: ph.to_owned(),
+      fixed: false,
+      extracted: false,
+    }
+  }
+
+  fn sort(keys: &str, limit: usize) -> Vec<(String, u64)> {
+    let keys = keys.split(',').map(|k| k.parse().unwrap()).collect();
+    let mut sorter = Sorter::new(keys, limit);
+    let records = [
+      accepted("201116613061", "b", 3),
+      accepted("201116613062", "a", 9),
+      accepted("201116613063", "c", 3),
+      accepted("201116613064", "a", 1),
+    ];
+    for accepted in records {
+      sorter.push(accepted).unwrap();
+    }
+    let mut sorted = Vec::new();
+    sorter
+      .finish(|accepted| {
+        sorted.push((accepted.record.name, accepted.record.count));
+        Ok(())
+      })
+      .unwrap();
+    sorted
+  }
+
+  #[test]
+  fn should_sort_records() {
+    let by_count = vec![
+      ("a".to_owned(), 9),
+      ("b".to_owned(), 3),
+      ("c".to_owned(), 3),
+      ("a".to_owned(), 1),
+    ];
+    assert_eq!(sort("count:desc", usize::MAX), by_count);
+    // a run per record, merged.
+    assert_eq!(sort("count:desc", 0), by_count);
+    assert_eq!(
+      sort("name,count", 0)[..2],
+      [("a".to_owned(), 1), ("a".to_owned(), 9)]
+    );
+    assert!("count:up".parse::<SortKey>().is_err());
+  }
+
+  #[test]
+  fn should_keep_top_records() {
+    let mut top = Top::new(2, SortField::Count);
+    top.push(accepted("201116613061", "a", 3));
+    top.push(accepted("201116613062", "b", 9));
+    top.push(accepted("201116613063", "c", 3));
+    top.push(accepted("201116613064", "d", 1));
+    let names: Vec<_> =
+      top.finish().into_iter().map(|a| a.record.name).collect();
+    assert_eq!(names, ["b", "a"]);
+  }
+}
@@ -0,0 +1,325 @@
+//! The input sources of the records, whatever their file format.
+
+use std::{
+  io::{BufRead, Read},
+  path::Path,
+  str::FromStr,
+};
+
+use calamine::{Data, Range, Reader};
+use failure::{bail, Error, ResultExt};
+use serde_json::{Map, Value};
+
+use crate::compress;
+
+/// The file format of the inputs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputFormat {
+  Csv,
+  /// An Excel (or OpenDocument) workbook.
+  Xlsx,
+  /// A JSON object per line.
+  Jsonl,
+}
+
+impl InputFormat {
+  pub const VARIANTS: &'static [&'static str] = &["csv", "xlsx", "jsonl"];
+
+  /// The format of the file at `path` by its extension, ignoring the
+  /// compression one, CSV by default.
+  pub fn of(path: &Path) -> Self {
+    let ext = compress::strip(path)
+      .extension()
+      .map(|ext| ext.to_string_lossy().to_ascii_lowercase());
+    match ext.as_deref() {
+      Some("xlsx") | Some("xlsm") | Some("xlsb") | Some("xls")
+      | Some("ods") => InputFormat::Xlsx,
+      Some("jsonl") | Some("ndjson") => InputFormat::Jsonl,
+      _ => InputFormat::Csv,
+    }
+  }
+}
+
+impl FromStr for InputFormat {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "csv" => Ok(InputFormat::Csv),
+      "xlsx" => Ok(InputFormat::Xlsx),
+      "jsonl" => Ok(InputFormat::Jsonl),
+      _ => Err(format!("unknown input format `{}`", s)),
+    }
+  }
+}
+
+/// A source of input rows, the first one being the headers row unless it
+/// has none.
+pub trait Source {
+  /// Reads the first row, without consuming it when there are no headers.
+  fn headers(&mut self) -> Result<csv::StringRecord, Error>;
+
+  /// The 1-based line of the next row, for the error messages.
+  fn line(&self) -> u64;
+
+  /// Reads the next row into `row`, returning whether there was one.
+  ///
+  /// The fields are not checked to be UTF-8, only the used ones are when
+  /// reading the record of the row.
+  fn read(&mut self, row: &mut csv::ByteRecord) -> Result<bool, Error>;
+}
+
+/// The rows of a CSV file.
+pub struct CsvSource {
+  rdr: csv::Reader<Box<dyn Read>>,
+}
+
+impl CsvSource {
+  pub fn new(rdr: csv::Reader<Box<dyn Read>>) -> Self {
+    CsvSource { rdr }
+  }
+}
+
+impl Source for CsvSource {
+  fn headers(&mut self) -> Result<csv::StringRecord, Error> {
+    Ok(self.rdr.headers()?.clone())
+  }
+
+  fn line(&self) -> u64 {
+    self.rdr.position().line()
+  }
+
+  fn read(&mut self, row: &mut csv::ByteRecord) -> Result<bool, Error> {
+    Ok(self.rdr.read_byte_record(row)?)
+  }
+}
+
+/// The rows of a sheet of an Excel (or OpenDocument) workbook.
+pub struct SheetSource {
+  range: Range<Data>,
+  /// The next row, relative to the first used row of the sheet.
+  next: usize,
+  has_headers: bool,
+}
+
+impl SheetSource {
+  /// Opens the `sheet` of the workbook at `path`, the first one by default.
+  pub fn open(
+    path: &Path,
+    sheet: Option<&str>,
+    has_headers: bool,
+  ) -> Result<Self, Error> {
+    let mut workbook = calamine::open_workbook_auto(path)
+      .with_context(|_| format!("could not open workbook {:?}", path))?;
+    let names = workbook.sheet_names();
+    let name = match sheet {
+      Some(sheet) if names.iter().any(|n| n == sheet) => sheet.to_owned(),
+      Some(sheet) => bail!(
+        "{:?} has no `{}` sheet, only `{}`",
+        path,
+        sheet,
+        names.join("`, `")
+      ),
+      None => match names.first() {
+        Some(name) => name.clone(),
+        None => bail!("{:?} has no sheets", path),
+      },
+    };
+    let range = workbook
+      .worksheet_range(&name)
+      .with_context(|_| format!("could not read the `{}` sheet", name))?;
+    Ok(SheetSource {
+      range,
+      next: 0,
+      has_headers,
+    })
+  }
+
+  fn row(&self, i: usize) -> csv::StringRecord {
+    (0..self.range.width())
+      .map(|j| {
+        self
+          .range
+          .get((i, j))
+          .map_or(String::new(), Data::to_string)
+      })
+      .collect()
+  }
+}
+
+impl Source for SheetSource {
+  fn headers(&mut self) -> Result<csv::StringRecord, Error> {
+    if self.range.height() == 0 {
+      return Ok(csv::StringRecord::new());
+    }
+    let headers = self.row(0);
+    if self.has_headers && self.next == 0 {
+      self.next = 1;
+    }
+    Ok(headers)
+  }
+
+  fn line(&self) -> u64 {
+    let start = self.range.start().map_or(0, |(row, _)| u64::from(row));
+    start + self.next as u64 + 1
+  }
+
+  fn read(&mut self, row: &mut csv::ByteRecord) -> Result<bool, Error> {
+    if self.next >= self.range.height() {
+      return Ok(false);
+    }
+    *row = self.row(self.next).into_byte_record();
+    self.next += 1;
+    Ok(true)
+  }
+}
+
+/// An object of a JSON Lines file, with its line.
+type Object = (u64, Map<String, Value>);
+
+/// The objects of a JSON Lines file, their columns are the keys of the
+/// first object, in order, the other objects can't have other keys.
+pub struct JsonlSource {
+  lines: Box<dyn BufRead>,
+  keys: Vec<String>,
+  /// The first object, read for its keys.
+  pending: Option<Object>,
+  /// The lines read so far, the blank ones after the last object too.
+  line: u64,
+}
+
+impl JsonlSource {
+  pub fn new(lines: Box<dyn BufRead>) -> Self {
+    JsonlSource {
+      lines,
+      keys: Vec::new(),
+      pending: None,
+      line: 0,
+    }
+  }
+
+  /// Reads the next object with its line, and the blank lines after it.
+  fn next_object(&mut self) -> Result<Option<Object>, Error> {
+    self.skip_blank_lines()?;
+    let mut line = String::new();
+    if self.lines.read_line(&mut line)? == 0 {
+      return Ok(None);
+    }
+    self.line += 1;
+    let object = match serde_json::from_str(&line)? {
+      Value::Object(object) => object,
+      _ => bail!("expected a JSON object"),
+    };
+    let at = self.line;
+    self.skip_blank_lines()?;
+    Ok(Some((at, object)))
+  }
+
+  /// Skips the blank lines, for the line of the next object to be known.
+  fn skip_blank_lines(&mut self) -> Result<(), Error> {
+    loop {
+      let buf = self.lines.fill_buf()?;
+      match buf.iter().position(|b| !b" \t\r".contains(b)) {
+        Some(i) if buf[i] == b'\n' => {
+          self.lines.consume(i + 1);
+          self.line += 1;
+        },
+        _ => return Ok(()),
+      }
+    }
+  }
+}
+
+impl Source for JsonlSource {
+  fn headers(&mut self) -> Result<csv::StringRecord, Error> {
+    if self.pending.is_none() && self.keys.is_empty() {
+      self.pending = self.next_object()?;
+      if let Some((_, object)) = &self.pending {
+        self.keys = object.keys().cloned().collect();
+      }
+    }
+    Ok(self.keys.iter().collect())
+  }
+
+  fn line(&self) -> u64 {
+    self
+      .pending
+      .as_ref()
+      .map_or(self.line + 1, |(line, _)| *line)
+  }
+
+  fn read(&mut self, row: &mut csv::ByteRecord) -> Result<bool, Error> {
+    let (_, object) = match self.pending.take() {
+      Some(pending) => pending,
+      None => match self.next_object()? {
+        Some(pending) => pending,
+        None => return Ok(false),
+      },
+    };
+    if let Some(key) = object.keys().find(|key| !self.keys.contains(key)) {
+      bail!("unexpected key `{}`, not one of the first object", key);
+    }
+    row.clear();
+    for key in &self.keys {
+      match object.get(key) {
+        Some(Value::String(s)) => row.push_field(s.as_bytes()),
+        Some(Value::Null) | None => row.push_field(b""),
+        Some(value) => row.push_field(value.to_string().as_bytes()),
+      }
+    }
+    Ok(true)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn should_read_csv_rows() {
+    let data: Box<dyn Read> = Box::new(&b"ph,name\n0111,a\n"[..]);
+    let mut source = CsvSource::new(csv::Reader::from_reader(data));
+    assert_eq!(source.headers().unwrap(), vec!["ph", "name"]);
+    let mut row = csv::ByteRecord::new();
+    assert_eq!(source.line(), 2);
+    assert!(source.read(&mut row).unwrap());
+    assert_eq!(row, vec!["0111", "a"]);
+    assert!(!source.read(&mut row).unwrap());
+  }
+
+  #[test]
+  fn should_read_jsonl_objects() {
+    let data =
+      &b"{\"ph\":\"0111\",\"count\":2,\"tag\":null}\n\n{\"count\":3}\n"[..];
+    let mut source = JsonlSource::new(Box::new(data));
+    assert_eq!(source.headers().unwrap(), vec!["ph", "count", "tag"]);
+    let mut row = csv::ByteRecord::new();
+    assert_eq!(source.line(), 1);
+    assert!(source.read(&mut row).unwrap());
+    assert_eq!(row, vec!["0111", "2", ""]);
+    assert_eq!(source.line(), 3);
+    assert!(source.read(&mut row).unwrap());
+    assert_eq!(row, vec!["", "3", ""]);
+    assert!(!source.read(&mut row).unwrap());
+    let mut source = JsonlSource::new(Box::new(&b"[1]\n"[..]));
+    assert!(source.headers().is_err());
+    let data = &b"\n{\"ph\":\"0111\"}\n \n{\"ph\":\"0112\",\"x\":1}\n"[..];
+    let mut source = JsonlSource::new(Box::new(data));
+    assert_eq!(source.headers().unwrap(), vec!["ph"]);
+    assert_eq!(source.line(), 2);
+    assert!(source.read(&mut row).unwrap());
+    assert_eq!(source.line(), 4);
+    assert!(source.read(&mut row).is_err());
+  }
+
+  #[test]
+  fn should_detect_input_formats() {
+    assert_eq!(InputFormat::of(Path::new("a.XLSX")), InputFormat::Xlsx);
+    assert_eq!(InputFormat::of(Path::new("a.ods")), InputFormat::Xlsx);
+    assert_eq!(InputFormat::of(Path::new("a.ndjson")), InputFormat::Jsonl);
+    assert_eq!(InputFormat::of(Path::new("a.csv")), InputFormat::Csv);
+    assert_eq!(InputFormat::of(Path::new("-")), InputFormat::Csv);
+    let path = Path::new("a.jsonl.zst");
+    assert_eq!(InputFormat::of(path), InputFormat::Jsonl);
+  }
+}
@@ -0,0 +1,270 @@
+//! The outputs split into several files by a value of the records, like
+//! their country, or into parts of a fixed size.
+
+use std::{
+  collections::BTreeMap,
+  path::{Path, PathBuf},
+  str::FromStr,
+};
+
+use failure::{bail, Error};
+use log::info;
+
+use crate::{
+  countries, operators,
+  pipeline::{self, Writer},
+  Config,
+};
+
+/// The key of the numbers of unknown countries or operators.
+const UNKNOWN: &str = "unknown";
+
+/// The value the outputs are split by.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SplitBy {
+  /// The ISO code of the country, like `eg`.
+  Country,
+  /// The mobile operator, like `vodafone`.
+  Operator,
+}
+
+impl SplitBy {
+  pub const VARIANTS: &'static [&'static str] = &["country", "operator"];
+
+  /// The placeholder of the value in the output path template.
+  pub fn placeholder(self) -> &'static str {
+    match self {
+      SplitBy::Country => "{country}",
+      SplitBy::Operator => "{operator}",
+    }
+  }
+
+  /// The value of the normalized number `ph`, lowercase.
+  pub fn key(self, ph: &str) -> String {
+    match self {
+      SplitBy::Country => countries::of(ph)
+        .map_or(UNKNOWN, |country| &country.iso)
+        .to_lowercase(),
+      SplitBy::Operator => operators::find(ph)
+        .map_or(UNKNOWN, |operator| operator.name)
+        .to_lowercase(),
+    }
+  }
+}
+
+impl FromStr for SplitBy {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "country" => Ok(SplitBy::Country),
+      "operator" => Ok(SplitBy::Operator),
+      _ => Err(format!("unknown split value `{}`", s)),
+    }
+  }
+}
+
+/// The output files of every value, created as the values are found.
+pub struct Splitter {
+  by: SplitBy,
+  template: String,
+  headers: Vec<String>,
+  writers: BTreeMap<String, Writer>,
+}
+
+impl Splitter {
+  /// Splits by the `by` value into the files of the path `template`, like
+  /// `clean-{country}.csv`, each one starting with the `headers` row.
+  pub fn new(
+    by: SplitBy,
+    template: &Path,
+    headers: &[&str],
+  ) -> Result<Self, Error> {
+    let template = template.to_string_lossy().into_owned();
+    if !template.contains(by.placeholder()) {
+      bail!(
+        "the output path {:?} has no `{}` placeholder for --split-by",
+        template,
+        by.placeholder()
+      );
+    }
+    Ok(Splitter {
+      by,
+      template,
+      headers: headers.iter().map(|&h| h.to_owned()).collect(),
+      writers: BTreeMap::new(),
+    })
+  }
+
+  /// The path of the output of the `key` value.
+  pub fn path(&self, key: &str) -> PathBuf {
+    self.template.replace(self.by.placeholder(), key).into()
+  }
+
+  /// The writer of the normalized number `ph`, creating it if needed.
+  pub fn writer(
+    &mut self,
+    config: &Config,
+    ph: &str,
+  ) -> Result<&mut Writer, Error> {
+    let key = self.by.key(ph);
+    if !self.writers.contains_key(&key) {
+      let path = self.path(&key);
+      info!("Writing the `{}` records to {:?}", key, path);
+      let headers: Vec<_> = self.headers.iter().map(String::as_str).collect();
+      let wrt = pipeline::create(&path, config, &headers)?;
+      self.writers.insert(key.clone(), wrt);
+    }
+    Ok(self.writers.get_mut(&key).unwrap())
+  }
+
+  /// Finishes all the outputs.
+  pub fn finish(&mut self) -> Result<(), Error> {
+    for wrt in self.writers.values_mut() {
+      wrt.finish()?;
+    }
+    Ok(())
+  }
+}
+
+/// The placeholder of the part number in the output path template.
+const PART: &str = "{n}";
+
+/// The output files of at most `size` records each, numbered from 1.
+pub struct Chunker {
+  template: String,
+  headers: Vec<String>,
+  size: u64,
+  part: u64,
+  /// The records written to the current part.
+  records: u64,
+  current: Option<Writer>,
+}
+
+impl Chunker {
+  /// Rolls over to a new file of the path `template`, like `batch-{n}.csv`,
+  /// every `size` records, each one starting with the `headers` row.
+  pub fn new(
+    size: u64,
+    template: &Path,
+    headers: &[&str],
+  ) -> Result<Self, Error> {
+    let template = template.to_string_lossy().into_owned();
+    if !template.contains(PART) {
+      bail!(
+        "the output path {:?} has no `{}` placeholder for --chunk-size",
+        template,
+        PART
+      );
+    }
+    if size == 0 {
+      bail!("--chunk-size must be at least 1");
+    }
+    Ok(Chunker {
+      template,
+      headers: headers.iter().map(|&h| h.to_owned()).collect(),
+      size,
+      part: 0,
+      records: 0,
+      current: None,
+    })
+  }
+
+  /// The path of the `part` file.
+  pub fn path(&self, part: u64) -> PathBuf {
+    self.template.replace(PART, &part.to_string()).into()
+  }
+
+  /// The writer of the next record, in a new part if the current one is
+  /// full.
+  pub fn writer(&mut self, config: &Config) -> Result<&mut Writer, Error> {
+    if self.current.is_none() || self.records == self.size {
+      self.finish()?;
+      self.part += 1;
+      self.records = 0;
+      let path = self.path(self.part);
+      info!("Writing the part {} to {:?}", self.part, path);
+      let headers: Vec<_> = self.headers.iter().map(String::as_str).collect();
+      self.current = Some(pipeline::create(&path, config, &headers)?);
+    }
+    self.records += 1;
+    Ok(self.current.as_mut().unwrap())
+  }
+
+  /// Finishes the current part, if any.
+  pub fn finish(&mut self) -> Result<(), Error> {
+    match self.current.take() {
+      Some(mut wrt) => wrt.finish(),
+      None => Ok(()),
+    }
+  }
+}
+
+/// Where the accepted records are written.
+pub enum Target {
+  File(Writer),
+  Split(Splitter),
+  Chunks(Chunker),
+}
+
+impl Target {
+  /// The writer of the normalized number `ph`.
+  pub fn writer(
+    &mut self,
+    config: &Config,
+    ph: &str,
+  ) -> Result<&mut Writer, Error> {
+    match self {
+      Target::File(wrt) => Ok(wrt),
+      Target::Split(splitter) => splitter.writer(config, ph),
+      Target::Chunks(chunker) => chunker.writer(config),
+    }
+  }
+
+  pub fn finish(&mut self) -> Result<(), Error> {
+    match self {
+      Target::File(wrt) => wrt.finish(),
+      Target::Split(splitter) => splitter.finish(),
+      Target::Chunks(chunker) => chunker.finish(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn should_split_by_country_and_operator() {
+    assert_eq!(SplitBy::Country.key("201116613061"), "eg");
+    assert_eq!(SplitBy::Country.key("966540029129"), "sa");
+    assert_eq!(SplitBy::Operator.key("201016613061"), "vodafone");
+    assert_eq!(SplitBy::Operator.key("966500029129"), "stc");
+    assert_eq!("operator".parse(), Ok(SplitBy::Operator));
+    let splitter =
+      Splitter::new(SplitBy::Country, Path::new("clean-{country}.csv"), &[])
+        .unwrap();
+    assert_eq!(splitter.path("eg"), Path::new("clean-eg.csv"));
+    assert!(
+      Splitter::new(SplitBy::Country, Path::new("clean.csv"), &[]).is_err()
+    );
+  }
+
+  #[test]
+  fn should_roll_over_chunks() {
+    let template = std::env::temp_dir().join("mobcsv-batch-{n}.csv");
+    let config = Config {
+      overwrite: true,
+      ..Config::default()
+    };
+    let mut chunker = Chunker::new(2, &template, &["ph"]).unwrap();
+    for ph in &["201116613061", "201116613062", "201116613063"] {
+      chunker.writer(&config).unwrap().write_row(&[ph]).unwrap();
+    }
+    chunker.finish().unwrap();
+    let part = |n| std::fs::read_to_string(chunker.path(n)).unwrap();
+    assert_eq!(part(1), "ph\n201116613061\n201116613062\n");
+    assert_eq!(part(2), "ph\n201116613063\n");
+    assert!(Chunker::new(0, &template, &[]).is_err());
+  }
+}
@@ -0,0 +1,84 @@
+//! The database of the phone numbers written by the previous runs, for the
+//! lists to be built incrementally.
+
+use std::{collections::HashSet, path::Path};
+
+use failure::{Error, ResultExt};
+use log::info;
+
+/// The numbers written by the previous runs, and the ones of this run,
+/// added once it is complete.
+pub struct StateDb {
+  /// None for a dry run without a database yet.
+  db: Option<sled::Db>,
+  batch: sled::Batch,
+  /// The numbers of this run, counted once even when repeated.
+  added: HashSet<String>,
+}
+
+impl StateDb {
+  /// Opens the database at `path`, creating it unless it is a `dry_run`.
+  pub fn open(path: &Path, dry_run: bool) -> Result<Self, Error> {
+    let db = if dry_run && !path.exists() {
+      None
+    } else {
+      info!("Opening the state database {:?}", path);
+      let db = sled::open(path).with_context(|_| {
+        format!("could not open state database {:?}", path)
+      })?;
+      Some(db)
+    };
+    Ok(StateDb {
+      db,
+      batch: sled::Batch::default(),
+      added: HashSet::new(),
+    })
+  }
+
+  /// Whether the normalized number `ph` was written by a previous run, the
+  /// ones of this run are only added once it is complete.
+  pub fn contains(&self, ph: &str) -> Result<bool, Error> {
+    match &self.db {
+      Some(db) => Ok(db.contains_key(ph)?),
+      None => Ok(false),
+    }
+  }
+
+  /// Remembers the normalized number `ph`, written by this run.
+  pub fn add(&mut self, ph: &str) {
+    if self.added.insert(ph.to_owned()) {
+      self.batch.insert(ph.as_bytes(), &[]);
+    }
+  }
+
+  /// Adds the numbers of this run at once, when its outputs are complete,
+  /// returning how many were added.
+  pub fn commit(self) -> Result<u64, Error> {
+    if let Some(db) = self.db {
+      db.apply_batch(self.batch)?;
+      db.flush()?;
+    }
+    Ok(self.added.len() as u64)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn should_remember_committed_numbers() {
+    let path = std::env::temp_dir().join("mobcsv-state.sled");
+    let _ = std::fs::remove_dir_all(&path);
+    let mut state = StateDb::open(&path, false).unwrap();
+    state.add("201116613061");
+    state.add("201116613061");
+    assert!(!state.contains("201116613061").unwrap());
+    assert_eq!(state.commit().unwrap(), 1);
+    let mut state = StateDb::open(&path, false).unwrap();
+    assert!(state.contains("201116613061").unwrap());
+    assert!(!state.contains("966540029129").unwrap());
+    state.add("966540029129");
+    assert!(!state.contains("966540029129").unwrap());
+  }
+}
@@ -0,0 +1,501 @@
+//! The statistics of a run, accumulated while processing the records.
+
+use std::{
+  cmp::Reverse,
+  collections::{BTreeMap, HashSet},
+  fmt,
+  fs::File,
+  io::BufWriter,
+  path::Path,
+};
+
+use console::Style;
+use failure::{Error, ResultExt};
+use serde::{Serialize, Serializer};
+
+use crate::{
+  countries,
+  dedupe::{Bloom, Seen},
+  dups::Dups,
+  operators,
+  reject::{self, RejectReason},
+  report::Details,
+  Accepted, Rejected,
+};
+
+/// The key of the numbers of unknown countries or operators.
+const UNKNOWN: &str = "unknown";
+
+/// A range of the `count` column values, by their powers of ten.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Bucket(u64);
+
+impl Bucket {
+  pub fn of(count: u64) -> Self {
+    match count {
+      0 | 1 => Bucket(count),
+      2..=9 => Bucket(2),
+      _ => Bucket(10u64.pow(count.ilog10().min(4))),
+    }
+  }
+}
+
+impl fmt::Display for Bucket {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self.0 {
+      0 | 1 => write!(f, "{}", self.0),
+      2 => f.write_str("2-9"),
+      10000 => f.write_str("10000+"),
+      low => write!(f, "{}-{}", low, low * 10 - 1),
+    }
+  }
+}
+
+impl Serialize for Bucket {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(self)
+  }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Stats {
+  /// The records read, accepted and rejected.
+  pub total: u64,
+  pub accepted: u64,
+  pub rejected: BTreeMap<RejectReason, u64>,
+  /// The accepted records per country ISO code.
+  pub countries: BTreeMap<String, u64>,
+  /// The accepted records per mobile operator.
+  pub operators: BTreeMap<String, u64>,
+  /// The histogram of the `count` column of the accepted records.
+  pub counts: BTreeMap<Bucket, u64>,
+  /// The input cells of several phone numbers, split into a record each.
+  pub split_cells: u64,
+  /// The accepted records with an already seen phone number, when they are
+  /// counted.
+  pub duplicates: u64,
+  /// The accepted records skipped as their phone number was already in the
+  /// output, or written by a previous run.
+  pub previously_seen: u64,
+  /// The phone numbers seen so far, only kept to count the duplicates.
+  #[serde(skip)]
+  seen: Option<Seen>,
+  /// The records kept for the `--report`, if any.
+  #[serde(skip)]
+  pub details: Option<Details>,
+  /// The rows kept for the `--dup-report` and `--name-conflicts`, if any.
+  #[serde(skip)]
+  pub dups: Option<Dups>,
+}
+
+impl Stats {
+  /// No statistics yet, keeping the `details` of the records and the rows
+  /// of their `dups` if needed, both counting the duplicates.
+  pub fn new(details: bool, dups: bool) -> Self {
+    Stats {
+      seen: Some(Seen::Exact(HashSet::new())).filter(|_| details || dups),
+      details: Some(Details::default()).filter(|_| details),
+      dups: Some(Dups::default()).filter(|_| dups),
+      ..Stats::default()
+    }
+  }
+
+  /// Counts the duplicates too, keeping every phone number seen.
+  pub fn count_duplicates(self) -> Self {
+    Stats {
+      seen: Some(Seen::Exact(HashSet::new())),
+      ..self
+    }
+  }
+
+  /// Counts the duplicates with a bloom filter sized for `items` phone
+  /// numbers, like `--dedupe-bloom`, missing about 1% of the unique ones.
+  pub fn count_duplicates_with_bloom(self, items: usize) -> Self {
+    Stats {
+      seen: Some(Seen::Bloom(Bloom::new(items))),
+      ..self
+    }
+  }
+
+  pub fn counts_duplicates(&self) -> bool {
+    self.seen.is_some()
+  }
+
+  pub fn accept(&mut self, accepted: &Accepted) {
+    let ph = &accepted.record.ph;
+    self.total += 1;
+    self.accepted += 1;
+    let country = countries::of(ph).map_or(UNKNOWN, |c| &c.iso);
+    *self.countries.entry(country.to_owned()).or_insert(0) += 1;
+    let operator = operators::find(ph).map_or(UNKNOWN, |o| o.name);
+    *self.operators.entry(operator.to_owned()).or_insert(0) += 1;
+    *self
+      .counts
+      .entry(Bucket::of(accepted.record.count))
+      .or_insert(0) += 1;
+    let seen = self.seen.as_mut().map(|seen| seen.insert(ph));
+    if seen == Some(false) {
+      self.duplicates += 1;
+      if let Some(details) = &mut self.details {
+        details.repeat(ph);
+      }
+    }
+  }
+
+  /// Accepts the record found at the `line` of the input at `path`.
+  pub fn accept_record(&mut self, accepted: &Accepted, path: &Path, line: u64) {
+    self.accept(accepted);
+    if let Some(dups) = &mut self.dups {
+      dups.push(accepted, path, line);
+    }
+  }
+
+  pub fn reject(&mut self, reason: RejectReason) {
+    self.total += 1;
+    *self.rejected.entry(reason).or_insert(0) += 1;
+  }
+
+  pub fn reject_record(&mut self, rejected: &Rejected) {
+    self.reject(rejected.reason);
+    if let Some(details) = &mut self.details {
+      details.reject(rejected);
+    }
+  }
+
+  /// Adds the `other` statistics to these ones.
+  pub fn merge(&mut self, other: Stats) {
+    fn add<K: Ord>(to: &mut BTreeMap<K, u64>, from: BTreeMap<K, u64>) {
+      for (key, n) in from {
+        *to.entry(key).or_insert(0) += n;
+      }
+    }
+    self.total += other.total;
+    self.accepted += other.accepted;
+    add(&mut self.rejected, other.rejected);
+    add(&mut self.countries, other.countries);
+    add(&mut self.operators, other.operators);
+    add(&mut self.counts, other.counts);
+    self.split_cells += other.split_cells;
+    self.duplicates += other.duplicates;
+    self.previously_seen += other.previously_seen;
+    // the numbers seen are only kept while the records are read.
+    match (&mut self.details, other.details) {
+      (Some(details), Some(other)) => details.merge(other),
+      (None, details) => self.details = details,
+      _ => {},
+    }
+    match (&mut self.dups, other.dups) {
+      (Some(dups), Some(other)) => dups.merge(other),
+      (None, dups) => self.dups = dups,
+      _ => {},
+    }
+  }
+
+  /// The count of all the rejected records.
+  pub fn rejected(&self) -> u64 {
+    self.rejected.values().sum()
+  }
+
+  /// The percentage of the rejected records.
+  pub fn reject_rate(&self) -> f64 {
+    reject::rate(self.rejected(), self.total)
+  }
+
+  /// The detailed report of the `stats` subcommand.
+  pub fn report(&self) -> Report<'_> {
+    Report(self)
+  }
+
+  /// The summary of a run as a table, for a terminal, with ANSI colors if
+  /// `color`.
+  pub fn table(&self, color: bool) -> Table<'_> {
+    Table { stats: self, color }
+  }
+
+  /// Writes these statistics as a JSON object to `path`.
+  pub fn write_json(&self, path: &Path) -> Result<(), Error> {
+    let out = File::create(path)
+      .with_context(|_| format!("could not create summary file {:?}", path))?;
+    serde_json::to_writer_pretty(BufWriter::new(out), self)?;
+    Ok(())
+  }
+}
+
+/// Joins the `counts` as `key: count` pairs.
+fn join<K: fmt::Display>(counts: &BTreeMap<K, u64>) -> String {
+  let pairs: Vec<_> = counts
+    .iter()
+    .map(|(key, n)| format!("{}: {}", key, n))
+    .collect();
+  pairs.join(", ")
+}
+
+impl fmt::Display for Stats {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "Read {} records", self.total)?;
+    if self.split_cells > 0 {
+      write!(
+        f,
+        ", splitting {} cells of several numbers",
+        self.split_cells
+      )?;
+    }
+    writeln!(f)?;
+    write!(f, "Accepted {} records", self.accepted)?;
+    if !self.countries.is_empty() {
+      write!(f, " ({})", join(&self.countries))?;
+    }
+    writeln!(f)?;
+    write!(f, "Rejected {} records", self.rejected())?;
+    if !self.rejected.is_empty() {
+      write!(f, " ({})", join(&self.rejected))?;
+    }
+    if self.counts_duplicates() {
+      writeln!(f)?;
+      write!(f, "Found {} duplicates", self.duplicates)?;
+      if self.previously_seen > 0 {
+        write!(f, ", skipped {} previously seen", self.previously_seen)?;
+      }
+    } else if self.previously_seen > 0 {
+      writeln!(f)?;
+      write!(f, "Skipped {} previously seen", self.previously_seen)?;
+    }
+    Ok(())
+  }
+}
+
+/// Displays the statistics with every breakdown, one value per line.
+pub struct Report<'a>(&'a Stats);
+
+/// Writes the `counts` under a `title`, one indented pair per line.
+fn section<K: fmt::Display>(
+  f: &mut fmt::Formatter,
+  title: &str,
+  counts: &BTreeMap<K, u64>,
+) -> fmt::Result {
+  writeln!(f, "{}", title)?;
+  for (key, n) in counts {
+    writeln!(f, "  {}: {}", key, n)?;
+  }
+  Ok(())
+}
+
+impl fmt::Display for Report<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let stats = self.0;
+    writeln!(f, "Read {} records", stats.total)?;
+    writeln!(f, "Accepted {} records", stats.accepted)?;
+    section(
+      f,
+      &format!(
+        "Rejected {} records ({:.2}%)",
+        stats.rejected(),
+        stats.reject_rate()
+      ),
+      &stats.rejected,
+    )?;
+    section(f, "Countries", &stats.countries)?;
+    section(f, "Operators", &stats.operators)?;
+    section(f, "Counts", &stats.counts)?;
+    writeln!(f, "Duplicates: {}", stats.duplicates)
+  }
+}
+
+/// The reject reasons of the summary table, the most frequent ones first.
+const TOP_REASONS: usize = 5;
+
+/// Displays the summary of a run as a table.
+pub struct Table<'a> {
+  stats: &'a Stats,
+  color: bool,
+}
+
+impl Table<'_> {
+  /// Writes the row of `n` records of a `label`, with their percentage of
+  /// the records read.
+  fn row(
+    &self,
+    f: &mut fmt::Formatter,
+    label: &dyn fmt::Display,
+    n: u64,
+    style: Style,
+  ) -> fmt::Result {
+    writeln!(
+      f,
+      "  {:<20}{} {:>7.2}%",
+      label.to_string(),
+      style
+        .force_styling(self.color)
+        .apply_to(format!("{:>10}", n)),
+      reject::rate(n, self.stats.total)
+    )
+  }
+
+  fn title(&self, f: &mut fmt::Formatter, title: &str) -> fmt::Result {
+    let style = Style::new().bold().force_styling(self.color);
+    writeln!(f, "{}", style.apply_to(title))
+  }
+}
+
+impl fmt::Display for Table<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let stats = self.stats;
+    self.title(f, "Records")?;
+    self.row(f, &"Read", stats.total, Style::new())?;
+    if stats.split_cells > 0 {
+      let split = stats.split_cells;
+      self.row(f, &"Split cells", split, Style::new().yellow())?;
+    }
+    self.row(f, &"Accepted", stats.accepted, Style::new().green())?;
+    self.row(f, &"Rejected", stats.rejected(), Style::new().red())?;
+    if stats.counts_duplicates() {
+      let duplicates = stats.duplicates;
+      self.row(f, &"Duplicates", duplicates, Style::new().yellow())?;
+    }
+    if stats.previously_seen > 0 {
+      let seen = stats.previously_seen;
+      self.row(f, &"Previously seen", seen, Style::new().yellow())?;
+    }
+    if !stats.countries.is_empty() {
+      self.title(f, "Countries")?;
+      for (iso, &n) in &stats.countries {
+        self.row(f, iso, n, Style::new().green())?;
+      }
+    }
+    if !stats.rejected.is_empty() {
+      self.title(f, "Top reject reasons")?;
+      let mut reasons: Vec<_> = stats.rejected.iter().collect();
+      reasons.sort_by_key(|&(_, &n)| Reverse(n));
+      for (reason, &n) in reasons.into_iter().take(TOP_REASONS) {
+        self.row(f, reason, n, Style::new().red())?;
+      }
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::Record;
+
+  fn accepted(ph: &str) -> Accepted {
+    Accepted {
+      record: Record::new(ph, "test1", 1),
+      original_ph: ph.to_owned(),
+      fixed: false,
+      extracted: false,
+    }
+  }
+
+  #[test]
+  fn should_accumulate_stats() {
+    let mut stats = Stats::default().count_duplicates();
+    stats.accept(&accepted("201116613061"));
+    stats.accept(&accepted("201116613061"));
+    stats.accept(&accepted("966540029129"));
+    stats.reject(RejectReason::TooShort);
+    stats.reject(RejectReason::UnknownCountry);
+    stats.reject(RejectReason::TooShort);
+    assert_eq!(stats.total, 6);
+    assert_eq!(stats.accepted, 3);
+    assert_eq!(stats.rejected(), 3);
+    assert_eq!(stats.rejected[&RejectReason::TooShort], 2);
+    assert_eq!(stats.countries["EG"], 2);
+    assert_eq!(stats.countries["SA"], 1);
+    assert_eq!(stats.duplicates, 1);
+    assert_eq!(
+      stats.to_string(),
+      "Read 6 records\n\
+       Accepted 3 records (EG: 2, SA: 1)\n\
+       Rejected 3 records (TooShort: 2, UnknownCountry: 1)\n\
+       Found 1 duplicates"
+    );
+    let table = stats.table(false).to_string();
+    assert!(table.contains("  Accepted                     3   50.00%\n"));
+    assert!(table.ends_with(
+      "  TooShort                     2   33.33%\n  \
+       UnknownCountry               1   16.67%\n"
+    ));
+    let mut stats = Stats::default();
+    stats.accept(&accepted("201116613061"));
+    stats.accept(&accepted("201116613061"));
+    assert_eq!(stats.duplicates, 0);
+    assert!(!stats.to_string().contains("duplicates"));
+    assert!(!stats.table(false).to_string().contains("Duplicates"));
+    let mut stats = Stats::default().count_duplicates_with_bloom(10);
+    stats.accept(&accepted("201116613061"));
+    stats.accept(&accepted("201116613061"));
+    assert_eq!(stats.duplicates, 1);
+  }
+
+  #[test]
+  fn should_serialize_stats() {
+    let mut stats = Stats::default();
+    stats.accept(&accepted("201116613061"));
+    stats.reject(RejectReason::TooShort);
+    let json = serde_json::to_value(&stats).unwrap();
+    assert_eq!(json["total"], 2);
+    assert_eq!(json["rejected"]["TooShort"], 1);
+    assert_eq!(json["countries"]["EG"], 1);
+    assert!(json.get("seen").is_none());
+  }
+
+  #[test]
+  fn should_merge_stats() {
+    let mut stats = Stats::default().count_duplicates();
+    stats.accept(&accepted("201116613061"));
+    let mut other = Stats::default().count_duplicates();
+    other.accept(&accepted("201116613061"));
+    other.accept(&accepted("201116613061"));
+    other.reject(RejectReason::TooShort);
+    stats.merge(other);
+    assert_eq!(stats.total, 4);
+    assert_eq!(stats.accepted, 3);
+    assert_eq!(stats.rejected(), 1);
+    assert_eq!(stats.countries["EG"], 3);
+    assert_eq!(stats.duplicates, 1);
+    stats.previously_seen = 2;
+    assert!(stats.to_string().ends_with(", skipped 2 previously seen"));
+  }
+
+  #[test]
+  fn should_bucket_counts() {
+    let labels: Vec<_> = [0, 1, 5, 10, 99, 150, 9999, 65535]
+      .iter()
+      .map(|&n| Bucket::of(n).to_string())
+      .collect();
+    assert_eq!(
+      labels,
+      [
+        "0",
+        "1",
+        "2-9",
+        "10-99",
+        "10-99",
+        "100-999",
+        "1000-9999",
+        "10000+"
+      ]
+    );
+  }
+
+  #[test]
+  fn should_report_stats() {
+    let mut stats = Stats::default();
+    stats.accept(&accepted("201016613061"));
+    stats.accept(&accepted("971501234567"));
+    stats.reject(RejectReason::TooShort);
+    stats.reject(RejectReason::TooShort);
+    assert_eq!(
+      stats.report().to_string(),
+      "Read 4 records\n\
+       Accepted 2 records\n\
+       Rejected 2 records (50.00%)\n  TooShort: 2\n\
+       Countries\n  AE: 1\n  EG: 1\n\
+       Operators\n  Vodafone: 1\n  unknown: 1\n\
+       Counts\n  1: 2\n\
+       Duplicates: 0\n"
+    );
+  }
+}
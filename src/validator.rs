@@ -0,0 +1,214 @@
+//! The engines deciding whether a cleaned phone number is an acceptable one.
+
+use std::{fmt, str::FromStr};
+
+use phonenumber::{country::Id, Mode};
+use regex::Regex;
+
+use crate::{
+  countries::{self, Country},
+  reject::RejectReason,
+  rules,
+};
+
+/// Validates a cleaned phone number, that is a number with all the spacial
+/// characters removed.
+pub trait PhoneValidator: fmt::Debug + Send + Sync {
+  /// Returns the number in the international format, digits only, or why it
+  /// is not an acceptable number.
+  fn normalize(&self, ph: &str) -> Result<String, RejectReason>;
+}
+
+/// The available validation engines.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Engine {
+  /// Our own fast rule table, see [`RegexValidator`].
+  Regex,
+  /// The strict `phonenumber` crate, see [`LibPhoneNumberValidator`].
+  LibPhoneNumber,
+}
+
+impl Engine {
+  pub const VARIANTS: &'static [&'static str] = &["regex", "libphonenumber"];
+
+  /// The validator of this engine, the `mob_regex` is only used by the
+  /// regex engine.
+  pub fn validator(
+    self,
+    default_country: Option<&'static Country>,
+    mob_regex: Option<Regex>,
+  ) -> Box<dyn PhoneValidator> {
+    match self {
+      Engine::Regex => Box::new(RegexValidator {
+        default_country,
+        mob_regex,
+      }),
+      Engine::LibPhoneNumber => {
+        Box::new(LibPhoneNumberValidator::new(default_country))
+      },
+    }
+  }
+}
+
+impl FromStr for Engine {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "regex" => Ok(Engine::Regex),
+      "libphonenumber" => Ok(Engine::LibPhoneNumber),
+      _ => Err(format!("unknown engine `{}`", s)),
+    }
+  }
+}
+
+/// Validates numbers against our country rule table and the prefix length
+/// rules, guessing the country of national numbers.
+#[derive(Debug, Default)]
+pub struct RegexValidator {
+  /// The country of national numbers written with a trunk prefix.
+  pub default_country: Option<&'static Country>,
+  /// A user provided pattern replacing the country and prefix rules.
+  pub mob_regex: Option<Regex>,
+}
+
+impl RegexValidator {
+  pub fn standardize(&self, mut ph: String) -> String {
+    if ph.starts_with("00") {
+      // already in the international format.
+      ph.replace_range(..2, "");
+      return ph;
+    }
+    if let Some(country) = self.default_country {
+      if let Some(trunk) = &country.trunk_prefix {
+        if ph.starts_with(trunk.as_str()) {
+          // a national number of the default country, swap the trunk prefix
+          // with the country dialing code.
+          ph.replace_range(..trunk.len(), &country.dialing_code);
+          return ph;
+        }
+      }
+    }
+    if ph.starts_with('0') {
+      // a trunk prefix of an unknown country, drop it and guess the country.
+      ph.remove(0);
+    }
+    // a bare national number, so we need to add its country dialing code.
+    if let Some(country) = countries::find_local(&ph) {
+      ph.insert_str(0, &country.dialing_code);
+    }
+    ph
+  }
+}
+
+impl PhoneValidator for RegexValidator {
+  fn normalize(&self, ph: &str) -> Result<String, RejectReason> {
+    let ph = self.standardize(ph.to_owned());
+    if let Some(re) = &self.mob_regex {
+      return if re.is_match(&ph) {
+        Ok(ph)
+      } else {
+        Err(RejectReason::NoRegexMatch)
+      };
+    }
+    let country = countries::check(&ph)?;
+    rules::get().check(&ph, country)?;
+    Ok(ph)
+  }
+}
+
+/// Validates numbers using the `phonenumber` crate, a port of Google's
+/// libphonenumber, which knows the numbering plan of every country.
+#[derive(Debug, Default)]
+pub struct LibPhoneNumberValidator {
+  default_country: Option<Id>,
+}
+
+impl LibPhoneNumberValidator {
+  pub fn new(default_country: Option<&Country>) -> Self {
+    LibPhoneNumberValidator {
+      default_country: default_country.and_then(|c| c.iso.parse().ok()),
+    }
+  }
+
+  fn parse(&self, ph: &str) -> Option<phonenumber::PhoneNumber> {
+    let national = self
+      .default_country
+      .and_then(|id| phonenumber::parse(Some(id), ph).ok())
+      .filter(phonenumber::is_valid);
+    // the `+` is already removed while cleaning, so we try it again as an
+    // international number.
+    national.or_else(|| {
+      let international = format!("+{}", ph.trim_start_matches("00"));
+      phonenumber::parse(None, international)
+        .ok()
+        .filter(phonenumber::is_valid)
+    })
+  }
+}
+
+impl PhoneValidator for LibPhoneNumberValidator {
+  fn normalize(&self, ph: &str) -> Result<String, RejectReason> {
+    let number = self.parse(ph).ok_or(RejectReason::InvalidNumber)?;
+    let e164 = number.format().mode(Mode::E164).to_string();
+    Ok(e164.trim_start_matches('+').to_owned())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn should_standardize_ph() {
+    let validator = RegexValidator::default();
+    assert_eq!(validator.standardize("1116613061".into()), "201116613061");
+    assert_eq!(validator.standardize("511661306".into()), "966511661306");
+  }
+
+  #[test]
+  fn should_validate_with_custom_regex() {
+    let validator = RegexValidator {
+      mob_regex: Some(Regex::new("^1[0-9]{10}$").unwrap()),
+      ..RegexValidator::default()
+    };
+    assert_eq!(validator.normalize("14155552671").unwrap(), "14155552671");
+    assert_eq!(
+      validator.normalize("201116613061"),
+      Err(RejectReason::NoRegexMatch)
+    );
+  }
+
+  #[test]
+  fn should_reject_with_reasons() {
+    let validator = RegexValidator::default();
+    let reason = |ph| validator.normalize(ph).unwrap_err();
+    assert_eq!(reason("2011166130"), RejectReason::TooShort);
+    assert_eq!(reason("2011166130611"), RejectReason::TooLong);
+    assert_eq!(reason("201916613061"), RejectReason::BadPrefix);
+    assert_eq!(reason("4415555267"), RejectReason::UnknownCountry);
+  }
+
+  #[test]
+  fn should_validate_with_libphonenumber() {
+    let validator = LibPhoneNumberValidator::default();
+    assert_eq!(validator.normalize("201116613061").unwrap(), "201116613061");
+    assert_eq!(
+      validator.normalize("00966540029129").unwrap(),
+      "966540029129"
+    );
+    assert_eq!(
+      validator.normalize("20111661306"),
+      Err(RejectReason::InvalidNumber)
+    );
+    assert!(validator.normalize("01116613061").is_err());
+  }
+
+  #[test]
+  fn should_use_default_country_with_libphonenumber() {
+    let eg = countries::by_iso(countries::all(), "EG").ok();
+    let validator = LibPhoneNumberValidator::new(eg);
+    assert_eq!(validator.normalize("01116613061").unwrap(), "201116613061");
+    assert_eq!(validator.normalize("201116613061").unwrap(), "201116613061");
+  }
+}